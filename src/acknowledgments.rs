@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::alerts::{Alert, Finding, format_timestamp};
+use crate::audit::current_user;
+use crate::cli::{AckArgs, SnoozeArgs};
+use crate::state::{self, ACKNOWLEDGMENTS};
+
+// A finding someone has taken responsibility for. Its notifications are suppressed, but it
+// is still listed in reports, marked with who acknowledged it and why.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Acknowledgment {
+    pub at: DateTime<Utc>,
+    pub by: Option<String>,
+    pub note: Option<String>,
+    // Snoozes end at this time; acknowledgments last until the credential is rotated, which
+    // gives it a new keyId.
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl Acknowledgment {
+    fn active(&self, now: DateTime<Utc>) -> bool {
+        self.until.is_none_or(|until| until > now)
+    }
+
+    // e.g. "snoozed until 2025-03-01 09:00 AEST by alice: waiting on the vendor"
+    pub fn label(&self) -> String {
+        let mut label = match self.until {
+            Some(until) => format!("snoozed until {}", format_timestamp(until)),
+            None => format!("acknowledged on {}", format_timestamp(self.at)),
+        };
+        if let Some(by) = &self.by {
+            label.push_str(&format!(" by {}", by));
+        }
+        if let Some(note) = &self.note {
+            label.push_str(&format!(": {}", note));
+        }
+        label
+    }
+}
+
+// Acknowledgments are keyed on the application (its appId, or name for objects without
+// one, like Key Vaults) and the credential's keyId, across tenants.
+fn key(app: &str, key_id: &str) -> String {
+    format!("{}:{}", app, key_id)
+}
+
+fn finding_key(alert: &Alert, finding: &Finding) -> String {
+    key(
+        alert.app_id.as_deref().unwrap_or(&alert.name),
+        finding.key_id.as_deref().unwrap_or(&finding.credential),
+    )
+}
+
+fn store() -> anyhow::Result<&'static dyn state::StateStore> {
+    state::store().ok_or_else(|| {
+        anyhow::anyhow!("Acknowledgments need a state store; set STATE_BACKEND or STATE_PATH")
+    })
+}
+
+// Who is acknowledging: --by, or the logged in user.
+fn user(by: &Option<String>) -> Option<String> {
+    by.clone().or_else(current_user)
+}
+
+// Record an acknowledgment, or with `until` a snooze, of a finding.
+pub async fn record(
+    app_id: &str,
+    key_id: &str,
+    acknowledgment: &Acknowledgment,
+) -> anyhow::Result<()> {
+    state::save(
+        store()?,
+        ACKNOWLEDGMENTS,
+        &key(app_id, key_id),
+        acknowledgment,
+    )
+    .await
+}
+
+// The acknowledgment or snooze of a finding in effect now, if any.
+pub async fn lookup(app_id: &str, key_id: &str) -> anyhow::Result<Option<Acknowledgment>> {
+    let acknowledgment: Option<Acknowledgment> =
+        state::load(store()?, ACKNOWLEDGMENTS, &key(app_id, key_id)).await?;
+    Ok(acknowledgment.filter(|a| a.active(Utc::now())))
+}
+
+// `ack`: suppress a finding's notifications until its credential is rotated, or with
+// --remove, lift an acknowledgment or snooze.
+pub async fn acknowledge(args: &AckArgs) -> anyhow::Result<()> {
+    let key = key(&args.app_id, &args.key_id);
+    if args.remove {
+        store()?.delete(ACKNOWLEDGMENTS, &key).await?;
+        println!("Removed the acknowledgment of {}", key);
+        return Ok(());
+    }
+
+    let acknowledgment = Acknowledgment {
+        at: Utc::now(),
+        by: user(&args.by),
+        note: args.note.clone(),
+        until: None,
+    };
+    record(&args.app_id, &args.key_id, &acknowledgment).await?;
+    println!("{}: {}", key, acknowledgment.label());
+    Ok(())
+}
+
+// `snooze`: suppress a finding's notifications until a date.
+pub async fn snooze(args: &SnoozeArgs) -> anyhow::Result<()> {
+    let key = key(&args.app_id, &args.key_id);
+    let until = args
+        .until
+        .and_hms_opt(0, 0, 0)
+        .expect("midnight is a valid time")
+        .and_utc();
+    if until <= Utc::now() {
+        anyhow::bail!("--until must be in the future");
+    }
+
+    let acknowledgment = Acknowledgment {
+        at: Utc::now(),
+        by: user(&args.by),
+        note: args.note.clone(),
+        until: Some(until),
+    };
+    record(&args.app_id, &args.key_id, &acknowledgment).await?;
+    println!("{}: {}", key, acknowledgment.label());
+    Ok(())
+}
+
+// Mark the findings of `alerts` that are acknowledged or snoozed. Snoozes that have run out
+// are removed, so the findings are notified again.
+pub async fn annotate(alerts: &mut [Alert]) {
+    let Some(store) = state::store() else {
+        return;
+    };
+    let acknowledgments: HashMap<String, Acknowledgment> =
+        match state::load_all(store, ACKNOWLEDGMENTS).await {
+            Ok(acknowledgments) => acknowledgments.into_iter().collect(),
+            Err(e) => {
+                warn!("Failed to load acknowledgments: {}", e);
+                return;
+            }
+        };
+
+    let now = Utc::now();
+    for (key, acknowledgment) in &acknowledgments {
+        if !acknowledgment.active(now) {
+            info!("Snooze of {} has ended", key);
+            if let Err(e) = store.delete(ACKNOWLEDGMENTS, key).await {
+                warn!("Failed to remove the ended snooze of {}: {}", key, e);
+            }
+        }
+    }
+
+    for alert in alerts.iter_mut() {
+        let labels: Vec<Option<String>> = alert
+            .findings
+            .iter()
+            .map(|f| {
+                acknowledgments
+                    .get(&finding_key(alert, f))
+                    .filter(|a| a.active(now))
+                    .map(|a| a.label())
+            })
+            .collect();
+        for (finding, label) in alert.findings.iter_mut().zip(labels) {
+            finding.acknowledgment = label;
+        }
+    }
+}
+
+// Drop acknowledged findings, and the alerts left without any, before notifying.
+pub fn without_acknowledged(alerts: Vec<Alert>) -> Vec<Alert> {
+    let mut suppressed = 0;
+    let alerts: Vec<Alert> = alerts
+        .into_iter()
+        .filter_map(|mut alert| {
+            let before = alert.findings.len();
+            alert.findings.retain(|f| f.acknowledgment.is_none());
+            suppressed += before - alert.findings.len();
+            (!alert.findings.is_empty()).then_some(alert)
+        })
+        .collect();
+    if suppressed > 0 {
+        info!(
+            "Suppressed {} acknowledged or snoozed credentials",
+            suppressed
+        );
+    }
+    alerts
+}
@@ -0,0 +1,359 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{date_format, display_timezone, setting};
+
+// Whether a credential has already expired or is only approaching its expiry.
+// Expired credentials need cleanup (or an outage is already happening),
+// expiring ones need renewal, so they are reported separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Category {
+    Expired,
+    ExpiringSoon,
+}
+
+impl Category {
+    pub fn for_expiry(end_date_time: DateTime<Utc>, now: DateTime<Utc>) -> Category {
+        if end_date_time <= now {
+            Category::Expired
+        } else {
+            Category::ExpiringSoon
+        }
+    }
+
+    pub fn heading(&self) -> &'static str {
+        match self {
+            Category::Expired => "Expired Credentials",
+            Category::ExpiringSoon => "Expiring Credentials",
+        }
+    }
+}
+
+// How urgently a finding needs attention: critical when expired or within 7 days,
+// high within 30, medium within 90, low beyond that.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Severity {
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl Severity {
+    // Most urgent first.
+    pub const ALL: [Severity; 4] = [
+        Severity::Critical,
+        Severity::High,
+        Severity::Medium,
+        Severity::Low,
+    ];
+
+    pub fn for_expiry(end_date_time: DateTime<Utc>, now: DateTime<Utc>) -> Severity {
+        match end_date_time - now {
+            d if d < chrono::Duration::days(7) => Severity::Critical,
+            d if d < chrono::Duration::days(30) => Severity::High,
+            d if d < chrono::Duration::days(90) => Severity::Medium,
+            _ => Severity::Low,
+        }
+    }
+
+    pub fn parse(value: &str) -> anyhow::Result<Severity> {
+        Severity::ALL
+            .into_iter()
+            .find(|s| s.label().eq_ignore_ascii_case(value.trim()))
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Unknown severity '{}' (expected critical, high, medium or low)",
+                    value
+                )
+            })
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Severity::Critical => "Critical",
+            Severity::High => "High",
+            Severity::Medium => "Medium",
+            Severity::Low => "Low",
+        }
+    }
+}
+
+// Reminder tiers in days before expiry, e.g. 90/60/30/7/1.
+// A credential is reported once it falls within the largest tier, and tagged
+// with the smallest tier it falls within so reminders escalate as expiry approaches.
+#[derive(Debug, Clone)]
+pub struct Thresholds {
+    // Sorted ascending, no duplicates.
+    days: Vec<i64>,
+}
+
+impl Thresholds {
+    pub const DEFAULT: &'static [i64] = &[90, 60, 30, 7, 1];
+
+    pub fn new(mut days: Vec<i64>) -> anyhow::Result<Thresholds> {
+        days.sort_unstable();
+        days.dedup();
+        if days.is_empty() || days[0] <= 0 {
+            anyhow::bail!("Alert thresholds must be a non-empty list of positive day counts");
+        }
+        Ok(Thresholds { days })
+    }
+
+    // Read ALERT_THRESHOLD_DAYS as a comma separated list, e.g. "90,60,30,7,1".
+    pub fn from_env() -> anyhow::Result<Thresholds> {
+        match setting("ALERT_THRESHOLD_DAYS") {
+            Some(value) => {
+                let days = value
+                    .split(',')
+                    .map(|s| s.trim().parse::<i64>())
+                    .collect::<Result<Vec<i64>, _>>()
+                    .map_err(|e| {
+                        anyhow::anyhow!("Invalid ALERT_THRESHOLD_DAYS '{}': {}", value, e)
+                    })?;
+                Thresholds::new(days)
+            }
+            None => Thresholds::new(Thresholds::DEFAULT.to_vec()),
+        }
+    }
+
+    // Limit the report window to `days`: tiers beyond it are dropped and `days` becomes the
+    // outermost tier, so --threshold-days 7 reports only what expires within a week.
+    pub fn with_max_days(self, days: i64) -> anyhow::Result<Thresholds> {
+        let mut tiers: Vec<i64> = self.days.into_iter().filter(|d| *d < days).collect();
+        tiers.push(days);
+        Thresholds::new(tiers)
+    }
+
+    // The outermost tier; anything expiring later than this is not reported.
+    pub fn max_days(&self) -> i64 {
+        *self.days.last().unwrap()
+    }
+
+    pub fn cutoff(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        now + chrono::Duration::days(self.max_days())
+    }
+
+    // The smallest tier the expiry falls within, or None if it is outside all tiers
+    // or has already expired.
+    pub fn tier_for(&self, end_date_time: DateTime<Utc>, now: DateTime<Utc>) -> Option<i64> {
+        if end_date_time <= now {
+            return None;
+        }
+        self.days
+            .iter()
+            .copied()
+            .find(|days| end_date_time <= now + chrono::Duration::days(*days))
+    }
+}
+
+// Human readable label for a tier, e.g. "expires in 7 days — urgent".
+pub fn tier_label(days: i64) -> String {
+    let urgency = match days {
+        ..=1 => "critical",
+        2..=7 => "urgent",
+        8..=30 => "action needed",
+        _ => "reminder",
+    };
+    let unit = if days == 1 { "day" } else { "days" };
+    format!("expires in {} {} — {}", days, unit, urgency)
+}
+
+// A timestamp in the display timezone and DATE_FORMAT, e.g. "2025-03-01 09:00 AEST".
+pub fn format_timestamp(timestamp: DateTime<Utc>) -> String {
+    timestamp
+        .with_timezone(&display_timezone())
+        .format(&date_format())
+        .to_string()
+}
+
+// An expiry with how far away it is, e.g. "2025-03-01 09:00 AEST (in 12 days)".
+pub fn format_expiry(end_date_time: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let days = (end_date_time - now).num_days();
+    let relative = match days {
+        _ if end_date_time <= now => match -days {
+            0 => "expired today".to_string(),
+            1 => "expired 1 day ago".to_string(),
+            d => format!("expired {} days ago", d),
+        },
+        0 => "today".to_string(),
+        1 => "in 1 day".to_string(),
+        d => format!("in {} days", d),
+    };
+    format!("{} ({})", format_timestamp(end_date_time), relative)
+}
+
+// A single credential that needs attention.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Finding {
+    pub category: Category,
+    pub severity: Severity,
+    // Reminder tier in days; None for expired credentials.
+    pub tier: Option<i64>,
+    pub end_date_time: DateTime<Utc>,
+    // Kind of credential, e.g. "Client secret" or "Certificate".
+    pub credential: String,
+    pub key_id: Option<String>,
+    pub hint: Option<String>,
+    pub description: String,
+    // Set when someone acknowledged or snoozed it (see acknowledgments.rs).
+    pub acknowledgment: Option<String>,
+}
+
+impl Finding {
+    pub fn new(
+        end_date_time: DateTime<Utc>,
+        now: DateTime<Utc>,
+        thresholds: &Thresholds,
+        credential: &str,
+        description: String,
+    ) -> Finding {
+        Finding {
+            category: Category::for_expiry(end_date_time, now),
+            severity: Severity::for_expiry(end_date_time, now),
+            tier: thresholds.tier_for(end_date_time, now),
+            end_date_time,
+            credential: credential.to_string(),
+            key_id: None,
+            hint: None,
+            description,
+            acknowledgment: None,
+        }
+    }
+
+    pub fn with_key(mut self, key_id: Option<String>, hint: Option<String>) -> Finding {
+        self.key_id = key_id;
+        self.hint = hint;
+        self
+    }
+
+    // Whole days until expiry; negative once expired.
+    pub fn days_remaining(&self, now: DateTime<Utc>) -> i64 {
+        (self.end_date_time - now).num_days()
+    }
+
+    // Description prefixed with the severity and tier, used in notifications.
+    pub fn summary(&self) -> String {
+        let summary = match self.tier {
+            Some(days) => format!(
+                "[{}] [{}] {}",
+                self.severity.label(),
+                tier_label(days),
+                self.description
+            ),
+            None => format!("[{}] [expired] {}", self.severity.label(), self.description),
+        };
+        match &self.acknowledgment {
+            Some(acknowledgment) => format!("{} ({})", summary, acknowledgment),
+            None => summary,
+        }
+    }
+}
+
+// Everything that needs attention on one application, and who to tell about it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Alert {
+    // Name of the tenant the finding came from; filled in once the tenant scan completes.
+    pub tenant: String,
+    pub name: String,
+    // None for findings that don't belong to an application, e.g. Key Vault items.
+    pub app_id: Option<String>,
+    pub owners: Vec<String>,
+    // Managers and leadership copied on notifications once the credentials have gone
+    // unactioned for a while; filled in by NotificationState::escalate.
+    pub escalate_to: Vec<String>,
+    pub findings: Vec<Finding>,
+}
+
+impl Alert {
+    pub fn has_category(&self, category: Category) -> bool {
+        self.findings.iter().any(|f| f.category == category)
+    }
+
+    pub fn findings_in(&self, category: Category) -> impl Iterator<Item = &Finding> {
+        self.findings.iter().filter(move |f| f.category == category)
+    }
+
+    // The most urgent severity among the findings.
+    pub fn severity(&self) -> Severity {
+        self.findings
+            .iter()
+            .map(|f| f.severity)
+            .max()
+            .unwrap_or(Severity::Low)
+    }
+}
+
+// Process exit code for a run, so the tool can gate a CI pipeline:
+// 0 when nothing needs attention, 2 when credentials are expiring, 3 when any have expired.
+// Runtime errors exit with 1.
+pub fn exit_code(alerts: &[Alert]) -> u8 {
+    if alerts.iter().any(|a| a.has_category(Category::Expired)) {
+        3
+    } else if alerts
+        .iter()
+        .any(|a| a.has_category(Category::ExpiringSoon))
+    {
+        2
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone};
+
+    use super::*;
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 6, 1, 12, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn tiers_are_sorted_and_deduplicated() {
+        let thresholds = Thresholds::new(vec![7, 30, 1, 30]).unwrap();
+        assert_eq!(thresholds.days, vec![1, 7, 30]);
+        assert_eq!(thresholds.max_days(), 30);
+    }
+
+    #[test]
+    fn empty_or_non_positive_tiers_are_rejected() {
+        assert!(Thresholds::new(vec![]).is_err());
+        assert!(Thresholds::new(vec![30, 0]).is_err());
+        assert!(Thresholds::new(vec![-1, 7]).is_err());
+    }
+
+    #[test]
+    fn tier_is_the_smallest_one_the_expiry_falls_within() {
+        let thresholds = Thresholds::new(Thresholds::DEFAULT.to_vec()).unwrap();
+        let tier = |days: i64| thresholds.tier_for(now() + Duration::days(days), now());
+        assert_eq!(tier(1), Some(1));
+        assert_eq!(tier(2), Some(7));
+        assert_eq!(tier(7), Some(7));
+        assert_eq!(tier(45), Some(60));
+        assert_eq!(tier(90), Some(90));
+        assert_eq!(tier(91), None);
+    }
+
+    #[test]
+    fn expired_credentials_have_no_tier() {
+        let thresholds = Thresholds::new(Thresholds::DEFAULT.to_vec()).unwrap();
+        assert_eq!(thresholds.tier_for(now(), now()), None);
+        assert_eq!(thresholds.tier_for(now() - Duration::days(3), now()), None);
+    }
+
+    #[test]
+    fn max_days_drops_the_tiers_beyond_it() {
+        let thresholds = Thresholds::new(Thresholds::DEFAULT.to_vec())
+            .unwrap()
+            .with_max_days(14)
+            .unwrap();
+        assert_eq!(thresholds.days, vec![1, 7, 14]);
+        assert_eq!(thresholds.cutoff(now()), now() + Duration::days(14));
+        assert_eq!(
+            thresholds.tier_for(now() + Duration::days(10), now()),
+            Some(14)
+        );
+    }
+}
@@ -0,0 +1,413 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::acknowledgments;
+use crate::alerts::{Alert, format_timestamp};
+use crate::changes::current_findings;
+use crate::cli::{AuditArgs, HistoryArgs, OutputFormat};
+use crate::config::dry_run;
+use crate::dedup::finding_key;
+use crate::report::FindingRecord;
+use crate::state::{self, ACTIONS, AUDIT};
+
+// One delivered notification, kept in the state store so it can be shown that owners were
+// warned. Entries are only ever added, never updated or removed.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AuditEntry {
+    pub at: DateTime<Utc>,
+    pub channel: String,
+    // Addresses or numbers it went to; empty for channels posted to a single destination,
+    // like a Slack webhook.
+    pub recipients: Vec<String>,
+    // The findings it was about, as tenant:application:keyId.
+    pub findings: Vec<String>,
+    // The id the service returned for the message or request, where there is one.
+    pub message_id: Option<String>,
+}
+
+// A change the tool made in Graph on someone's behalf, such as adding or removing a
+// credential. Recorded whether it succeeded or not; like AuditEntry, only ever added.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ActionEntry {
+    pub at: DateTime<Utc>,
+    // The Graph operation, e.g. "addPassword", "removePassword" or "addKey".
+    pub action: String,
+    // The user that ran the tool, and the app registration the change was made as.
+    pub by: Option<String>,
+    pub client_id: String,
+    pub tenant: String,
+    pub app_id: String,
+    pub application: String,
+    // The credential added or removed.
+    pub key_id: Option<String>,
+    pub details: String,
+    // Graph's request-id response header, for support cases and the directory audit log.
+    pub request_id: Option<String>,
+    // None when it succeeded.
+    pub error: Option<String>,
+}
+
+// Distinguishes entries recorded within the same instant.
+static SEQUENCE: AtomicUsize = AtomicUsize::new(0);
+
+// Keys sort in the order entries were recorded.
+fn entry_key(at: DateTime<Utc>) -> String {
+    format!(
+        "{}#{:06}",
+        at.to_rfc3339_opts(SecondsFormat::Nanos, true),
+        SEQUENCE.fetch_add(1, Ordering::Relaxed) % 1_000_000
+    )
+}
+
+// The user running the tool.
+pub fn current_user() -> Option<String> {
+    std::env::var("USER")
+        .ok()
+        .or_else(|| std::env::var("USERNAME").ok())
+}
+
+// The findings of `alerts`, for AuditEntry::findings.
+pub fn alert_findings<'a>(alerts: impl IntoIterator<Item = &'a Alert>) -> Vec<String> {
+    alerts
+        .into_iter()
+        .flat_map(|a| a.findings.iter().map(move |f| finding_key(a, f)))
+        .collect()
+}
+
+// The finding of a FindingRecord, keyed the same way as alert_findings.
+pub fn record_finding(record: &FindingRecord) -> String {
+    format!(
+        "{}:{}:{}",
+        record.tenant,
+        record.appId.as_deref().unwrap_or(&record.application),
+        record.keyId.as_deref().unwrap_or(&record.credential)
+    )
+}
+
+// The request id a service put in its response headers, if any.
+pub fn response_id(headers: &reqwest::header::HeaderMap) -> Option<String> {
+    [
+        "request-id",
+        "x-request-id",
+        "x-ms-request-id",
+        "x-github-request-id",
+    ]
+    .iter()
+    .find_map(|name| headers.get(*name))
+    .and_then(|value| value.to_str().ok())
+    .map(|value| value.to_string())
+}
+
+// The message id in a JSON response body, under one of the names the services use for it.
+pub fn body_id(body: &str) -> Option<String> {
+    let body: serde_json::Value = serde_json::from_str(body).ok()?;
+    ["id", "sid", "requestId", "dedup_key", "ts"]
+        .iter()
+        .find_map(|name| match &body[*name] {
+            serde_json::Value::String(id) => Some(id.clone()),
+            serde_json::Value::Number(id) => Some(id.to_string()),
+            _ => None,
+        })
+}
+
+// Add a delivered notification to the audit log. Does nothing without a state store or on a
+// dry run; a failure to record is logged and never fails the notification itself.
+pub async fn record_notification(
+    channel: &str,
+    recipients: &[String],
+    findings: &[String],
+    message_id: Option<String>,
+) {
+    let Some(store) = state::store() else {
+        return;
+    };
+    if dry_run() {
+        return;
+    }
+
+    let entry = AuditEntry {
+        at: Utc::now(),
+        channel: channel.to_string(),
+        recipients: recipients.to_vec(),
+        findings: findings.to_vec(),
+        message_id,
+    };
+    if let Err(e) = state::save(store, AUDIT, &entry_key(entry.at), &entry).await {
+        warn!(
+            "Failed to record the {} notification in the audit log: {}",
+            channel, e
+        );
+    }
+}
+
+fn matches(entry: &AuditEntry, args: &AuditArgs) -> bool {
+    let contains =
+        |haystack: &str, needle: &str| haystack.to_lowercase().contains(&needle.to_lowercase());
+    args.since
+        .is_none_or(|since| entry.at.date_naive() >= since)
+        && args
+            .channel
+            .as_ref()
+            .is_none_or(|channel| contains(&entry.channel, channel))
+        && args
+            .recipient
+            .as_ref()
+            .is_none_or(|recipient| entry.recipients.iter().any(|r| contains(r, recipient)))
+        && args
+            .finding
+            .as_ref()
+            .is_none_or(|finding| entry.findings.iter().any(|f| contains(f, finding)))
+}
+
+// `audit`: print the notifications sent, oldest first, narrowed down by the filters in
+// `args`. --output json prints the entries instead.
+pub async fn print_audit(args: &AuditArgs, output: OutputFormat) -> anyhow::Result<()> {
+    let Some(store) = state::store() else {
+        anyhow::bail!("The audit log needs a state store; set STATE_BACKEND or STATE_PATH");
+    };
+    let entries: Vec<AuditEntry> = state::load_all::<AuditEntry>(store, AUDIT)
+        .await?
+        .into_iter()
+        .map(|(_, entry)| entry)
+        .filter(|entry| matches(entry, args))
+        .collect();
+
+    if output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
+
+    if entries.is_empty() {
+        println!("No notifications recorded.");
+        return Ok(());
+    }
+    for entry in &entries {
+        println!("{}  {}", format_timestamp(entry.at), entry.channel);
+        if !entry.recipients.is_empty() {
+            println!("  To: {}", entry.recipients.join(", "));
+        }
+        if let Some(id) = &entry.message_id {
+            println!("  Message ID: {}", id);
+        }
+        for finding in &entry.findings {
+            println!("  - {}", finding);
+        }
+    }
+    println!();
+    println!("{} notifications", entries.len());
+    Ok(())
+}
+
+// Add a write action to the action trail. Unlike notifications, a change to a credential
+// must not go unrecorded: without a state store it is logged instead, and a failure to
+// record is an error.
+pub async fn record_action(entry: ActionEntry) -> anyhow::Result<()> {
+    info!(
+        "{} on {} ({}) in tenant {} by {}: {}{}",
+        entry.action,
+        entry.application,
+        entry.app_id,
+        entry.tenant,
+        entry.by.as_deref().unwrap_or("unknown user"),
+        entry.details,
+        match &entry.error {
+            Some(e) => format!(" failed: {}", e),
+            None => String::new(),
+        }
+    );
+    let Some(store) = state::store() else {
+        return Ok(());
+    };
+    state::save(store, ACTIONS, &entry_key(entry.at), &entry)
+        .await
+        .map_err(|e| {
+            anyhow::anyhow!(
+                "Failed to record {} in the action trail: {}",
+                entry.action,
+                e
+            )
+        })
+}
+
+// A notification about one of an application's credentials, for `history --app`.
+#[derive(Serialize, Debug)]
+struct CredentialNotification {
+    at: DateTime<Utc>,
+    channel: String,
+    recipients: Vec<String>,
+    tenant: String,
+    key_id: String,
+    message_id: Option<String>,
+    // Where the credential stands now: "open", "resolved", the acknowledgment, or "unknown"
+    // if no run has been recorded.
+    state: String,
+}
+
+async fn credential_state(
+    tenant: &str,
+    app: &str,
+    key_id: &str,
+    open: &Option<HashSet<String>>,
+) -> anyhow::Result<String> {
+    if let Some(acknowledgment) = acknowledgments::lookup(app, key_id).await? {
+        return Ok(acknowledgment.label());
+    }
+    Ok(match open {
+        Some(open) if open.contains(&format!("{}:{}:{}", tenant, app, key_id)) => "open",
+        Some(_) => "resolved",
+        None => "unknown",
+    }
+    .to_string())
+}
+
+// The notifications sent about the credentials of the application with App ID `app`.
+async fn app_notifications(
+    store: &dyn state::StateStore,
+    app: &str,
+    args: &HistoryArgs,
+) -> anyhow::Result<Vec<CredentialNotification>> {
+    let open = current_findings().await?;
+    let mut states: HashMap<(String, String), String> = HashMap::new();
+    let mut notifications = Vec::new();
+    for (_, entry) in state::load_all::<AuditEntry>(store, AUDIT).await? {
+        if args
+            .since
+            .is_some_and(|since| entry.at.date_naive() < since)
+        {
+            continue;
+        }
+        for finding in &entry.findings {
+            // tenant:application:keyId, see dedup::finding_key.
+            let mut parts = finding.splitn(3, ':');
+            let (Some(tenant), Some(finding_app), Some(key_id)) =
+                (parts.next(), parts.next(), parts.next())
+            else {
+                continue;
+            };
+            if !finding_app.eq_ignore_ascii_case(app) {
+                continue;
+            }
+            let state_key = (tenant.to_string(), key_id.to_string());
+            if !states.contains_key(&state_key) {
+                let state = credential_state(tenant, finding_app, key_id, &open).await?;
+                states.insert(state_key.clone(), state);
+            }
+            notifications.push(CredentialNotification {
+                at: entry.at,
+                channel: entry.channel.clone(),
+                recipients: entry.recipients.clone(),
+                tenant: tenant.to_string(),
+                key_id: key_id.to_string(),
+                message_id: entry.message_id.clone(),
+                state: states[&state_key].clone(),
+            });
+        }
+    }
+    Ok(notifications)
+}
+
+// `history`: print the changes made to credentials, oldest first, narrowed down by the
+// filters in `args`. With --app, also every notification sent about the application's
+// credentials and where each credential stands now, from the audit log.
+// --output json prints the entries instead.
+pub async fn print_history(args: &HistoryArgs, output: OutputFormat) -> anyhow::Result<()> {
+    let Some(store) = state::store() else {
+        anyhow::bail!("The action trail needs a state store; set STATE_BACKEND or STATE_PATH");
+    };
+    let entries: Vec<ActionEntry> = state::load_all::<ActionEntry>(store, ACTIONS)
+        .await?
+        .into_iter()
+        .map(|(_, entry)| entry)
+        .filter(|entry| {
+            args.since
+                .is_none_or(|since| entry.at.date_naive() >= since)
+                && args.app.as_ref().is_none_or(|app| {
+                    entry.app_id.eq_ignore_ascii_case(app)
+                        || entry.application.eq_ignore_ascii_case(app)
+                })
+                && args
+                    .action
+                    .as_ref()
+                    .is_none_or(|action| entry.action.eq_ignore_ascii_case(action))
+        })
+        .collect();
+    // --action narrows down to Graph actions, which notifications aren't.
+    let notifications = match &args.app {
+        Some(app) if args.action.is_none() => app_notifications(store, app, args).await?,
+        _ => Vec::new(),
+    };
+
+    if output == OutputFormat::Json {
+        if args.app.is_some() {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&serde_json::json!({
+                    "actions": entries,
+                    "notifications": notifications,
+                }))?
+            );
+        } else {
+            println!("{}", serde_json::to_string_pretty(&entries)?);
+        }
+        return Ok(());
+    }
+
+    if entries.is_empty() && notifications.is_empty() {
+        println!("No actions recorded.");
+        return Ok(());
+    }
+    for entry in &entries {
+        println!(
+            "{}  {} {} ({}) in {}",
+            format_timestamp(entry.at),
+            entry.action,
+            entry.application,
+            entry.app_id,
+            entry.tenant
+        );
+        println!(
+            "  By: {} as {}",
+            entry.by.as_deref().unwrap_or("unknown user"),
+            entry.client_id
+        );
+        if let Some(key_id) = &entry.key_id {
+            println!("  Key ID: {}", key_id);
+        }
+        println!("  {}", entry.details);
+        if let Some(id) = &entry.request_id {
+            println!("  Request ID: {}", id);
+        }
+        if let Some(error) = &entry.error {
+            println!("  Failed: {}", error);
+        }
+    }
+    println!();
+    println!("{} actions", entries.len());
+
+    if args.app.is_some() && args.action.is_none() {
+        println!();
+        for notification in &notifications {
+            println!(
+                "{}  {} about {} in {} ({})",
+                format_timestamp(notification.at),
+                notification.channel,
+                notification.key_id,
+                notification.tenant,
+                notification.state
+            );
+            if !notification.recipients.is_empty() {
+                println!("  To: {}", notification.recipients.join(", "));
+            }
+            if let Some(id) = &notification.message_id {
+                println!("  Message ID: {}", id);
+            }
+        }
+        println!();
+        println!("{} notifications", notifications.len());
+    }
+    Ok(())
+}
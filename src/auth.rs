@@ -0,0 +1,30 @@
+use crate::config::Tenant;
+use crate::models::AccessToken;
+
+// Request an access token for `scope` with the tenant's client secret credentials,
+// the same credentials the Graph client uses.
+pub async fn client_credentials_token(
+    http: &reqwest::Client,
+    tenant: &Tenant,
+    scope: &str,
+) -> anyhow::Result<String> {
+    let token: AccessToken = http
+        .post(format!(
+            "{}/{}/oauth2/v2.0/token",
+            tenant.cloud.authority_host(),
+            tenant.tenant_id
+        ))
+        .form(&[
+            ("grant_type", "client_credentials"),
+            ("client_id", tenant.client_id.as_str()),
+            ("client_secret", tenant.client_secret.as_str()),
+            ("scope", scope),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    Ok(token.access_token)
+}
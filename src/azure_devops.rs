@@ -0,0 +1,229 @@
+use chrono::Utc;
+use log::{error, info};
+
+use crate::alerts::{Alert, format_expiry};
+use crate::config::{dry_run, http_client, setting};
+use crate::notifiers::alerts_at_or_above;
+use crate::report::html_escape;
+use crate::stats::{NOTIFICATIONS_FAILED, NOTIFICATIONS_SENT, count};
+use crate::templates::portal_url;
+
+const API_VERSION: &str = "7.1";
+const COMMENTS_API_VERSION: &str = "7.1-preview.4";
+
+// Azure DevOps work items are enabled by AZDO_ORG_URL and AZDO_PROJECT. One work item is
+// created per application and tagged with its appId; later runs comment on the open item
+// instead of creating another.
+//
+//     AZDO_ORG_URL          e.g. https://dev.azure.com/contoso
+//     AZDO_PROJECT          project work items are created in
+//     AZDO_PAT              personal access token with Work Items (read & write)
+//     AZDO_WORK_ITEM_TYPE   defaults to Task
+//     AZDO_AREA_PATH        optional area path, e.g. "Platform\Identity"
+//     AZDO_MIN_SEVERITY     leave out less urgent applications
+pub fn enabled() -> bool {
+    setting("AZDO_ORG_URL").is_some() && setting("AZDO_PROJECT").is_some()
+}
+
+fn app_tag(alert: &Alert) -> String {
+    format!(
+        "secret-manager-app-{}",
+        alert.app_id.as_deref().unwrap_or(&alert.name)
+    )
+}
+
+fn description(alert: &Alert) -> String {
+    let now = Utc::now();
+    let mut html = format!(
+        "<p>Tenant: {}<br>Application: {}<br>Owners: {}",
+        html_escape(&alert.tenant),
+        html_escape(&alert.name),
+        if alert.owners.is_empty() {
+            "None".to_string()
+        } else {
+            html_escape(&alert.owners.join(", "))
+        }
+    );
+    if let Some(app_id) = &alert.app_id {
+        html.push_str(&format!(
+            "<br>App ID: <a href=\"{}\">{}</a>",
+            html_escape(&portal_url(app_id)),
+            html_escape(app_id)
+        ));
+    }
+    html.push_str("</p><ul>");
+    for finding in &alert.findings {
+        html.push_str(&format!(
+            "<li>[{}] {} {}</li>",
+            finding.severity.label(),
+            html_escape(&finding.credential),
+            html_escape(&format_expiry(finding.end_date_time, now))
+        ));
+    }
+    html.push_str("</ul>");
+    html
+}
+
+struct AzureDevOps {
+    http: reqwest::Client,
+    project_url: String,
+}
+
+impl AzureDevOps {
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        self.http
+            .request(method, format!("{}/_apis/wit/{}", self.project_url, path))
+            .basic_auth("", setting("AZDO_PAT"))
+    }
+
+    // The open work item tagged for the application, if there is one.
+    async fn find_open_item(&self, alert: &Alert) -> anyhow::Result<Option<u64>> {
+        let query = format!(
+            "SELECT [System.Id] FROM WorkItems WHERE [System.TeamProject] = @project \
+             AND [System.Tags] CONTAINS '{}' \
+             AND [System.State] NOT IN ('Closed', 'Done', 'Removed', 'Resolved') \
+             ORDER BY [System.CreatedDate] DESC",
+            app_tag(alert).replace('\'', "''")
+        );
+        let response: serde_json::Value = self
+            .request(
+                reqwest::Method::POST,
+                &format!("wiql?api-version={}", API_VERSION),
+            )
+            .json(&serde_json::json!({ "query": query }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(response["workItems"]
+            .get(0)
+            .and_then(|item| item["id"].as_u64()))
+    }
+
+    async fn create_item(&self, alert: &Alert) -> anyhow::Result<u64> {
+        let work_item_type = setting("AZDO_WORK_ITEM_TYPE").unwrap_or_else(|| "Task".to_string());
+        let mut patch = vec![
+            serde_json::json!({
+                "op": "add",
+                "path": "/fields/System.Title",
+                "value": format!(
+                    "[{}] Credentials expiring for {} ({})",
+                    alert.severity().label(),
+                    alert.name,
+                    alert.tenant
+                )
+            }),
+            serde_json::json!({
+                "op": "add",
+                "path": "/fields/System.Description",
+                "value": description(alert)
+            }),
+            serde_json::json!({
+                "op": "add",
+                "path": "/fields/System.Tags",
+                "value": format!("secret-manager; {}", app_tag(alert))
+            }),
+        ];
+        if let Some(area_path) = setting("AZDO_AREA_PATH") {
+            patch.push(serde_json::json!({
+                "op": "add",
+                "path": "/fields/System.AreaPath",
+                "value": area_path
+            }));
+        }
+
+        let response: serde_json::Value = self
+            .request(
+                reqwest::Method::POST,
+                &format!("workitems/${}?api-version={}", work_item_type, API_VERSION),
+            )
+            .header(reqwest::header::CONTENT_TYPE, "application/json-patch+json")
+            .body(serde_json::to_vec(&patch)?)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(response["id"].as_u64().unwrap_or_default())
+    }
+
+    async fn comment(&self, id: u64, alert: &Alert) -> anyhow::Result<()> {
+        self.request(
+            reqwest::Method::POST,
+            &format!(
+                "workItems/{}/comments?api-version={}",
+                id, COMMENTS_API_VERSION
+            ),
+        )
+        .json(&serde_json::json!({
+            "text": format!("<p>Still needs attention:</p>{}", description(alert))
+        }))
+        .send()
+        .await?
+        .error_for_status()?;
+        Ok(())
+    }
+
+    async fn sync(&self, alert: &Alert) -> anyhow::Result<()> {
+        match self.find_open_item(alert).await? {
+            Some(id) => {
+                self.comment(id, alert).await?;
+                info!("Commented on work item {} for {}", id, alert.name);
+            }
+            None => {
+                let id = self.create_item(alert).await?;
+                info!("Created work item {} for {}", id, alert.name);
+            }
+        }
+        Ok(())
+    }
+}
+
+// Create or comment on a work item for every application at or above AZDO_MIN_SEVERITY.
+pub async fn sync_work_items(alerts: &[Alert]) -> anyhow::Result<()> {
+    let org_url =
+        setting("AZDO_ORG_URL").ok_or_else(|| anyhow::anyhow!("AZDO_ORG_URL is not set"))?;
+    let project =
+        setting("AZDO_PROJECT").ok_or_else(|| anyhow::anyhow!("AZDO_PROJECT is not set"))?;
+    let azure_devops = AzureDevOps {
+        http: http_client(),
+        project_url: format!(
+            "{}/{}",
+            org_url.trim_end_matches('/'),
+            url::form_urlencoded::byte_serialize(project.as_bytes()).collect::<String>()
+        ),
+    };
+    let alerts = alerts_at_or_above(alerts, "AZDO_MIN_SEVERITY");
+
+    if dry_run() {
+        for alert in &alerts {
+            println!(
+                "[dry-run] Would create or comment on an Azure DevOps work item in {} for {} ({})",
+                project, alert.name, alert.tenant
+            );
+        }
+        return Ok(());
+    }
+
+    let mut failures = 0;
+    for alert in &alerts {
+        match azure_devops.sync(alert).await {
+            Ok(()) => count(&NOTIFICATIONS_SENT, 1),
+            Err(e) => {
+                count(&NOTIFICATIONS_FAILED, 1);
+                error!("Failed to sync the work item for {}: {}", alert.name, e);
+                failures += 1;
+            }
+        }
+    }
+
+    if failures > 0 {
+        anyhow::bail!(
+            "{} of {} Azure DevOps work items failed",
+            failures,
+            alerts.len()
+        );
+    }
+    Ok(())
+}
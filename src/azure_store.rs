@@ -0,0 +1,487 @@
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use base64::Engine;
+use reqwest::StatusCode;
+use tokio::sync::Mutex;
+
+use crate::auth::client_credentials_token;
+use crate::config::{Tenant, http_client};
+use crate::json_store::StateFile;
+use crate::state::{LOCKS, Lock, StateStore};
+
+// The ETag of a response, for conditional writes.
+fn etag(response: &reqwest::Response) -> Option<String> {
+    response
+        .headers()
+        .get("ETag")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string())
+}
+
+// Whether a conditional write lost to another writer.
+fn lost_race(status: StatusCode) -> bool {
+    status == StatusCode::PRECONDITION_FAILED || status == StatusCode::CONFLICT
+}
+
+// Storage accepts the same resource in every cloud.
+const STORAGE_SCOPE: &str = "https://storage.azure.com/.default";
+// Needed for Entra authentication on both the Table and Blob services.
+const STORAGE_API_VERSION: &str = "2021-08-06";
+// Tokens last at least an hour; refresh well before that for long-running daemons.
+const TOKEN_LIFETIME: Duration = Duration::from_secs(30 * 60);
+
+// A storage token for the first tenant's app registration, refreshed as it ages. The app
+// needs the Storage Table Data Contributor or Storage Blob Data Contributor role.
+struct StorageToken {
+    http: reqwest::Client,
+    tenant: Tenant,
+    cached: Mutex<Option<(String, Instant)>>,
+}
+
+impl StorageToken {
+    fn new(http: reqwest::Client, tenant: &Tenant) -> StorageToken {
+        StorageToken {
+            http,
+            tenant: tenant.clone(),
+            cached: Mutex::new(None),
+        }
+    }
+
+    async fn get(&self) -> anyhow::Result<String> {
+        let mut cached = self.cached.lock().await;
+        if let Some((token, fetched)) = cached.as_ref()
+            && fetched.elapsed() < TOKEN_LIFETIME
+        {
+            return Ok(token.clone());
+        }
+        let token = client_credentials_token(&self.http, &self.tenant, STORAGE_SCOPE).await?;
+        *cached = Some((token.clone(), Instant::now()));
+        Ok(token)
+    }
+}
+
+// Row keys can't contain '/', '\', '#' or '?', which credential names may, so keys are
+// stored base64url-encoded.
+fn row_key(key: &str) -> String {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(key)
+}
+
+fn from_row_key(row_key: &str) -> anyhow::Result<String> {
+    Ok(String::from_utf8(
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(row_key)?,
+    )?)
+}
+
+#[derive(serde::Deserialize)]
+struct Entity {
+    #[serde(rename = "RowKey")]
+    row_key: String,
+    #[serde(rename = "Value")]
+    value: String,
+}
+
+#[derive(serde::Deserialize)]
+struct Entities {
+    value: Vec<Entity>,
+}
+
+// STATE_BACKEND=azure-table: one entity per document in the table at STATE_PATH
+// (https://{account}.table.core.windows.net/{table}), partitioned by namespace. The table
+// must already exist.
+pub struct AzureTableStore {
+    http: reqwest::Client,
+    token: StorageToken,
+    table_url: String,
+}
+
+impl AzureTableStore {
+    pub fn open(table_url: &str, tenant: &Tenant) -> AzureTableStore {
+        let http = http_client();
+        AzureTableStore {
+            token: StorageToken::new(http.clone(), tenant),
+            http,
+            table_url: table_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    // Namespaces are fixed identifiers and row keys are base64url, so neither needs
+    // escaping in the URL.
+    fn entity_url(&self, namespace: &str, key: &str) -> String {
+        format!(
+            "{}(PartitionKey='{}',RowKey='{}')",
+            self.table_url,
+            namespace,
+            row_key(key)
+        )
+    }
+
+    async fn request(
+        &self,
+        method: reqwest::Method,
+        url: &str,
+    ) -> anyhow::Result<reqwest::RequestBuilder> {
+        Ok(self
+            .http
+            .request(method, url)
+            .bearer_auth(self.token.get().await?)
+            .header("x-ms-version", STORAGE_API_VERSION)
+            .header("Accept", "application/json;odata=nometadata"))
+    }
+}
+
+#[async_trait]
+impl StateStore for AzureTableStore {
+    async fn get(&self, namespace: &str, key: &str) -> anyhow::Result<Option<serde_json::Value>> {
+        let response = self
+            .request(reqwest::Method::GET, &self.entity_url(namespace, key))
+            .await?
+            .send()
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let entity: Entity = response.error_for_status()?.json().await?;
+        Ok(Some(serde_json::from_str(&entity.value)?))
+    }
+
+    async fn put(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        // PUT without If-Match inserts or replaces.
+        self.request(reqwest::Method::PUT, &self.entity_url(namespace, key))
+            .await?
+            .json(&serde_json::json!({ "Value": value.to_string() }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn delete(&self, namespace: &str, key: &str) -> anyhow::Result<()> {
+        let response = self
+            .request(reqwest::Method::DELETE, &self.entity_url(namespace, key))
+            .await?
+            .header("If-Match", "*")
+            .send()
+            .await?;
+        if response.status() != StatusCode::NOT_FOUND {
+            response.error_for_status()?;
+        }
+        Ok(())
+    }
+
+    // Inserted if absent, or replaced once expired on the condition that the entity is
+    // unchanged since it was read; losing either race means another instance took it.
+    async fn try_lock(&self, name: &str, lock: &Lock) -> anyhow::Result<bool> {
+        let url = self.entity_url(LOCKS, name);
+        let response = self
+            .request(reqwest::Method::GET, &url)
+            .await?
+            .send()
+            .await?;
+        let value = serde_json::to_value(lock)?.to_string();
+
+        let request = if response.status() == StatusCode::NOT_FOUND {
+            self.request(reqwest::Method::POST, &self.table_url)
+                .await?
+                .header("Prefer", "return-no-content")
+                .json(&serde_json::json!({
+                    "PartitionKey": LOCKS,
+                    "RowKey": row_key(name),
+                    "Value": value,
+                }))
+        } else {
+            let response = response.error_for_status()?;
+            let etag = etag(&response).unwrap_or_else(|| "*".to_string());
+            let entity: Entity = response.json().await?;
+            if let Ok(current) = serde_json::from_str::<Lock>(&entity.value)
+                && !current.available_to(&lock.holder, chrono::Utc::now())
+            {
+                return Ok(false);
+            }
+            self.request(reqwest::Method::PUT, &url)
+                .await?
+                .header("If-Match", etag)
+                .json(&serde_json::json!({ "Value": value }))
+        };
+
+        let response = request.send().await?;
+        if lost_race(response.status()) {
+            return Ok(false);
+        }
+        response.error_for_status()?;
+        Ok(true)
+    }
+
+    async fn list(&self, namespace: &str) -> anyhow::Result<Vec<(String, serde_json::Value)>> {
+        let url = format!("{}()", self.table_url);
+        let filter = format!("PartitionKey eq '{}'", namespace);
+        let mut documents = Vec::new();
+        // Follow the continuation headers until the partition is exhausted.
+        let mut continuation: Option<(String, String)> = None;
+        loop {
+            let mut request = self
+                .request(reqwest::Method::GET, &url)
+                .await?
+                .query(&[("$filter", filter.as_str()), ("$select", "RowKey,Value")]);
+            if let Some((partition, row)) = &continuation {
+                request = request.query(&[("NextPartitionKey", partition), ("NextRowKey", row)]);
+            }
+            let response = request.send().await?.error_for_status()?;
+
+            let header = |name: &str| {
+                response
+                    .headers()
+                    .get(name)
+                    .and_then(|v| v.to_str().ok())
+                    .map(|v| v.to_string())
+            };
+            continuation = header("x-ms-continuation-NextPartitionKey")
+                .zip(header("x-ms-continuation-NextRowKey"));
+
+            let page: Entities = response.json().await?;
+            for entity in page.value {
+                documents.push((
+                    from_row_key(&entity.row_key)?,
+                    serde_json::from_str(&entity.value)?,
+                ));
+            }
+            if continuation.is_none() {
+                break;
+            }
+        }
+        // Encoded keys don't sort like the keys themselves.
+        documents.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(documents)
+    }
+}
+
+// STATE_BACKEND=azure-blob: the same document as the JSON file backend, kept in the block
+// blob at STATE_PATH (https://{account}.blob.core.windows.net/{container}/{blob}). Writes
+// are conditional on the blob's ETag, so two instances can't silently overwrite each other;
+// the one that loses reads the blob again and reapplies its change to what the other wrote.
+pub struct AzureBlobStore {
+    http: reqwest::Client,
+    token: StorageToken,
+    blob_url: String,
+    // The document and the ETag it was read or last written with (None if the blob doesn't
+    // exist yet).
+    contents: Mutex<(StateFile, Option<String>)>,
+}
+
+// How often a change is reapplied after losing the write to another instance.
+const WRITE_ATTEMPTS: usize = 5;
+
+impl AzureBlobStore {
+    pub async fn open(blob_url: &str, tenant: &Tenant) -> anyhow::Result<AzureBlobStore> {
+        let http = http_client();
+        let store = AzureBlobStore {
+            token: StorageToken::new(http.clone(), tenant),
+            http,
+            blob_url: blob_url.to_string(),
+            contents: Mutex::new((StateFile::default(), None)),
+        };
+        store.reload().await?;
+        Ok(store)
+    }
+
+    // The document in the blob and its ETag, or an empty document if there is no blob yet.
+    async fn fetch(&self) -> anyhow::Result<(StateFile, Option<String>)> {
+        let response = self
+            .http
+            .get(&self.blob_url)
+            .bearer_auth(self.token.get().await?)
+            .header("x-ms-version", STORAGE_API_VERSION)
+            .send()
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok((StateFile::default(), None));
+        }
+        let response = response.error_for_status()?;
+        let etag = etag(&response);
+        Ok((
+            StateFile::parse(response.text().await?, &self.blob_url)?,
+            etag,
+        ))
+    }
+
+    // Apply `change` to a copy of the document and write it, conditional on the blob being
+    // unchanged since it was read. If another instance wrote it in the meantime, the change is
+    // applied again to what that instance wrote. The cached document is only replaced once a
+    // write succeeds. `change` returns whether it changed anything.
+    async fn update(
+        &self,
+        change: impl Fn(&mut StateFile) -> bool + Send + Sync,
+    ) -> anyhow::Result<()> {
+        let mut contents = self.contents.lock().await;
+        for _ in 0..WRITE_ATTEMPTS {
+            let mut document = contents.0.clone();
+            if !change(&mut document) {
+                return Ok(());
+            }
+            let mut request = self
+                .http
+                .put(&self.blob_url)
+                .bearer_auth(self.token.get().await?)
+                .header("x-ms-version", STORAGE_API_VERSION)
+                .header("x-ms-blob-type", "BlockBlob")
+                .header("Content-Type", "application/json")
+                .body(document.to_json()?);
+            request = match &contents.1 {
+                Some(etag) => request.header("If-Match", etag),
+                None => request.header("If-None-Match", "*"),
+            };
+
+            let response = request.send().await?;
+            if lost_race(response.status()) {
+                *contents = self.fetch().await?;
+                continue;
+            }
+            let response = response.error_for_status()?;
+            *contents = (document, etag(&response));
+            return Ok(());
+        }
+        anyhow::bail!(
+            "State blob '{}' kept being changed by other instances; gave up writing it after {} attempts",
+            self.blob_url,
+            WRITE_ATTEMPTS
+        )
+    }
+
+    // Locks are kept in blobs of their own next to the state blob, "{blob}.{name}.lock",
+    // so taking one doesn't conflict with writes to the state.
+    fn lock_url(&self, name: &str) -> String {
+        format!("{}.{}.lock", self.blob_url, name)
+    }
+
+    // The lock blob `url` and its ETag, if it exists.
+    async fn read_lock(&self, url: &str) -> anyhow::Result<Option<(Option<Lock>, String)>> {
+        let response = self
+            .http
+            .get(url)
+            .bearer_auth(self.token.get().await?)
+            .header("x-ms-version", STORAGE_API_VERSION)
+            .send()
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = response.error_for_status()?;
+        let etag = etag(&response).unwrap_or_else(|| "*".to_string());
+        Ok(Some((
+            serde_json::from_str(&response.text().await?).ok(),
+            etag,
+        )))
+    }
+}
+
+#[async_trait]
+impl StateStore for AzureBlobStore {
+    async fn get(&self, namespace: &str, key: &str) -> anyhow::Result<Option<serde_json::Value>> {
+        Ok(self.contents.lock().await.0.get(namespace, key))
+    }
+
+    async fn put(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        self.update(|document| {
+            document.insert(namespace, key, value);
+            true
+        })
+        .await
+    }
+
+    async fn delete(&self, namespace: &str, key: &str) -> anyhow::Result<()> {
+        self.update(|document| document.remove(namespace, key))
+            .await
+    }
+
+    async fn list(&self, namespace: &str) -> anyhow::Result<Vec<(String, serde_json::Value)>> {
+        Ok(self.contents.lock().await.0.list(namespace))
+    }
+
+    async fn reload(&self) -> anyhow::Result<()> {
+        let contents = self.fetch().await?;
+        *self.contents.lock().await = contents;
+        Ok(())
+    }
+
+    // Written like the state blob: created only if absent, or replaced once expired only if
+    // unchanged since it was read.
+    async fn try_lock(&self, name: &str, lock: &Lock) -> anyhow::Result<bool> {
+        let url = self.lock_url(name);
+        let condition = match self.read_lock(&url).await? {
+            None => ("If-None-Match", "*".to_string()),
+            Some((Some(current), _)) if !current.available_to(&lock.holder, chrono::Utc::now()) => {
+                return Ok(false);
+            }
+            Some((_, etag)) => ("If-Match", etag),
+        };
+
+        let response = self
+            .http
+            .put(&url)
+            .bearer_auth(self.token.get().await?)
+            .header("x-ms-version", STORAGE_API_VERSION)
+            .header("x-ms-blob-type", "BlockBlob")
+            .header("Content-Type", "application/json")
+            .header(condition.0, condition.1)
+            .body(serde_json::to_string(lock)?)
+            .send()
+            .await?;
+        if lost_race(response.status()) {
+            return Ok(false);
+        }
+        response.error_for_status()?;
+        Ok(true)
+    }
+
+    async fn unlock(&self, name: &str, holder: &str) -> anyhow::Result<()> {
+        let url = self.lock_url(name);
+        let Some((Some(current), etag)) = self.read_lock(&url).await? else {
+            return Ok(());
+        };
+        if current.holder != holder {
+            return Ok(());
+        }
+        let response = self
+            .http
+            .delete(&url)
+            .bearer_auth(self.token.get().await?)
+            .header("x-ms-version", STORAGE_API_VERSION)
+            .header("If-Match", etag)
+            .send()
+            .await?;
+        if response.status() != StatusCode::NOT_FOUND && !lost_race(response.status()) {
+            response.error_for_status()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn row_keys_carry_characters_tables_do_not_allow() {
+        let key = "contoso:Payroll/API#1?:key\\id";
+        let encoded = row_key(key);
+        assert!(!encoded.contains(['/', '\\', '#', '?']));
+        assert_eq!(from_row_key(&encoded).unwrap(), key);
+    }
+
+    #[test]
+    fn failed_conditions_are_lost_races() {
+        assert!(lost_race(StatusCode::PRECONDITION_FAILED));
+        assert!(lost_race(StatusCode::CONFLICT));
+        assert!(!lost_race(StatusCode::FORBIDDEN));
+    }
+}
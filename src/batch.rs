@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use graph_rs_sdk::*;
+use log::info;
+use reqwest::StatusCode;
+
+use crate::config::application_select;
+use crate::filters::AppFilter;
+use crate::models::{App, Owner, Page};
+use crate::owners::{complete_application_owners, list_application_owners};
+use crate::retry::{MAX_RETRIES, is_retryable, send_with_retry, wait_to_retry};
+
+// Graph accepts at most 20 requests per $batch call.
+pub const MAX_BATCH_SIZE: usize = 20;
+
+// Retry-After of a batched response, whose headers come as a JSON object.
+fn retry_after(item: &serde_json::Value) -> Option<Duration> {
+    let value = &item["headers"]["Retry-After"];
+    value
+        .as_u64()
+        .or_else(|| value.as_str()?.trim().parse().ok())
+        .map(Duration::from_secs)
+}
+
+// Send GET requests (relative URLs, e.g. "/applications/{id}") through /$batch,
+// MAX_BATCH_SIZE at a time. Requests Graph throttles within a batch are sent again in a
+// batch of their own, after the longest Retry-After they were given. Returns the body of
+// each successful response keyed by its index in `urls`; failed requests are logged and
+// left out.
+pub async fn batch_get(
+    client: &GraphClient,
+    urls: &[String],
+) -> anyhow::Result<HashMap<usize, serde_json::Value>> {
+    let mut results: HashMap<usize, serde_json::Value> = HashMap::new();
+
+    for offset in (0..urls.len()).step_by(MAX_BATCH_SIZE) {
+        let mut pending: Vec<usize> = (offset..urls.len().min(offset + MAX_BATCH_SIZE)).collect();
+        let mut attempt = 0;
+
+        while !pending.is_empty() {
+            let requests = pending
+                .iter()
+                .map(|&index| {
+                    serde_json::json!({
+                        "id": index.to_string(),
+                        "method": "GET",
+                        "url": urls[index]
+                    })
+                })
+                .collect::<Vec<serde_json::Value>>();
+
+            let response = send_with_retry(|| {
+                client
+                    .batch(&serde_json::json!({ "requests": requests }))
+                    .send()
+            })
+            .await?
+            .json::<serde_json::Value>()
+            .await?;
+
+            // Responses can come back in any order; match them up by id.
+            let mut throttled: Vec<usize> = Vec::new();
+            let mut throttled_status = StatusCode::TOO_MANY_REQUESTS;
+            let mut wait: Option<Duration> = None;
+            for item in response["responses"].as_array().into_iter().flatten() {
+                let Some(index) = item["id"].as_str().and_then(|id| id.parse::<usize>().ok())
+                else {
+                    continue;
+                };
+                let status = item["status"].as_u64().unwrap_or(0);
+                if (200..300).contains(&status) {
+                    results.insert(index, item["body"].clone());
+                } else if let Ok(status) = StatusCode::from_u16(status as u16)
+                    && is_retryable(status)
+                    && attempt < MAX_RETRIES
+                {
+                    throttled.push(index);
+                    throttled_status = status;
+                    wait = wait.max(retry_after(item));
+                } else {
+                    info!(
+                        "Batch request '{}' failed with status {}: {}",
+                        urls[index], status, item["body"]["error"]
+                    );
+                }
+            }
+
+            if !throttled.is_empty() {
+                wait_to_retry(attempt, throttled_status, wait, "").await;
+                attempt += 1;
+            }
+            pending = throttled;
+        }
+    }
+
+    Ok(results)
+}
+
+// Fetch the given applications and their owners using $batch, two requests per application
+// packed into each batch. IDs are looked up as appIds first; any that are not found are
+// retried as object IDs.
+pub async fn get_applications_by_app_id(
+    client: &GraphClient,
+    app_ids: &[String],
+) -> anyhow::Result<Vec<App>> {
+    let (mut apps, missing) = batch_get_applications(client, app_ids, |id| {
+        format!("/applications(appId='{}')", id)
+    })
+    .await?;
+
+    if !missing.is_empty() {
+        let (by_object_id, missing) =
+            batch_get_applications(client, &missing, |id| format!("/applications/{}", id)).await?;
+        apps.extend(by_object_id);
+
+        for id in missing {
+            info!("Application '{}' not found. Skipping.", id);
+        }
+    }
+
+    info!("Fetched {} applications by ID", apps.len());
+
+    Ok(apps)
+}
+
+// Fetch applications through the resource path `path` builds for each ID.
+// Returns the applications and the IDs that were not found.
+async fn batch_get_applications(
+    client: &GraphClient,
+    ids: &[String],
+    path: impl Fn(&str) -> String,
+) -> anyhow::Result<(Vec<App>, Vec<String>)> {
+    let select = application_select().join(",");
+
+    let mut urls: Vec<String> = Vec::new();
+    for id in ids {
+        urls.push(format!("{}?$select={}", path(id), select));
+        urls.push(format!(
+            "{}/owners?$select=id,displayName,mail,userPrincipalName",
+            path(id)
+        ));
+    }
+
+    let mut responses = batch_get(client, &urls).await?;
+
+    let filter = AppFilter::from_env()?;
+    let mut apps: Vec<App> = Vec::new();
+    let mut missing: Vec<String> = Vec::new();
+
+    for (i, id) in ids.iter().enumerate() {
+        let Some(application) = responses.remove(&(i * 2)) else {
+            missing.push(id.clone());
+            continue;
+        };
+
+        let mut app: App = match serde_json::from_value(application) {
+            Ok(a) => a,
+            Err(e) => {
+                info!("Failed to parse application '{}': {}. Skipping.", id, e);
+                continue;
+            }
+        };
+
+        if !filter.allows(app.displayName.as_deref(), app.appId.as_deref()) {
+            continue;
+        }
+
+        let owners_page = responses
+            .remove(&(i * 2 + 1))
+            .and_then(|o| serde_json::from_value::<Page<Owner>>(o).ok());
+
+        // Batched responses are single pages; fetch the rest directly if there are more.
+        let owners: Vec<Owner> = match owners_page {
+            Some(page) if page.nextLink.is_some() => {
+                list_application_owners(client, &app.id).await?
+            }
+            Some(page) => page.value,
+            None => Vec::new(),
+        };
+
+        app.insert_owners(complete_application_owners(client, &app, owners).await?);
+        apps.push(app);
+    }
+
+    Ok((apps, missing))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_after_is_read_from_batched_headers() {
+        let item =
+            |headers: serde_json::Value| serde_json::json!({ "status": 429, "headers": headers });
+        assert_eq!(
+            retry_after(&item(serde_json::json!({ "Retry-After": "7" }))),
+            Some(Duration::from_secs(7))
+        );
+        assert_eq!(
+            retry_after(&item(serde_json::json!({ "Retry-After": 3 }))),
+            Some(Duration::from_secs(3))
+        );
+        assert_eq!(retry_after(&item(serde_json::json!({}))), None);
+    }
+}
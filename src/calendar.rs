@@ -0,0 +1,126 @@
+use chrono::{DateTime, Duration, Utc};
+
+use crate::alerts::{Alert, Category};
+use crate::config::{flag_setting, setting};
+use crate::email::Attachment;
+use crate::templates::portal_url;
+
+// Days before expiry the renewal reminder is placed on the calendar.
+const DEFAULT_LEAD_DAYS: i64 = 14;
+
+// With EMAIL_ICS=true, mail to owners carries an .ics file with an all-day event per
+// expiring credential, ICS_LEAD_DAYS (default 14) before it expires, so the renewal lands
+// on their calendar. Reminders that would fall in the past are placed on today.
+pub fn enabled() -> bool {
+    flag_setting("EMAIL_ICS")
+}
+
+fn lead_days() -> i64 {
+    setting("ICS_LEAD_DAYS")
+        .and_then(|d| d.trim().parse().ok())
+        .unwrap_or(DEFAULT_LEAD_DAYS)
+}
+
+// Escape a TEXT value (RFC 5545 3.3.11).
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+// Fold a content line at 75 octets, continuing with a leading space (RFC 5545 3.1).
+fn fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            width = 1;
+        }
+        folded.push(c);
+        width += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+fn event(
+    alert: &Alert,
+    end: DateTime<Utc>,
+    credential: &str,
+    key: &str,
+    now: DateTime<Utc>,
+) -> String {
+    let day = (end - Duration::days(lead_days())).max(now).date_naive();
+    let mut description = format!(
+        "{} of {} ({}) expires on {}. Rotate it before then.",
+        credential,
+        alert.name,
+        alert.tenant,
+        end.format("%Y-%m-%d")
+    );
+    if let Some(app_id) = &alert.app_id {
+        description.push_str(&format!("\n{}", portal_url(app_id)));
+    }
+
+    [
+        "BEGIN:VEVENT".to_string(),
+        format!("UID:{}@secret-manager", escape(key)),
+        format!("DTSTAMP:{}", now.format("%Y%m%dT%H%M%SZ")),
+        format!("DTSTART;VALUE=DATE:{}", day.format("%Y%m%d")),
+        format!(
+            "DTEND;VALUE=DATE:{}",
+            (day + Duration::days(1)).format("%Y%m%d")
+        ),
+        format!(
+            "SUMMARY:{}",
+            escape(&format!("Rotate {} of {}", credential, alert.name))
+        ),
+        format!("DESCRIPTION:{}", escape(&description)),
+        "TRANSP:TRANSPARENT".to_string(),
+        "END:VEVENT".to_string(),
+    ]
+    .iter()
+    .map(|line| fold(line))
+    .collect()
+}
+
+// An .ics attachment with a reminder for every credential of `alerts` that hasn't expired
+// yet, or None if they all have.
+pub fn renewal_reminders(alerts: &[&Alert]) -> Option<Attachment> {
+    let now = Utc::now();
+    let events: Vec<String> = alerts
+        .iter()
+        .flat_map(|alert| {
+            alert.findings_in(Category::ExpiringSoon).map(move |f| {
+                // Stable per credential, so a later invite updates the same event.
+                let key = format!(
+                    "{}-{}",
+                    alert.app_id.as_deref().unwrap_or(&alert.name),
+                    f.key_id.as_deref().unwrap_or(&f.credential)
+                );
+                event(alert, f.end_date_time, &f.credential, &key, now)
+            })
+        })
+        .collect();
+    if events.is_empty() {
+        return None;
+    }
+
+    let calendar = [
+        fold("BEGIN:VCALENDAR"),
+        fold("VERSION:2.0"),
+        fold("PRODID:-//secret-manager//credential expiry//EN"),
+        fold("METHOD:PUBLISH"),
+        events.concat(),
+        fold("END:VCALENDAR"),
+    ]
+    .concat();
+
+    Some(Attachment {
+        name: "credential-renewals.ics".to_string(),
+        content_type: "text/calendar".to_string(),
+        content: calendar.into_bytes(),
+    })
+}
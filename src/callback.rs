@@ -0,0 +1,373 @@
+use std::net::SocketAddr;
+
+use axum::Router;
+use axum::extract::{Form, Query};
+use axum::http::StatusCode;
+use axum::response::Html;
+use axum::routing::get;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::{Duration, Utc};
+use hmac::{Hmac, Mac};
+use log::{info, warn};
+use serde::Deserialize;
+use sha2::Sha256;
+
+use crate::acknowledgments::{self, Acknowledgment};
+use crate::alerts::{Alert, Finding};
+use crate::config::setting;
+use crate::report::html_escape;
+
+// Alert emails can carry acknowledge and snooze links for each finding, so owners can close
+// the loop without the CLI. The links point at a small HTTP endpoint the daemon serves:
+//
+//     CALLBACK_URL          the endpoint's public base URL, e.g. https://secrets.contoso.com
+//                           (enables the links)
+//     CALLBACK_SECRET       key the links are signed with, at least 16 characters
+//     CALLBACK_LISTEN       address to listen on (default 0.0.0.0:8080)
+//     CALLBACK_SNOOZE_DAYS  how long the snooze link snoozes for (default 7)
+//
+// Links are signed, so they can't be altered to acknowledge other findings, and expire after
+// LINK_LIFETIME_DAYS. Opening one only shows a confirmation page; the acknowledgment is
+// recorded when its button is pressed, so mail scanners following links don't acknowledge
+// anything.
+const LINK_LIFETIME_DAYS: i64 = 30;
+const DEFAULT_LISTEN: &str = "0.0.0.0:8080";
+
+pub fn enabled() -> bool {
+    setting("CALLBACK_URL").is_some()
+}
+
+// Check the callback settings, returning a description of each problem.
+pub fn validate() -> Vec<String> {
+    let mut problems = Vec::new();
+    let Some(url) = setting("CALLBACK_URL") else {
+        return problems;
+    };
+    if let Err(e) = url::Url::parse(&url) {
+        problems.push(format!("CALLBACK_URL is not a valid URL: {}", e));
+    }
+    if setting("CALLBACK_SECRET").is_none_or(|s| s.trim().len() < 16) {
+        problems.push("CALLBACK_SECRET must be set to at least 16 characters".to_string());
+    }
+    if let Some(listen) = setting("CALLBACK_LISTEN")
+        && listen.trim().parse::<SocketAddr>().is_err()
+    {
+        problems.push(format!(
+            "CALLBACK_LISTEN '{}' is not an address, e.g. 0.0.0.0:8080",
+            listen
+        ));
+    }
+    if let Some(days) = setting("CALLBACK_SNOOZE_DAYS")
+        && days.trim().parse::<u32>().is_err()
+    {
+        problems.push(format!(
+            "CALLBACK_SNOOZE_DAYS must be a positive number, got '{}'",
+            days
+        ));
+    }
+    if setting("STATE_BACKEND").is_none() && setting("STATE_PATH").is_none() {
+        problems.push(
+            "CALLBACK_URL needs a state store to record acknowledgments; set STATE_BACKEND or \
+             STATE_PATH"
+                .to_string(),
+        );
+    }
+    problems
+}
+
+fn snooze_days() -> u32 {
+    setting("CALLBACK_SNOOZE_DAYS")
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(7)
+}
+
+// What a link acknowledges: a finding, and for a snooze, for how many days.
+#[derive(Deserialize)]
+struct Link {
+    app: String,
+    key: String,
+    days: Option<u32>,
+    // Unix time the link stops working.
+    exp: i64,
+    sig: String,
+}
+
+// Signs what the link does, acknowledge or snooze for some days, and the finding it is for.
+// Each field is prefixed with its length, so no two different links sign the same bytes.
+fn mac(secret: &str, link: &Link) -> Option<Hmac<Sha256>> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.trim().as_bytes()).ok()?;
+    let kind = match link.days {
+        Some(days) => format!("snooze {}", days),
+        None => "acknowledge".to_string(),
+    };
+    for field in [
+        kind,
+        link.app.clone(),
+        link.key.clone(),
+        link.exp.to_string(),
+    ] {
+        mac.update(&(field.len() as u64).to_be_bytes());
+        mac.update(field.as_bytes());
+    }
+    Some(mac)
+}
+
+fn sign(secret: &str, link: &mut Link) -> Option<()> {
+    link.sig = URL_SAFE_NO_PAD.encode(mac(secret, link)?.finalize().into_bytes());
+    Some(())
+}
+
+fn is_signed(secret: &str, link: &Link) -> bool {
+    URL_SAFE_NO_PAD
+        .decode(&link.sig)
+        .ok()
+        .zip(mac(secret, link))
+        .is_some_and(|(sig, mac)| mac.verify_slice(&sig).is_ok())
+}
+
+fn link_url(mut link: Link) -> Option<String> {
+    sign(&setting("CALLBACK_SECRET")?, &mut link)?;
+    let mut url = url::Url::parse(&setting("CALLBACK_URL")?).ok()?;
+    url.path_segments_mut()
+        .ok()?
+        .pop_if_empty()
+        .push("acknowledge");
+    url.query_pairs_mut()
+        .append_pair("app", &link.app)
+        .append_pair("key", &link.key);
+    if let Some(days) = link.days {
+        url.query_pairs_mut().append_pair("days", &days.to_string());
+    }
+    url.query_pairs_mut()
+        .append_pair("exp", &link.exp.to_string())
+        .append_pair("sig", &link.sig);
+    Some(url.to_string())
+}
+
+// The acknowledge and snooze links of a finding, if CALLBACK_URL is set.
+pub fn links(alert: &Alert, finding: &Finding) -> Option<(String, String)> {
+    if !enabled() {
+        return None;
+    }
+    let app = alert.app_id.as_deref().unwrap_or(&alert.name);
+    let key = finding.key_id.as_deref().unwrap_or(&finding.credential);
+    let exp = (Utc::now() + Duration::days(LINK_LIFETIME_DAYS)).timestamp();
+    let link = |days: Option<u32>| Link {
+        app: app.to_string(),
+        key: key.to_string(),
+        days,
+        exp,
+        sig: String::new(),
+    };
+    Some((link_url(link(None))?, link_url(link(Some(snooze_days())))?))
+}
+
+fn verify(link: &Link) -> Result<(), (StatusCode, Html<String>)> {
+    if !setting("CALLBACK_SECRET").is_some_and(|secret| is_signed(&secret, link)) {
+        return Err(page(
+            StatusCode::FORBIDDEN,
+            "Invalid link",
+            "This link is not valid.",
+        ));
+    }
+    if link.exp < Utc::now().timestamp() {
+        return Err(page(
+            StatusCode::GONE,
+            "Link expired",
+            "This link has expired. Use the links in the latest notification instead.",
+        ));
+    }
+    Ok(())
+}
+
+fn page(status: StatusCode, title: &str, body: &str) -> (StatusCode, Html<String>) {
+    (
+        status,
+        Html(format!(
+            "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{0}</title></head>\
+             <body style=\"font-family: Segoe UI, Helvetica, Arial, sans-serif\">\
+             <h1>{0}</h1>{1}</body></html>",
+            html_escape(title),
+            body
+        )),
+    )
+}
+
+fn describe(link: &Link) -> String {
+    match link.days {
+        Some(days) => format!(
+            "Snooze notifications about credential <b>{}</b> of <b>{}</b> for {} days?",
+            html_escape(&link.key),
+            html_escape(&link.app),
+            days
+        ),
+        None => format!(
+            "Acknowledge credential <b>{}</b> of <b>{}</b>? Notifications about it stop until \
+             it is rotated.",
+            html_escape(&link.key),
+            html_escape(&link.app)
+        ),
+    }
+}
+
+// GET: confirm before recording anything.
+async fn confirm(Query(link): Query<Link>) -> (StatusCode, Html<String>) {
+    if let Err(response) = verify(&link) {
+        return response;
+    }
+    page(
+        StatusCode::OK,
+        "Confirm",
+        &format!(
+            "<p>{}</p><form method=\"post\">\
+             <p><label>Your name <input name=\"by\" required></label></p>\
+             <p><label>Note <input name=\"note\" size=\"60\"></label></p>\
+             <p><button type=\"submit\">Confirm</button></p></form>",
+            describe(&link)
+        ),
+    )
+}
+
+#[derive(Deserialize)]
+struct Confirmation {
+    by: String,
+    note: Option<String>,
+}
+
+// POST: record the acknowledgment or snooze.
+async fn record(
+    Query(link): Query<Link>,
+    Form(confirmation): Form<Confirmation>,
+) -> (StatusCode, Html<String>) {
+    if let Err(response) = verify(&link) {
+        return response;
+    }
+    let now = Utc::now();
+    let acknowledgment = Acknowledgment {
+        at: now,
+        by: Some(confirmation.by.trim().to_string()).filter(|by| !by.is_empty()),
+        note: confirmation
+            .note
+            .map(|note| note.trim().to_string())
+            .filter(|note| !note.is_empty()),
+        until: link.days.map(|days| now + Duration::days(days.into())),
+    };
+    match acknowledgments::record(&link.app, &link.key, &acknowledgment).await {
+        Ok(()) => {
+            info!(
+                "{}:{} {} through an email link",
+                link.app,
+                link.key,
+                acknowledgment.label()
+            );
+            page(
+                StatusCode::OK,
+                "Done",
+                &format!(
+                    "<p>{}</p>",
+                    html_escape(&format!("{}: {}", link.key, acknowledgment.label()))
+                ),
+            )
+        }
+        Err(e) => {
+            warn!(
+                "Failed to record an acknowledgment from an email link: {}",
+                e
+            );
+            page(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Something went wrong",
+                "The acknowledgment could not be recorded. Please try again later.",
+            )
+        }
+    }
+}
+
+// Serve the acknowledge endpoint until the daemon stops.
+pub async fn serve() -> anyhow::Result<()> {
+    let listen = setting("CALLBACK_LISTEN").unwrap_or_else(|| DEFAULT_LISTEN.to_string());
+    let app = Router::new().route("/acknowledge", get(confirm).post(record));
+    let listener = tokio::net::TcpListener::bind(listen.trim())
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to listen on {}: {}", listen, e))?;
+    info!("Serving acknowledge links on {}", listen);
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "0123456789abcdef";
+
+    fn signed(days: Option<u32>) -> Link {
+        let mut link = Link {
+            app: "app-id".to_string(),
+            key: "key-id".to_string(),
+            days,
+            exp: 1_900_000_000,
+            sig: String::new(),
+        };
+        sign(SECRET, &mut link).unwrap();
+        link
+    }
+
+    #[test]
+    fn signed_links_verify() {
+        assert!(is_signed(SECRET, &signed(None)));
+        assert!(is_signed(SECRET, &signed(Some(7))));
+    }
+
+    #[test]
+    fn altered_links_do_not_verify() {
+        let mut link = signed(None);
+        link.key = "other-key".to_string();
+        assert!(!is_signed(SECRET, &link));
+
+        let mut link = signed(Some(7));
+        link.days = Some(365);
+        assert!(!is_signed(SECRET, &link));
+
+        let mut link = signed(None);
+        link.exp += 1;
+        assert!(!is_signed(SECRET, &link));
+    }
+
+    #[test]
+    fn a_snooze_link_does_not_verify_as_an_acknowledgment() {
+        let mut link = signed(Some(0));
+        link.days = None;
+        assert!(!is_signed(SECRET, &link));
+
+        let mut link = signed(None);
+        link.days = Some(0);
+        assert!(!is_signed(SECRET, &link));
+    }
+
+    #[test]
+    fn fields_can_not_be_shifted_into_each_other() {
+        let mut link = signed(None);
+        link.app = "app\nkey".to_string();
+        link.key = "id".to_string();
+        sign(SECRET, &mut link).unwrap();
+        link.app = "app".to_string();
+        link.key = "key\nid".to_string();
+        assert!(!is_signed(SECRET, &link));
+    }
+
+    #[test]
+    fn links_signed_with_another_secret_do_not_verify() {
+        assert!(!is_signed("another secret value", &signed(None)));
+    }
+
+    #[test]
+    fn malformed_signatures_do_not_verify() {
+        let mut link = signed(None);
+        link.sig = "not base64!".to_string();
+        assert!(!is_signed(SECRET, &link));
+        link.sig = String::new();
+        assert!(!is_signed(SECRET, &link));
+    }
+}
@@ -0,0 +1,275 @@
+use std::collections::{BTreeMap, HashSet};
+use std::io::Write;
+
+use chrono::{DateTime, Utc};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::alerts::{Alert, Category, format_timestamp};
+use crate::cli::{ReportArgs, ReportFormat};
+use crate::dedup::finding_key;
+use crate::report::markdown_cell;
+use crate::state::{self, FINDINGS};
+
+// A finding as remembered for the next run's comparison.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct PastFinding {
+    tenant: String,
+    application: String,
+    app_id: Option<String>,
+    credential: String,
+    key_id: Option<String>,
+    expired: bool,
+    expiry: DateTime<Utc>,
+}
+
+// The findings of the last run over a set of tenants, keyed like dedup::finding_key.
+#[derive(Serialize, Deserialize, Debug)]
+struct Snapshot {
+    at: DateTime<Utc>,
+    // What the run was limited to (see ScanOptions::scope), None for a full scan.
+    #[serde(default)]
+    scope: Option<String>,
+    findings: BTreeMap<String, PastFinding>,
+}
+
+// Snapshots are kept per set of tenants and scan scope, so runs of different profiles, or
+// over some of the applications, aren't compared with each other.
+fn snapshot_key(tenants: &[String], scope: Option<&str>) -> String {
+    let mut tenants = tenants.to_vec();
+    tenants.sort();
+    match scope {
+        Some(scope) => format!("{} [{}]", tenants.join(","), scope),
+        None => tenants.join(","),
+    }
+}
+
+fn snapshot(alerts: &[Alert], scope: Option<&str>, at: DateTime<Utc>) -> Snapshot {
+    let findings = alerts
+        .iter()
+        .flat_map(|alert| {
+            alert.findings.iter().map(move |finding| {
+                (
+                    finding_key(alert, finding),
+                    PastFinding {
+                        tenant: alert.tenant.clone(),
+                        application: alert.name.clone(),
+                        app_id: alert.app_id.clone(),
+                        credential: finding.credential.clone(),
+                        key_id: finding.key_id.clone(),
+                        expired: finding.category == Category::Expired,
+                        expiry: finding.end_date_time,
+                    },
+                )
+            })
+        })
+        .collect();
+    Snapshot {
+        at,
+        scope: scope.map(str::to_string),
+        findings,
+    }
+}
+
+// Remember this run's findings for the next `report --diff`. Does nothing without a state
+// store.
+pub async fn record(
+    alerts: &[Alert],
+    tenants: &[String],
+    scope: Option<&str>,
+    at: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    let Some(store) = state::store() else {
+        return Ok(());
+    };
+    state::save(
+        store,
+        FINDINGS,
+        &snapshot_key(tenants, scope),
+        &snapshot(alerts, scope, at),
+    )
+    .await
+}
+
+// The findings of the latest full scan over every set of tenants, keyed like
+// dedup::finding_key, or None if no full scan has been recorded. Runs over some of the
+// applications are left out, as their snapshots go stale once a full scan covers them again.
+pub async fn current_findings() -> anyhow::Result<Option<HashSet<String>>> {
+    let Some(store) = state::store() else {
+        return Ok(None);
+    };
+    let snapshots: Vec<Snapshot> = state::load_all::<Snapshot>(store, FINDINGS)
+        .await?
+        .into_iter()
+        .map(|(_, snapshot)| snapshot)
+        .filter(|snapshot| snapshot.scope.is_none())
+        .collect();
+    if snapshots.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(
+        snapshots
+            .into_iter()
+            .flat_map(|snapshot| snapshot.findings.into_keys())
+            .collect(),
+    ))
+}
+
+// How the findings changed since the previous run.
+struct Changes {
+    since: Option<DateTime<Utc>>,
+    new: Vec<PastFinding>,
+    still_open: Vec<PastFinding>,
+    // Rotated, removed, or no longer within the thresholds.
+    resolved: Vec<PastFinding>,
+}
+
+fn compare(previous: Option<Snapshot>, current: Snapshot) -> Changes {
+    let since = previous.as_ref().map(|p| p.at);
+    let mut previous = previous.map(|p| p.findings).unwrap_or_default();
+    let mut changes = Changes {
+        since,
+        new: Vec::new(),
+        still_open: Vec::new(),
+        resolved: Vec::new(),
+    };
+    for (key, finding) in current.findings {
+        match previous.remove(&key) {
+            Some(_) => changes.still_open.push(finding),
+            None => changes.new.push(finding),
+        }
+    }
+    changes.resolved = previous.into_values().collect();
+    for findings in [
+        &mut changes.new,
+        &mut changes.still_open,
+        &mut changes.resolved,
+    ] {
+        findings.sort_by_key(|f| f.expiry);
+    }
+    changes
+}
+
+// e.g. "Payroll API (0f2c…) in contoso: Client secret, expired 2025-03-01 09:00 AEST"
+fn describe(finding: &PastFinding) -> String {
+    let mut line = finding.application.clone();
+    if let Some(app_id) = &finding.app_id {
+        line.push_str(&format!(" ({})", app_id));
+    }
+    if !finding.tenant.is_empty() {
+        line.push_str(&format!(" in {}", finding.tenant));
+    }
+    line.push_str(&format!(
+        ": {}{}, {} {}",
+        finding.credential,
+        finding
+            .key_id
+            .as_deref()
+            .map(|id| format!(" [{}]", id))
+            .unwrap_or_default(),
+        if finding.expired {
+            "expired"
+        } else {
+            "expires"
+        },
+        format_timestamp(finding.expiry)
+    ));
+    line
+}
+
+fn sections(changes: &Changes) -> [(&'static str, &Vec<PastFinding>); 3] {
+    [
+        ("New", &changes.new),
+        ("Still open", &changes.still_open),
+        ("Resolved", &changes.resolved),
+    ]
+}
+
+fn render_text(changes: &Changes) -> String {
+    let mut content = match changes.since {
+        Some(since) => format!("Changes since the run of {}\n", format_timestamp(since)),
+        None => "No previous run recorded; every finding is new.\n".to_string(),
+    };
+    for (title, findings) in sections(changes) {
+        content.push_str(&format!("\n{} ({})\n", title, findings.len()));
+        for finding in findings {
+            content.push_str(&format!("  - {}\n", describe(finding)));
+        }
+    }
+    content
+}
+
+fn render_markdown(changes: &Changes) -> String {
+    let mut content = String::from("# Credential Changes\n\n");
+    content.push_str(&match changes.since {
+        Some(since) => format!("Since the run of {}.\n", format_timestamp(since)),
+        None => "No previous run recorded; every finding is new.\n".to_string(),
+    });
+    for (title, findings) in sections(changes) {
+        content.push_str(&format!("\n## {} ({})\n\n", title, findings.len()));
+        if findings.is_empty() {
+            content.push_str("None.\n");
+        }
+        for finding in findings {
+            content.push_str(&format!("- {}\n", markdown_cell(&describe(finding))));
+        }
+    }
+    content
+}
+
+// `report --diff`: report the findings of this run against the previous run's, in sections
+// of new, still open and resolved findings. Reads the snapshot before run() replaces it; only
+// a previous run over the same tenants and scope is compared against.
+pub async fn write_diff_report(
+    alerts: &[Alert],
+    tenants: &[String],
+    scope: Option<&str>,
+    args: &ReportArgs,
+) -> anyhow::Result<()> {
+    let Some(store) = state::store() else {
+        anyhow::bail!("report --diff needs a state store; set STATE_BACKEND or STATE_PATH");
+    };
+    let previous: Option<Snapshot> =
+        state::load(store, FINDINGS, &snapshot_key(tenants, scope)).await?;
+    let changes = compare(previous, snapshot(alerts, scope, Utc::now()));
+
+    let report = match args.format {
+        ReportFormat::Text => render_text(&changes),
+        ReportFormat::Markdown => render_markdown(&changes),
+        _ => anyhow::bail!("--diff supports the text and markdown formats"),
+    };
+    match &args.out {
+        Some(path) => {
+            std::fs::write(path, report)
+                .map_err(|e| anyhow::anyhow!("Failed to write report to '{}': {}", path, e))?;
+            info!(
+                "Wrote {} new, {} still open and {} resolved findings to {}",
+                changes.new.len(),
+                changes.still_open.len(),
+                changes.resolved.len(),
+                path
+            );
+        }
+        None => std::io::stdout().write_all(report.as_bytes())?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_over_some_applications_are_kept_apart_from_full_scans() {
+        let tenants = ["fabrikam".to_string(), "contoso".to_string()];
+        assert_eq!(snapshot_key(&tenants, None), "contoso,fabrikam");
+        assert_eq!(
+            snapshot_key(&tenants, Some("apps=app-1")),
+            "contoso,fabrikam [apps=app-1]"
+        );
+        assert_ne!(
+            snapshot_key(&tenants, Some("apps=app-1")),
+            snapshot_key(&tenants, Some("apps=app-2"))
+        );
+    }
+}
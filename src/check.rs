@@ -0,0 +1,148 @@
+use base64::Engine;
+use graph_rs_sdk::*;
+
+use crate::auth::client_credentials_token;
+use crate::config::{Tenant, flag_setting, http_client, setting, validate};
+use crate::retry::send_with_retry;
+
+// Application permissions the scan needs, with alternatives that also cover it.
+const REQUIRED_ROLES: &[(&str, &[&str])] = &[
+    (
+        "Application.Read.All",
+        &[
+            "Application.Read.All",
+            "Application.ReadWrite.All",
+            "Directory.Read.All",
+        ],
+    ),
+    (
+        "GroupMember.Read.All",
+        &[
+            "GroupMember.Read.All",
+            "Group.Read.All",
+            "Directory.Read.All",
+        ],
+    ),
+];
+
+fn report(ok: bool, message: &str) -> bool {
+    println!("[{}] {}", if ok { " ok " } else { "FAIL" }, message);
+    ok
+}
+
+// Application permissions ("roles" claim) granted in an access token.
+fn token_roles(token: &str) -> anyhow::Result<Vec<String>> {
+    let payload = token
+        .split('.')
+        .nth(1)
+        .ok_or_else(|| anyhow::anyhow!("access token is not a JWT"))?;
+    let claims: serde_json::Value =
+        serde_json::from_slice(&base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(payload)?)?;
+    Ok(claims["roles"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|r| r.as_str().map(|r| r.to_string()))
+        .collect())
+}
+
+// Check one tenant's credential and permissions, and that the alerting mailbox resolves.
+async fn check_tenant(tenant: &Tenant, client: &GraphClient, notify: bool) -> bool {
+    let mut ok = true;
+    let http = http_client();
+    let scope = format!(
+        "{}/.default",
+        tenant.cloud.graph_endpoint().trim_end_matches("/v1.0")
+    );
+
+    let token = match client_credentials_token(&http, tenant, &scope).await {
+        Ok(token) => {
+            report(true, &format!("{}: acquired a Graph token", tenant.name));
+            token
+        }
+        Err(e) => {
+            return report(
+                false,
+                &format!("{}: could not acquire a Graph token: {}", tenant.name, e),
+            );
+        }
+    };
+
+    let roles = match token_roles(&token) {
+        Ok(roles) => roles,
+        Err(e) => {
+            return report(
+                false,
+                &format!("{}: could not read token permissions: {}", tenant.name, e),
+            );
+        }
+    };
+
+    let mut required: Vec<(&str, &[&str])> = REQUIRED_ROLES.to_vec();
+    if flag_setting("OWNER_MANAGER_FALLBACK") {
+        required.push((
+            "User.Read.All",
+            &["User.Read.All", "User.ReadWrite.All", "Directory.Read.All"],
+        ));
+    }
+    // Over SMTP the relay sends the mail, so Graph doesn't need to.
+    let graph_mail = notify && !crate::smtp::enabled();
+    if graph_mail {
+        required.push(("Mail.Send", &["Mail.Send"]));
+    }
+
+    for (name, accepted) in required {
+        ok &= report(
+            roles.iter().any(|r| accepted.contains(&r.as_str())),
+            &format!("{}: application permission {}", tenant.name, name),
+        );
+    }
+
+    if graph_mail && let Some(mailbox) = setting("ALERTING_EMAIL") {
+        let resolved = send_with_retry(|| {
+            client
+                .user(&mailbox)
+                .get_user()
+                .select(&["id", "mail"])
+                .send()
+        })
+        .await
+        .map(|r| r.status().is_success());
+        ok &= report(
+            resolved.unwrap_or(false),
+            &format!("{}: alerting mailbox {} resolves", tenant.name, mailbox),
+        );
+    }
+
+    ok
+}
+
+// `config check`: validate the configuration, then for each tenant acquire a token, verify
+// the granted application permissions and resolve the alerting mailbox, without scanning.
+// Notifications go out through the first tenant, so only that one needs Mail.Send.
+pub async fn check_config(tenants: &[Tenant], clients: &[GraphClient]) -> anyhow::Result<()> {
+    let mut ok = true;
+    for (i, (tenant, client)) in tenants.iter().zip(clients).enumerate() {
+        ok &= check_tenant(tenant, client, i == 0).await;
+    }
+
+    if !ok {
+        anyhow::bail!("Configuration check failed");
+    }
+    println!("Configuration check passed");
+    Ok(())
+}
+
+// Report configuration problems in the same format as the other checks.
+pub fn check_settings() -> anyhow::Result<()> {
+    match validate(true) {
+        Ok(()) => {
+            report(true, "settings are complete and well-formed");
+            Ok(())
+        }
+        Err(e) => {
+            report(false, &e.to_string());
+            anyhow::bail!("Configuration check failed")
+        }
+    }
+}
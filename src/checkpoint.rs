@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::models::Owner;
+use crate::state::{self, CHECKPOINTS};
+
+// Owners resolved since the last save are written out after this many applications.
+const SAVE_EVERY: usize = 200;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Progress {
+    started: DateTime<Utc>,
+    // Resolved owners by application object id.
+    owners: HashMap<String, Vec<Owner>>,
+}
+
+// Progress of a tenant's scan, kept in the state store while it runs. Resolving owners is
+// where a large tenant's scan spends its time, one or more Graph calls per application, so
+// each application's owners are checkpointed as they are resolved. With --resume, a run
+// picks up the checkpoint an interrupted run left and only resolves the applications it
+// hadn't got to. Checkpoints are removed once a run completes.
+pub struct Checkpoint {
+    tenant: String,
+    progress: Mutex<(Progress, usize)>,
+}
+
+impl Checkpoint {
+    pub async fn open(tenant: &str, resume: bool) -> Checkpoint {
+        let mut progress = Progress {
+            started: Utc::now(),
+            owners: HashMap::new(),
+        };
+        if let Some(store) = state::store() {
+            match state::load::<Progress>(store, CHECKPOINTS, tenant).await {
+                Ok(Some(saved)) if resume => {
+                    info!(
+                        "Resuming the scan of tenant '{}' started {}: {} applications already resolved",
+                        tenant,
+                        saved.started,
+                        saved.owners.len()
+                    );
+                    progress = saved;
+                }
+                Ok(Some(_)) => info!(
+                    "Ignoring the checkpoint of an interrupted scan of tenant '{}'; pass --resume to continue it",
+                    tenant
+                ),
+                Ok(None) if resume => {
+                    info!("No checkpoint for tenant '{}'; scanning it in full", tenant)
+                }
+                Ok(None) => {}
+                Err(e) => warn!(
+                    "Failed to load the checkpoint of tenant '{}': {}",
+                    tenant, e
+                ),
+            }
+        } else if resume {
+            warn!("--resume needs a state store (STATE_BACKEND or STATE_PATH); scanning in full");
+        }
+
+        Checkpoint {
+            tenant: tenant.to_string(),
+            progress: Mutex::new((progress, 0)),
+        }
+    }
+
+    // The owners of an application resolved before the run was interrupted.
+    pub async fn owners(&self, id: &str) -> Option<Vec<Owner>> {
+        self.progress.lock().await.0.owners.get(id).cloned()
+    }
+
+    // Record an application's owners, saving the checkpoint every SAVE_EVERY applications.
+    pub async fn resolved(&self, id: &str, owners: &[Owner]) {
+        let mut progress = self.progress.lock().await;
+        progress.0.owners.insert(id.to_string(), owners.to_vec());
+        progress.1 += 1;
+        if progress.1 >= SAVE_EVERY {
+            progress.1 = 0;
+            self.save(&progress.0).await;
+        }
+    }
+
+    async fn save(&self, progress: &Progress) {
+        let Some(store) = state::store() else {
+            return;
+        };
+        if let Err(e) = state::save(store, CHECKPOINTS, &self.tenant, progress).await {
+            warn!(
+                "Failed to save the checkpoint of tenant '{}': {}",
+                self.tenant, e
+            );
+        }
+    }
+}
+
+// Forget the checkpoints once every tenant has been scanned.
+pub async fn clear() {
+    let Some(store) = state::store() else {
+        return;
+    };
+    let checkpoints = match store.list(CHECKPOINTS).await {
+        Ok(checkpoints) => checkpoints,
+        Err(e) => {
+            warn!("Failed to list scan checkpoints: {}", e);
+            return;
+        }
+    };
+    for (tenant, _) in checkpoints {
+        if let Err(e) = store.delete(CHECKPOINTS, &tenant).await {
+            warn!(
+                "Failed to remove the checkpoint of tenant '{}': {}",
+                tenant, e
+            );
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use chrono::{DateTime, Duration, Utc};
+use graph_rs_sdk::GraphClient;
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::audit::{self, ActionEntry};
+use crate::cli::{CleanupArgs, OutputFormat};
+use crate::config::{Tenant, dry_run, setting};
+use crate::models::{App, PasswordCredential};
+use crate::retry::send_with_retry;
+
+// `cleanup --expired` removes client secrets that expired more than CLEANUP_AFTER_DAYS
+// (default 30) ago with removePassword, so dead secrets don't pile up on app registrations.
+// Like `rotate` it needs Application.ReadWrite.All. An application with no unexpired secret
+// keeps the one that expired last, so it is never left without any.
+const DEFAULT_AFTER_DAYS: u32 = 30;
+
+fn after_days(args: &CleanupArgs) -> u32 {
+    args.older_than_days
+        .or_else(|| setting("CLEANUP_AFTER_DAYS").and_then(|v| v.trim().parse().ok()))
+        .unwrap_or(DEFAULT_AFTER_DAYS)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Removed {
+    tenant: String,
+    app_id: String,
+    application: String,
+    key_id: String,
+    hint: Option<String>,
+    end_date_time: DateTime<Utc>,
+}
+
+// The secrets of `app` to remove: those that expired before `cutoff`, less the one that
+// expired last when none are still valid.
+fn expired_secrets(app: &App, cutoff: DateTime<Utc>) -> Vec<&PasswordCredential> {
+    let now = Utc::now();
+    let keep = if app.passwordCredentials.iter().any(|c| c.endDateTime > now) {
+        None
+    } else {
+        app.passwordCredentials.iter().max_by_key(|c| c.endDateTime)
+    };
+    app.passwordCredentials
+        .iter()
+        .filter(|c| c.endDateTime < cutoff && c.keyId.is_some())
+        .filter(|c| !keep.is_some_and(|k| std::ptr::eq(*c, k)))
+        .collect()
+}
+
+// Remove one secret, recording the attempt in the audit log either way.
+async fn remove_password(
+    client: &GraphClient,
+    tenant: &Tenant,
+    app: &App,
+    credential: &PasswordCredential,
+) -> anyhow::Result<()> {
+    let key_id = credential.keyId.clone().unwrap_or_default();
+    let body = serde_json::json!({ "keyId": key_id });
+
+    let mut request_id = None;
+    let result = async {
+        let response =
+            send_with_retry(|| client.application(&app.id).remove_password(&body).send()).await?;
+        request_id = audit::response_id(response.headers());
+        let status = response.status();
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            anyhow::bail!("removePassword failed with status {}: {}", status, text);
+        }
+        Ok::<_, anyhow::Error>(())
+    }
+    .await;
+
+    audit::record_action(ActionEntry {
+        at: Utc::now(),
+        action: "removePassword".to_string(),
+        by: audit::current_user(),
+        client_id: tenant.client_id.clone(),
+        tenant: tenant.name.clone(),
+        app_id: app.appId.clone().unwrap_or_else(|| app.id.clone()),
+        application: app.displayName.clone().unwrap_or_else(|| app.id.clone()),
+        key_id: Some(key_id),
+        details: format!(
+            "Removed client secret (hint {}) that expired {}",
+            credential.hint.as_deref().unwrap_or("-"),
+            credential.endDateTime.format("%Y-%m-%d")
+        ),
+        request_id,
+        error: result.as_ref().err().map(|e| e.to_string()),
+    })
+    .await?;
+
+    result
+}
+
+// Remove the expired secrets of `apps`, returning the ones removed (or that would be, in a
+// dry run) and the number that failed.
+pub async fn remove_expired(
+    args: &CleanupArgs,
+    tenant: &Tenant,
+    client: &GraphClient,
+    apps: &[App],
+) -> anyhow::Result<(Vec<Removed>, usize)> {
+    let cutoff = Utc::now() - Duration::days(after_days(args).into());
+    let mut removed = Vec::new();
+    let mut failed = 0;
+
+    for app in apps {
+        let application = app.displayName.clone().unwrap_or_else(|| app.id.clone());
+        for credential in expired_secrets(app, cutoff) {
+            let entry = Removed {
+                tenant: tenant.name.clone(),
+                app_id: app.appId.clone().unwrap_or_else(|| app.id.clone()),
+                application: application.clone(),
+                key_id: credential.keyId.clone().unwrap_or_default(),
+                hint: credential.hint.clone(),
+                end_date_time: credential.endDateTime,
+            };
+            if !dry_run()
+                && let Err(e) = remove_password(client, tenant, app, credential).await
+            {
+                warn!(
+                    "Failed to remove client secret {} from {}: {}",
+                    entry.key_id, application, e
+                );
+                failed += 1;
+                continue;
+            }
+            removed.push(entry);
+        }
+    }
+
+    Ok((removed, failed))
+}
+
+pub fn print_removed(removed: &[Removed], output: OutputFormat) -> anyhow::Result<()> {
+    if output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(removed)?);
+        return Ok(());
+    }
+    let verb = if dry_run() { "Would remove" } else { "Removed" };
+    for r in removed {
+        println!(
+            "{} client secret {} (hint {}, expired {}) from {} ({}) in tenant {}",
+            verb,
+            r.key_id,
+            r.hint.as_deref().unwrap_or("-"),
+            r.end_date_time.format("%Y-%m-%d"),
+            r.application,
+            r.app_id,
+            r.tenant
+        );
+    }
+    info!("{} {} expired client secrets", verb, removed.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An application with a secret per entry of `expiries`, given in days from now; the
+    // secret's keyId is its index.
+    fn app(expiries: &[i64]) -> App {
+        let now = Utc::now();
+        serde_json::from_value(serde_json::json!({
+            "id": "object-id",
+            "appId": "app-id",
+            "displayName": "app",
+            "passwordCredentials": expiries
+                .iter()
+                .enumerate()
+                .map(|(i, days)| serde_json::json!({
+                    "keyId": i.to_string(),
+                    "endDateTime": (now + Duration::days(*days)).to_rfc3339(),
+                }))
+                .collect::<Vec<_>>(),
+        }))
+        .unwrap()
+    }
+
+    fn removed(app: &App, after_days: i64) -> Vec<&str> {
+        expired_secrets(app, Utc::now() - Duration::days(after_days))
+            .iter()
+            .map(|c| c.keyId.as_deref().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn removes_secrets_expired_before_the_cutoff() {
+        let app = app(&[-100, -10, 200]);
+        assert_eq!(removed(&app, 30), vec!["0"]);
+        assert_eq!(removed(&app, 5), vec!["0", "1"]);
+    }
+
+    #[test]
+    fn never_removes_a_secret_that_has_not_expired() {
+        let app = app(&[1, 200]);
+        assert!(removed(&app, 0).is_empty());
+    }
+
+    #[test]
+    fn keeps_the_last_secret_when_none_is_still_valid() {
+        let app = app(&[-300, -100, -200]);
+        assert_eq!(removed(&app, 30), vec!["0", "2"]);
+    }
+
+    #[test]
+    fn keeps_an_only_secret_however_long_ago_it_expired() {
+        let app = app(&[-1000]);
+        assert!(removed(&app, 30).is_empty());
+    }
+
+    #[test]
+    fn skips_secrets_without_a_key_id() {
+        let mut app = app(&[-100, 200]);
+        app.passwordCredentials[0].keyId = None;
+        assert!(removed(&app, 30).is_empty());
+    }
+}
@@ -0,0 +1,515 @@
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use crate::config::{flag_setting, list_setting, setting};
+use crate::logging::{LogFormat, LogRotation};
+
+// Command line interface. Every option can also be set through the environment variable
+// named in its help text; flags take precedence.
+#[derive(Parser, Debug, Clone)]
+#[command(
+    name = "secret-manager",
+    version,
+    about = "Find expiring Entra ID application credentials and notify their owners"
+)]
+pub struct Cli {
+    // Defaults to `notify` when no subcommand is given.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Config file to read settings from [default: secret-manager.toml, if present].
+    #[arg(long, env = "SECRET_MANAGER_CONFIG", global = true)]
+    pub config: Option<String>,
+
+    /// Use the settings of this profile from the config file.
+    #[arg(long, env = "SECRET_MANAGER_PROFILE", global = true)]
+    pub profile: Option<String>,
+
+    /// Scan as usual but print the notifications that would be sent instead of sending them.
+    #[arg(long, env = "DRY_RUN", global = true)]
+    pub dry_run: bool,
+
+    /// Only report credentials expiring within this many days, overriding the outermost
+    /// ALERT_THRESHOLD_DAYS tier.
+    #[arg(long, env = "THRESHOLD_DAYS", global = true)]
+    pub threshold_days: Option<i64>,
+
+    /// Format of the findings written to stdout.
+    #[arg(long, value_enum, env = "OUTPUT", default_value_t = OutputFormat::Text, global = true)]
+    pub output: OutputFormat,
+
+    #[command(flatten)]
+    pub logging: LogOptions,
+
+    #[command(flatten)]
+    pub scan: ScanOptions,
+
+    #[command(flatten)]
+    pub notify: NotifyOptions,
+}
+
+#[derive(Subcommand, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigCommand {
+    /// Validate settings, acquire a token, verify permissions and resolve the alerting
+    /// mailbox without scanning.
+    Check,
+}
+
+#[derive(Args, Debug, Clone, PartialEq, Eq)]
+pub struct DaemonArgs {
+    /// Cron expression with a leading seconds field, e.g. "0 0 6 * * *" for 06:00 daily.
+    #[arg(long, env = "SCHEDULE")]
+    pub schedule: String,
+
+    /// Also run once immediately on startup.
+    #[arg(long, env = "RUN_ON_START")]
+    pub run_on_start: bool,
+}
+
+#[derive(Args, Debug, Clone, PartialEq, Eq)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct ReportArgs {
+    // Without a subcommand, scan and report the findings.
+    #[command(subcommand)]
+    pub command: Option<ReportCommand>,
+
+    /// Report format.
+    #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+    pub format: ReportFormat,
+
+    /// Write the report to this file instead of stdout.
+    #[arg(long)]
+    pub out: Option<String>,
+
+    /// Only report how the findings changed since the previous run: new, still open and
+    /// resolved (rotated or removed) credentials. Needs a state store; text and markdown only.
+    #[arg(long)]
+    pub diff: bool,
+}
+
+#[derive(Subcommand, Debug, Clone, PartialEq, Eq)]
+pub enum ReportCommand {
+    /// Show how the findings changed over the runs recorded in the state store, without
+    /// scanning.
+    Trends(TrendsArgs),
+    /// Show how long the runs recorded in the state store took, their Graph calls and
+    /// throttling, and their findings by severity, without scanning.
+    Metrics(TrendsArgs),
+}
+
+#[derive(Args, Debug, Clone, PartialEq, Eq)]
+pub struct TrendsArgs {
+    /// Number of most recent runs to show.
+    #[arg(long, default_value_t = 30)]
+    pub last: usize,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// Plain text, as sent in the notification emails.
+    Text,
+    /// One row per credential, for Excel and ticketing systems.
+    Csv,
+    /// Standalone HTML page with a sortable table, color-coded by days remaining.
+    Html,
+    /// Markdown grouped by application, for wiki pages and issue bodies.
+    Markdown,
+    /// Excel workbook with a summary sheet and one sheet per tenant (requires --out).
+    Xlsx,
+    /// JUnit XML with a failing test case per application, for CI test reports.
+    Junit,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human readable report (`report` only).
+    Text,
+    /// The full findings list as a JSON array, for any scanning command.
+    Json,
+}
+
+#[derive(Subcommand, Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Scan for expired and expiring credentials and log the findings.
+    Scan,
+    /// Scan and write a report of the findings.
+    Report(ReportArgs),
+    /// Scan and send notifications for the findings.
+    Notify,
+    /// Stay running and scan and notify on a cron schedule.
+    Daemon(DaemonArgs),
+    /// Scan and browse the findings in an interactive terminal dashboard.
+    Tui,
+    /// Inspect the configuration.
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// Print every application's password, key and federated identity credentials.
+    Inventory,
+    /// Acknowledge a finding: stop notifying about it until the credential is rotated.
+    Ack(AckArgs),
+    /// Stop notifying about a finding until a date.
+    Snooze(SnoozeArgs),
+    /// Show the audit log of notifications sent.
+    Audit(AuditArgs),
+    /// Show the changes the tool made to application credentials, and with --app the
+    /// notifications sent about them.
+    History(HistoryArgs),
+    /// Maintain the state store.
+    #[command(subcommand)]
+    State(StateCommand),
+    /// Add a new client secret to applications with addPassword and print it. The old
+    /// secrets are left in place. --dry-run only shows what would be added.
+    Rotate(RotateArgs),
+    /// Remove dead credentials from applications. --dry-run only shows what would be removed.
+    Cleanup(CleanupArgs),
+}
+
+#[derive(Args, Debug, Clone, PartialEq, Eq)]
+pub struct CleanupArgs {
+    /// Remove client secrets that expired more than --older-than-days ago, keeping the last
+    /// one of an application that has no unexpired secret.
+    #[arg(long, required = true)]
+    pub expired: bool,
+
+    /// Only secrets expired more than this many days ago [default: CLEANUP_AFTER_DAYS, or 30].
+    #[arg(long)]
+    pub older_than_days: Option<u32>,
+}
+
+#[derive(Args, Debug, Clone, PartialEq, Eq)]
+pub struct RotateArgs {
+    /// App ID (or object ID) of an application to add a secret to; repeat for several.
+    #[arg(long = "app", required = true)]
+    pub apps: Vec<String>,
+
+    /// How many days the new secret is valid [default: ROTATION_LIFETIME_DAYS, or 180].
+    #[arg(long)]
+    pub lifetime_days: Option<u32>,
+
+    /// Display name of the new secret; {date}, {user} and {app} are replaced
+    /// [default: ROTATION_DISPLAY_NAME, or "secret-manager {date}"].
+    #[arg(long)]
+    pub display_name: Option<String>,
+}
+
+#[derive(Subcommand, Debug, Clone, PartialEq, Eq)]
+pub enum StateCommand {
+    /// Remove scan history, audit log entries and other records older than the retention
+    /// period. --dry-run only counts them.
+    Prune(PruneArgs),
+    /// Write everything in the state store to a portable JSON file, e.g. to move to another
+    /// backend.
+    Export(ExportArgs),
+    /// Load a file written by `state export`, a json backend state file or a
+    /// --notify-state-file into the state store.
+    Import(ImportArgs),
+}
+
+#[derive(Args, Debug, Clone, PartialEq, Eq)]
+pub struct ExportArgs {
+    /// Write to this file instead of stdout.
+    #[arg(long)]
+    pub out: Option<String>,
+}
+
+#[derive(Args, Debug, Clone, PartialEq, Eq)]
+pub struct ImportArgs {
+    /// The file to import.
+    pub file: String,
+
+    /// Remove what is in the store under the imported namespaces first, instead of merging.
+    #[arg(long)]
+    pub replace: bool,
+}
+
+#[derive(Args, Debug, Clone, PartialEq, Eq)]
+pub struct PruneArgs {
+    /// Remove records older than this many days [default: STATE_RETENTION_DAYS].
+    #[arg(long)]
+    pub older_than: Option<u32>,
+}
+
+#[derive(Args, Debug, Clone, PartialEq, Eq)]
+pub struct HistoryArgs {
+    /// Only changes to this application (App ID or name). With an App ID, also lists the
+    /// notifications sent about its credentials and whether each is still open, acknowledged
+    /// or resolved.
+    #[arg(long)]
+    pub app: Option<String>,
+
+    /// Only changes made on or after this date (YYYY-MM-DD, UTC).
+    #[arg(long)]
+    pub since: Option<chrono::NaiveDate>,
+
+    /// Only this Graph action, e.g. addPassword or removePassword.
+    #[arg(long)]
+    pub action: Option<String>,
+}
+
+#[derive(Args, Debug, Clone, PartialEq, Eq)]
+pub struct AuditArgs {
+    /// Only notifications sent on or after this date (YYYY-MM-DD, UTC).
+    #[arg(long)]
+    pub since: Option<chrono::NaiveDate>,
+
+    /// Only notifications sent through channels whose name contains this, e.g. "email".
+    #[arg(long)]
+    pub channel: Option<String>,
+
+    /// Only notifications to recipients containing this, e.g. an owner's address.
+    #[arg(long)]
+    pub recipient: Option<String>,
+
+    /// Only notifications about findings containing this, e.g. an App ID or Key ID.
+    #[arg(long)]
+    pub finding: Option<String>,
+}
+
+#[derive(Args, Debug, Clone, PartialEq, Eq)]
+pub struct AckArgs {
+    /// App ID of the application (or the name of the Key Vault).
+    pub app_id: String,
+
+    /// Key ID of the credential (or the name of the Key Vault item).
+    pub key_id: String,
+
+    /// Why, shown in reports next to the finding.
+    #[arg(long)]
+    pub note: Option<String>,
+
+    /// Who is acknowledging it [default: the logged in user].
+    #[arg(long)]
+    pub by: Option<String>,
+
+    /// Remove the acknowledgment or snooze instead.
+    #[arg(long, conflicts_with_all = ["note", "by"])]
+    pub remove: bool,
+}
+
+#[derive(Args, Debug, Clone, PartialEq, Eq)]
+pub struct SnoozeArgs {
+    /// App ID of the application (or the name of the Key Vault).
+    pub app_id: String,
+
+    /// Key ID of the credential (or the name of the Key Vault item).
+    pub key_id: String,
+
+    /// Date (YYYY-MM-DD, UTC) notifications resume on.
+    #[arg(long)]
+    pub until: chrono::NaiveDate,
+
+    /// Why, shown in reports next to the finding.
+    #[arg(long)]
+    pub note: Option<String>,
+
+    /// Who is snoozing it [default: the logged in user].
+    #[arg(long)]
+    pub by: Option<String>,
+}
+
+// Where and how to log.
+#[derive(Args, Debug, Clone)]
+pub struct LogOptions {
+    /// Log more: -v for debug, -vv for trace.
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    pub verbose: u8,
+
+    /// Only log errors; findings are still printed.
+    #[arg(short, long, conflicts_with = "verbose", global = true)]
+    pub quiet: bool,
+
+    /// Log line format.
+    #[arg(long, value_enum, env = "LOG_FORMAT", default_value_t = LogFormat::Text, global = true)]
+    pub log_format: LogFormat,
+
+    /// Also write logs to this file, rotated according to --log-rotation.
+    #[arg(long, env = "LOG_FILE", global = true)]
+    pub log_file: Option<String>,
+
+    /// When to start a new log file.
+    #[arg(long, value_enum, env = "LOG_ROTATION", default_value_t = LogRotation::Daily, global = true)]
+    pub log_rotation: LogRotation,
+
+    /// Size in MB at which the log file is rotated with --log-rotation size.
+    #[arg(long, env = "LOG_MAX_SIZE_MB", default_value_t = 10, global = true)]
+    pub log_max_size_mb: u64,
+
+    /// Number of rotated log files to keep.
+    #[arg(long, env = "LOG_MAX_FILES", default_value_t = 7, global = true)]
+    pub log_max_files: usize,
+}
+
+impl LogOptions {
+    // Level set by -v/-vv/--quiet; None leaves the default (info, or RUST_LOG).
+    pub fn level(&self) -> Option<log::LevelFilter> {
+        if self.quiet {
+            return Some(log::LevelFilter::Error);
+        }
+        match self.verbose {
+            0 => None,
+            1 => Some(log::LevelFilter::Debug),
+            _ => Some(log::LevelFilter::Trace),
+        }
+    }
+}
+
+// Options that control who gets notified and how.
+#[derive(Args, Debug, Clone, Default)]
+pub struct NotifyOptions {
+    /// Send each owner one digest email listing all of their applications, instead of one
+    /// email for everything to RECIEVER_EMAIL.
+    #[arg(long, env = "NOTIFY_DIGEST", global = true)]
+    pub digest: bool,
+
+    /// Email each application's owners directly, one email per application, instead of
+    /// sending everything to RECIEVER_EMAIL.
+    #[arg(long, env = "NOTIFY_OWNERS", global = true)]
+    pub notify_owners: bool,
+
+    /// With --notify-owners, CC RECIEVER_EMAIL on every owner email.
+    #[arg(long, env = "NOTIFY_OWNERS_CC_CENTRAL", global = true)]
+    pub cc_central: bool,
+
+    /// Remember what was notified in this file and don't notify the same credential again
+    /// until it reaches the next reminder tier or REMINDER_CADENCE says it is due again.
+    #[arg(long, env = "NOTIFY_STATE_FILE", global = true)]
+    pub notify_state_file: Option<String>,
+}
+
+// Options that control what gets scanned.
+#[derive(Args, Debug, Clone, Default)]
+pub struct ScanOptions {
+    /// Only scan these app IDs (comma separated), fetched with $batch.
+    #[arg(
+        long = "application",
+        env = "APPLICATION",
+        value_delimiter = ',',
+        global = true
+    )]
+    pub applications: Vec<String>,
+
+    /// Also scan the application object IDs or appIds listed in this file, one per line or
+    /// comma separated. Blank lines and `#` comments are ignored.
+    #[arg(long, env = "APPS_FILE", global = true)]
+    pub apps_file: Option<String>,
+
+    /// Also scan the application object IDs or appIds read from stdin, one per line.
+    #[arg(long, global = true)]
+    pub stdin: bool,
+
+    /// Keep a delta token and application snapshot in this file and only fetch changes.
+    #[arg(long, env = "DELTA_STATE_FILE", global = true)]
+    pub delta_state_file: Option<String>,
+
+    /// Use delta queries like --delta-state-file, keeping the delta token and application
+    /// snapshot in the state store instead, so incremental scans survive restarts.
+    #[arg(long, env = "DELTA_SCAN", global = true)]
+    pub delta: bool,
+
+    /// Give SAML enterprise apps a dedicated signing certificate check.
+    #[arg(long, env = "CHECK_SAML_CERTIFICATES", global = true)]
+    pub check_saml: bool,
+
+    /// Continue an interrupted scan from its checkpoint in the state store instead of
+    /// resolving every application's owners again.
+    #[arg(long, global = true)]
+    pub resume: bool,
+}
+
+impl ScanOptions {
+    // Add the IDs listed in --apps-file and, with --stdin, piped in on stdin to the
+    // applications to scan.
+    pub fn read_app_ids(&mut self) -> anyhow::Result<()> {
+        if let Some(path) = &self.apps_file {
+            let contents = std::fs::read_to_string(path)
+                .map_err(|e| anyhow::anyhow!("Failed to read apps file '{}': {}", path, e))?;
+            self.applications.extend(parse_id_list(&contents));
+        }
+
+        if self.stdin {
+            for line in std::io::stdin().lines() {
+                let line = line?;
+                let id = line.trim();
+                if !id.is_empty() && !self.applications.iter().any(|a| a == id) {
+                    self.applications.push(id.to_string());
+                }
+            }
+            if self.applications.is_empty() {
+                anyhow::bail!("--stdin was given but no application IDs were read");
+            }
+        }
+
+        Ok(())
+    }
+
+    // Fill in options not given on the command line or in the environment from the config file.
+    pub fn apply_config_file(&mut self) {
+        if self.applications.is_empty() {
+            self.applications = list_setting("APPLICATION").unwrap_or_default();
+        }
+        if self.apps_file.is_none() {
+            self.apps_file = setting("APPS_FILE");
+        }
+        if self.delta_state_file.is_none() {
+            self.delta_state_file = setting("DELTA_STATE_FILE");
+        }
+        if !self.delta {
+            self.delta = flag_setting("DELTA_SCAN");
+        }
+        if !self.check_saml {
+            self.check_saml = flag_setting("CHECK_SAML_CERTIFICATES");
+        }
+    }
+
+    // What the scan is limited to when it doesn't cover every application: the application
+    // IDs it was given and the INCLUDE_APPS/EXCLUDE_APPS filters. None for a full scan.
+    pub fn scope(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        if !self.applications.is_empty() {
+            let mut ids = self.applications.clone();
+            ids.sort();
+            ids.dedup();
+            parts.push(format!("apps={}", ids.join(",")));
+        }
+        for var in ["INCLUDE_APPS", "EXCLUDE_APPS"] {
+            if let Some(patterns) = list_setting(var)
+                && !patterns.is_empty()
+            {
+                parts.push(format!("{}={}", var.to_lowercase(), patterns.join(",")));
+            }
+        }
+        (!parts.is_empty()).then(|| parts.join(";"))
+    }
+}
+
+impl NotifyOptions {
+    // Fill in options not given on the command line or in the environment from the config file.
+    pub fn apply_config_file(&mut self) {
+        if !self.digest {
+            self.digest = flag_setting("NOTIFY_DIGEST");
+        }
+        if !self.notify_owners {
+            self.notify_owners = flag_setting("NOTIFY_OWNERS");
+        }
+        if !self.cc_central {
+            self.cc_central = flag_setting("NOTIFY_OWNERS_CC_CENTRAL");
+        }
+        if self.notify_state_file.is_none() {
+            self.notify_state_file = setting("NOTIFY_STATE_FILE");
+        }
+    }
+}
+
+// Split a list of IDs given one per line or comma separated, ignoring blank lines and
+// anything after a `#`. Duplicates are dropped, keeping the first occurrence.
+pub fn parse_id_list(contents: &str) -> Vec<String> {
+    let mut ids: Vec<String> = Vec::new();
+    for line in contents.lines() {
+        let line = line.split('#').next().unwrap_or_default();
+        for id in line.split(',').map(|s| s.trim()) {
+            if !id.is_empty() && !ids.iter().any(|i| i == id) {
+                ids.push(id.to_string());
+            }
+        }
+    }
+    ids
+}
@@ -0,0 +1,672 @@
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use graph_rs_sdk::identity::AzureCloudInstance;
+
+use crate::models::{App, ServicePrincipal};
+
+// Settings are read from the environment, falling back to the config file.
+//
+// The config file (secret-manager.toml by default) uses the lower-cased environment variable
+// names as keys, with lists written as TOML arrays instead of comma separated strings:
+//
+//     alert_threshold_days = [90, 30, 7]
+//     reciever_email = "security@example.com"
+//     admin_email = ["admins@example.com", "secops@example.com"]
+//     exclude_apps = ["*-test"]
+//     application = ["00000000-0000-0000-0000-000000000000"]
+//
+//     [[tenants]]
+//     name = "contoso"
+//     azure_tenant_id = "..."
+//     azure_client_id = "..."
+//     azure_client_secret = "..."
+//     key_vault_names = ["contoso-kv"]
+//
+//     [profiles.sandbox]
+//     reciever_email = "dev-team@example.com"
+//     alert_threshold_days = [30, 7]
+//     tenants = [{ name = "sandbox", azure_tenant_id = "...", azure_client_id = "..." }]
+//
+// Environment variables always win, so secrets can stay out of the file.
+
+static CONFIG_FILE: OnceLock<toml::Table> = OnceLock::new();
+
+// Load the config file. An explicitly given path must exist; otherwise secret-manager.toml
+// in the working directory is used if present.
+//
+// The file may define named profiles, e.g. [profiles.prod] and [profiles.sandbox], each
+// with its own tenants, thresholds and recipients. With --profile, the chosen profile's
+// settings override the top-level ones.
+pub fn load_config_file(path: Option<&str>, profile: Option<&str>) -> anyhow::Result<()> {
+    let (path, required) = match path {
+        Some(p) => (p, true),
+        None => ("secret-manager.toml", false),
+    };
+
+    let mut table = match std::fs::read_to_string(path) {
+        Ok(contents) => contents
+            .parse::<toml::Table>()
+            .map_err(|e| anyhow::anyhow!("Invalid config file '{}': {}", path, e))?,
+        Err(e) if required => anyhow::bail!("Failed to read config file '{}': {}", path, e),
+        Err(_) => toml::Table::new(),
+    };
+
+    let profiles = match table.remove("profiles") {
+        Some(toml::Value::Table(profiles)) => profiles,
+        Some(_) => anyhow::bail!("'profiles' in config file '{}' must be a table", path),
+        None => toml::Table::new(),
+    };
+
+    if let Some(name) = profile {
+        let Some(toml::Value::Table(settings)) = profiles.get(name) else {
+            anyhow::bail!(
+                "Profile '{}' is not defined in config file '{}' (available: {})",
+                name,
+                path,
+                if profiles.is_empty() {
+                    "none".to_string()
+                } else {
+                    profiles.keys().cloned().collect::<Vec<String>>().join(", ")
+                }
+            );
+        };
+        table.extend(settings.clone());
+    }
+
+    let _ = CONFIG_FILE.set(table);
+    Ok(())
+}
+
+fn config_file() -> Option<&'static toml::Table> {
+    CONFIG_FILE.get()
+}
+
+// Render a config file value the way it would be written in the environment.
+fn value_to_string(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(s) => s.clone(),
+        toml::Value::Array(items) => items
+            .iter()
+            .map(value_to_string)
+            .collect::<Vec<String>>()
+            .join(","),
+        other => other.to_string(),
+    }
+}
+
+// Look a setting up in the environment, then in the config file under the lower-cased name.
+pub fn setting(name: &str) -> Option<String> {
+    if let Ok(value) = std::env::var(name) {
+        return Some(value);
+    }
+    config_file()?
+        .get(&name.to_lowercase())
+        .map(value_to_string)
+}
+
+// A comma separated list setting, trimmed, with empty entries dropped.
+pub fn list_setting(name: &str) -> Option<Vec<String>> {
+    setting(name).map(|v| {
+        v.split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect()
+    })
+}
+
+// Set by --dry-run: notifications are printed instead of sent.
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+pub fn set_dry_run(enabled: bool) {
+    DRY_RUN.store(enabled, Ordering::Relaxed);
+}
+
+pub fn dry_run() -> bool {
+    DRY_RUN.load(Ordering::Relaxed)
+}
+
+// A boolean setting; "true" (any case) and "1" count as true.
+pub fn flag_setting(name: &str) -> bool {
+    setting(name).is_some_and(|v| v.eq_ignore_ascii_case("true") || v == "1")
+}
+
+// Timezone expiry dates are shown in, from DISPLAY_TIMEZONE (an IANA name such as
+// "Australia/Sydney"). Defaults to UTC.
+pub fn display_timezone() -> chrono_tz::Tz {
+    setting("DISPLAY_TIMEZONE")
+        .and_then(|tz| tz.trim().parse::<chrono_tz::Tz>().ok())
+        .unwrap_or(chrono_tz::UTC)
+}
+
+// strftime pattern for displayed timestamps, from DATE_FORMAT, e.g. "%d/%m/%Y %H:%M %Z".
+pub fn date_format() -> String {
+    setting("DATE_FORMAT").unwrap_or_else(|| "%Y-%m-%d %H:%M %Z".to_string())
+}
+
+// How many Graph requests to run concurrently when fetching owners etc.
+// Read from FETCH_CONCURRENCY, defaults to 8.
+pub fn fetch_concurrency() -> usize {
+    setting("FETCH_CONCURRENCY")
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(8)
+}
+
+// Marker in an application's tags or notes that opts it out of alerting.
+// Read from IGNORE_MARKER, defaults to "secret-manager:ignore".
+pub fn ignore_marker() -> String {
+    setting("IGNORE_MARKER").unwrap_or_else(|| "secret-manager:ignore".to_string())
+}
+
+// Graph page size ($top) for application and service principal listings.
+// Read from GRAPH_PAGE_SIZE (1-999); None leaves the Graph default of 100.
+pub fn page_size() -> Option<String> {
+    setting("GRAPH_PAGE_SIZE")
+        .and_then(|v| v.trim().parse::<u32>().ok())
+        .filter(|n| (1..=999).contains(n))
+        .map(|n| n.to_string())
+}
+
+// Required fields plus any extra comma separated fields from `var`, without duplicates.
+fn select_fields(required: &[&str], var: &str) -> Vec<String> {
+    let mut fields: Vec<String> = required.iter().map(|f| f.to_string()).collect();
+    for field in list_setting(var).unwrap_or_default() {
+        if !fields.contains(&field) {
+            fields.push(field);
+        }
+    }
+    fields
+}
+
+// $select for applications: App::SELECT plus APPLICATION_SELECT_EXTRA (e.g. "notes,tags").
+pub fn application_select() -> Vec<String> {
+    select_fields(App::SELECT, "APPLICATION_SELECT_EXTRA")
+}
+
+// $select for service principals: ServicePrincipal::SELECT plus SERVICE_PRINCIPAL_SELECT_EXTRA.
+pub fn service_principal_select() -> Vec<String> {
+    select_fields(ServicePrincipal::SELECT, "SERVICE_PRINCIPAL_SELECT_EXTRA")
+}
+
+// The Azure cloud a tenant lives in. Each national cloud has its own Graph, login and
+// Key Vault endpoints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AzureCloud {
+    Public,
+    // Azure US Government (GCC High).
+    UsGovernment,
+    // Azure US Government DoD.
+    UsGovernmentDod,
+    // Azure China, operated by 21Vianet.
+    China,
+}
+
+impl AzureCloud {
+    pub fn parse(value: &str) -> anyhow::Result<AzureCloud> {
+        match value.trim().to_lowercase().as_str() {
+            "" | "public" | "global" => Ok(AzureCloud::Public),
+            "usgov" | "usgovernment" | "gcchigh" => Ok(AzureCloud::UsGovernment),
+            "dod" | "usgovdod" => Ok(AzureCloud::UsGovernmentDod),
+            "china" | "21vianet" => Ok(AzureCloud::China),
+            other => anyhow::bail!(
+                "Unknown Azure cloud '{}'; expected public, usgov, dod or china",
+                other
+            ),
+        }
+    }
+
+    pub fn graph_endpoint(&self) -> &'static str {
+        match self {
+            AzureCloud::Public => "https://graph.microsoft.com/v1.0",
+            AzureCloud::UsGovernment => "https://graph.microsoft.us/v1.0",
+            AzureCloud::UsGovernmentDod => "https://dod-graph.microsoft.us/v1.0",
+            AzureCloud::China => "https://microsoftgraph.chinacloudapi.cn/v1.0",
+        }
+    }
+
+    pub fn authority_host(&self) -> &'static str {
+        match self {
+            AzureCloud::Public => "https://login.microsoftonline.com",
+            AzureCloud::UsGovernment | AzureCloud::UsGovernmentDod => {
+                "https://login.microsoftonline.us"
+            }
+            AzureCloud::China => "https://login.chinacloudapi.cn",
+        }
+    }
+
+    pub fn cloud_instance(&self) -> AzureCloudInstance {
+        match self {
+            AzureCloud::Public => AzureCloudInstance::AzurePublic,
+            AzureCloud::UsGovernment | AzureCloud::UsGovernmentDod => {
+                AzureCloudInstance::AzureUsGovernment
+            }
+            AzureCloud::China => AzureCloudInstance::AzureChina,
+        }
+    }
+
+    // DNS suffix of Key Vaults, e.g. "vault.azure.net" for https://{name}.vault.azure.net.
+    pub fn key_vault_suffix(&self) -> &'static str {
+        match self {
+            AzureCloud::Public => "vault.azure.net",
+            AzureCloud::UsGovernment | AzureCloud::UsGovernmentDod => "vault.usgovcloudapi.net",
+            AzureCloud::China => "vault.azure.cn",
+        }
+    }
+
+    // Token scope for Azure Monitor's ingestion endpoints.
+    pub fn monitor_scope(&self) -> &'static str {
+        match self {
+            AzureCloud::Public => "https://monitor.azure.com/.default",
+            AzureCloud::UsGovernment | AzureCloud::UsGovernmentDod => {
+                "https://monitor.azure.us/.default"
+            }
+            AzureCloud::China => "https://monitor.azure.cn/.default",
+        }
+    }
+}
+
+// A tenant to scan and the client secret credentials to scan it with.
+#[derive(Debug, Clone)]
+pub struct Tenant {
+    pub name: String,
+    pub tenant_id: String,
+    pub client_id: String,
+    pub client_secret: String,
+    // Read from AZURE_CLOUD, defaults to the public cloud.
+    pub cloud: AzureCloud,
+    // Key Vaults to scan in this tenant.
+    pub key_vault_names: Vec<String>,
+}
+
+impl Tenant {
+    // Read a tenant's settings through `lookup`, which is given the setting name without
+    // any tenant prefix, e.g. "AZURE_TENANT_ID". `prefix` is only used to name the
+    // variables in errors; every missing or malformed setting is reported at once.
+    fn from_lookup(
+        name: &str,
+        prefix: &str,
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> anyhow::Result<Tenant> {
+        let mut problems: Vec<String> = Vec::new();
+        let mut var = |key: &str| {
+            lookup(key).unwrap_or_else(|| {
+                problems.push(format!("{}{} is not set", prefix, key));
+                String::new()
+            })
+        };
+
+        let tenant_id = var("AZURE_TENANT_ID");
+        let client_id = var("AZURE_CLIENT_ID");
+        let client_secret = var("AZURE_CLIENT_SECRET");
+
+        // Caught here rather than when the first token is requested.
+        if !client_id.is_empty() && uuid::Uuid::parse_str(client_id.trim()).is_err() {
+            problems.push(format!(
+                "{}AZURE_CLIENT_ID '{}' is not a GUID",
+                prefix, client_id
+            ));
+        }
+        let domain = tenant_id.contains('.') && !tenant_id.trim().contains(char::is_whitespace);
+        if !tenant_id.is_empty() && uuid::Uuid::parse_str(tenant_id.trim()).is_err() && !domain {
+            problems.push(format!(
+                "{}AZURE_TENANT_ID '{}' is not a GUID or domain name",
+                prefix, tenant_id
+            ));
+        }
+
+        let cloud = match AzureCloud::parse(&lookup("AZURE_CLOUD").unwrap_or_default()) {
+            Ok(cloud) => cloud,
+            Err(e) => {
+                problems.push(format!("{}AZURE_CLOUD: {}", prefix, e));
+                AzureCloud::Public
+            }
+        };
+
+        if !problems.is_empty() {
+            anyhow::bail!("tenant '{}': {}", name, problems.join("; "));
+        }
+
+        Ok(Tenant {
+            name: name.to_string(),
+            tenant_id,
+            client_id,
+            client_secret,
+            cloud,
+            key_vault_names: lookup("KEY_VAULT_NAMES")
+                .map(|v| {
+                    v.split(',')
+                        .map(|s| s.trim().to_string())
+                        .filter(|s| !s.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+        })
+    }
+
+    // A named tenant: variables prefixed with its upper-cased name (e.g. CONTOSO_AZURE_TENANT_ID)
+    // override the matching [[tenants]] entry in the config file.
+    fn named(name: &str) -> anyhow::Result<Tenant> {
+        let prefix = format!("{}_", name.to_uppercase());
+        let table = config_file()
+            .and_then(|f| f.get("tenants"))
+            .and_then(|t| t.as_array())
+            .and_then(|tenants| {
+                tenants
+                    .iter()
+                    .filter_map(|t| t.as_table())
+                    .find(|t| t.get("name").and_then(|n| n.as_str()) == Some(name))
+            });
+
+        Tenant::from_lookup(name, &prefix, |key| {
+            std::env::var(format!("{}{}", prefix, key))
+                .ok()
+                .or_else(|| {
+                    table
+                        .and_then(|t| t.get(&key.to_lowercase()))
+                        .map(value_to_string)
+                })
+        })
+    }
+}
+
+// The tenants to scan.
+// TENANTS is a comma separated list of tenant names; each tenant's settings are read from
+// variables prefixed with its upper-cased name, e.g. CONTOSO_AZURE_TENANT_ID,
+// CONTOSO_AZURE_CLIENT_ID, CONTOSO_AZURE_CLIENT_SECRET, CONTOSO_AZURE_CLOUD and
+// CONTOSO_KEY_VAULT_NAMES, falling back to the [[tenants]] entries of the config file.
+// Without TENANTS the config file's [[tenants]] are scanned, and without those a single
+// tenant named "default" is read from the unprefixed settings.
+pub fn tenants() -> anyhow::Result<Vec<Tenant>> {
+    let names: Vec<String> = match std::env::var("TENANTS") {
+        Ok(names) => names
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect(),
+        Err(_) => config_file()
+            .and_then(|f| f.get("tenants"))
+            .and_then(|t| t.as_array())
+            .map(|tenants| {
+                tenants
+                    .iter()
+                    .filter_map(|t| t.get("name").and_then(|n| n.as_str()))
+                    .map(|n| n.to_string())
+                    .collect()
+            })
+            .unwrap_or_default(),
+    };
+
+    if names.is_empty() {
+        if std::env::var("TENANTS").is_ok() {
+            anyhow::bail!("TENANTS is set but lists no tenants");
+        }
+        return Ok(vec![Tenant::from_lookup("default", "", setting)?]);
+    }
+
+    let mut tenants: Vec<Tenant> = Vec::new();
+    let mut problems: Vec<String> = Vec::new();
+    for name in &names {
+        match Tenant::named(name) {
+            Ok(tenant) => tenants.push(tenant),
+            Err(e) => problems.push(e.to_string()),
+        }
+    }
+
+    if !problems.is_empty() {
+        anyhow::bail!("{}", problems.join("\n"));
+    }
+
+    Ok(tenants)
+}
+
+// Check all settings up front, so a misconfigured run fails before doing any work with a
+// list of everything that is missing or malformed rather than a bare error mid-run.
+// `notify` adds the settings needed to send mail.
+pub fn validate(notify: bool) -> anyhow::Result<()> {
+    let mut problems: Vec<String> = Vec::new();
+
+    if let Err(e) = tenants() {
+        problems.extend(e.to_string().lines().map(|l| l.to_string()));
+    }
+
+    if notify {
+        for name in ["ALERTING_EMAIL", "RECIEVER_EMAIL"] {
+            if setting(name).is_none_or(|v| v.trim().is_empty()) {
+                problems.push(format!(
+                    "{} is not set (required to send notifications)",
+                    name
+                ));
+            }
+        }
+    }
+
+    if let Err(e) = crate::alerts::Thresholds::from_env() {
+        problems.push(e.to_string());
+    }
+
+    for name in [
+        "FETCH_CONCURRENCY",
+        "THRESHOLD_DAYS",
+        "PAGERDUTY_DAYS",
+        "TWILIO_DAYS",
+        "ICS_LEAD_DAYS",
+        "REMINDER_INTERVAL_DAYS",
+        "ESCALATE_AFTER",
+        "OWNER_CACHE_HOURS",
+        "STATE_RETENTION_DAYS",
+        "LOCK_TTL_MINUTES",
+        "CLEANUP_AFTER_DAYS",
+    ] {
+        if let Some(value) = setting(name)
+            && value.trim().parse::<u32>().is_err()
+        {
+            problems.push(format!(
+                "{} must be a positive number, got '{}'",
+                name, value
+            ));
+        }
+    }
+
+    if let Some(value) = setting("GRAPH_PAGE_SIZE")
+        && !value
+            .trim()
+            .parse::<u32>()
+            .is_ok_and(|n| (1..=999).contains(&n))
+    {
+        problems.push(format!(
+            "GRAPH_PAGE_SIZE must be between 1 and 999, got '{}'",
+            value
+        ));
+    }
+
+    if let Err(e) = crate::filters::AppFilter::from_env() {
+        problems.push(e.to_string());
+    }
+
+    if let Some(tz) = setting("DISPLAY_TIMEZONE")
+        && tz.trim().parse::<chrono_tz::Tz>().is_err()
+    {
+        problems.push(format!(
+            "DISPLAY_TIMEZONE '{}' is not an IANA timezone name, e.g. 'Europe/London'",
+            tz
+        ));
+    }
+
+    for name in [
+        "HIGH_IMPORTANCE_SEVERITY",
+        "SLACK_MIN_SEVERITY",
+        "DISCORD_MIN_SEVERITY",
+        "WEBHOOK_MIN_SEVERITY",
+        "OPSGENIE_MIN_SEVERITY",
+        "JIRA_MIN_SEVERITY",
+        "SERVICENOW_MIN_SEVERITY",
+        "AZDO_MIN_SEVERITY",
+        "GITHUB_MIN_SEVERITY",
+        "NTFY_MIN_SEVERITY",
+    ] {
+        if let Some(severity) = setting(name)
+            && let Err(e) = crate::alerts::Severity::parse(&severity)
+        {
+            problems.push(format!("{}: {}", name, e));
+        }
+    }
+
+    problems.extend(crate::templates::validate());
+
+    problems.extend(crate::smtp::validate());
+
+    if setting("TEAMS_TEAM_ID").is_some() != setting("TEAMS_CHANNEL_ID").is_some() {
+        problems.push(
+            "TEAMS_TEAM_ID and TEAMS_CHANNEL_ID must be set together to post to a Teams channel"
+                .to_string(),
+        );
+    }
+
+    // Notifier endpoints, including the per-severity Slack webhooks.
+    let severity_suffixes = std::iter::once(String::new()).chain(
+        crate::alerts::Severity::ALL
+            .iter()
+            .map(|s| format!("_{}", s.label().to_uppercase())),
+    );
+    let webhook_vars = [
+        "TEAMS_WEBHOOK_URL",
+        "DISCORD_WEBHOOK_URL",
+        "WEBHOOK_URL",
+        "OPSGENIE_API_URL",
+        "JIRA_URL",
+        "SERVICENOW_URL",
+        "AZDO_ORG_URL",
+        "GITHUB_API_URL",
+        "NTFY_TOPIC_URL",
+    ]
+    .map(|v| v.to_string())
+    .into_iter()
+    .chain(severity_suffixes.map(|suffix| format!("SLACK_WEBHOOK_URL{}", suffix)));
+    for var in webhook_vars {
+        if let Some(url) = setting(&var)
+            && let Err(e) = url::Url::parse(&url)
+        {
+            problems.push(format!("{} is not a valid URL: {}", var, e));
+        }
+    }
+
+    problems.extend(crate::webhook::validate());
+    problems.extend(crate::log_analytics::validate());
+    problems.extend(crate::callback::validate());
+    problems.extend(crate::delivery::validate());
+    problems.extend(crate::dedup::validate());
+    problems.extend(crate::encryption::validate());
+    problems.extend(crate::rotate::validate());
+
+    if let Some(backend) = setting("STATE_BACKEND") {
+        let backend = backend.trim().to_lowercase();
+        let backends = [
+            "sqlite",
+            "json",
+            "azure-table",
+            "azure-blob",
+            "redis",
+            "postgres",
+        ];
+        if !backends.contains(&backend.as_str()) {
+            problems.push(format!(
+                "STATE_BACKEND must be one of {}, got '{}'",
+                backends.join(", "),
+                backend
+            ));
+        } else if backend.starts_with("azure-") || backend == "redis" || backend == "postgres" {
+            match setting("STATE_PATH") {
+                None => problems.push(format!(
+                    "STATE_PATH must be set to the URL for STATE_BACKEND={}",
+                    backend
+                )),
+                Some(path) if url::Url::parse(&path).is_err() => problems.push(format!(
+                    "STATE_PATH is not a valid URL for STATE_BACKEND={}: '{}'",
+                    backend, path
+                )),
+                Some(_) => {}
+            }
+        }
+    }
+
+    for format in list_setting("EMAIL_REPORT_ATTACHMENT").unwrap_or_default() {
+        if !["csv", "html", "xlsx"].contains(&format.to_lowercase().as_str()) {
+            problems.push(format!(
+                "EMAIL_REPORT_ATTACHMENT format must be csv, html or xlsx, got '{}'",
+                format
+            ));
+        }
+    }
+
+    if setting("TWILIO_ACCOUNT_SID").is_some() {
+        for name in ["TWILIO_AUTH_TOKEN", "TWILIO_FROM", "TWILIO_TO"] {
+            if setting(name).is_none_or(|v| v.trim().is_empty()) {
+                problems.push(format!("{} is not set (required to send SMS)", name));
+            }
+        }
+    }
+
+    for (url, project, what) in [
+        ("JIRA_URL", "JIRA_PROJECT", "open Jira issues"),
+        (
+            "AZDO_ORG_URL",
+            "AZDO_PROJECT",
+            "create Azure DevOps work items",
+        ),
+    ] {
+        if setting(url).is_some() != setting(project).is_some() {
+            problems.push(format!(
+                "{} and {} must be set together to {}",
+                url, project, what
+            ));
+        }
+    }
+
+    if let Some(url) = setting("PROXY_URL")
+        && let Err(e) = url::Url::parse(&url)
+    {
+        problems.push(format!("PROXY_URL '{}' is not a valid URL: {}", url, e));
+    }
+
+    if problems.is_empty() {
+        return Ok(());
+    }
+
+    anyhow::bail!(
+        "Configuration problems:\n{}",
+        problems
+            .iter()
+            .map(|p| format!("  - {}", p))
+            .collect::<Vec<String>>()
+            .join("\n")
+    )
+}
+
+// The proxy all outbound HTTP goes through: PROXY_URL, if set, with optional basic auth from
+// PROXY_USERNAME and PROXY_PASSWORD. It is given to each HTTP client explicitly (the Graph
+// client, the token endpoint, Key Vault and the notifiers); without PROXY_URL, reqwest still
+// honors HTTPS_PROXY/HTTP_PROXY from the environment.
+pub fn proxy() -> anyhow::Result<Option<reqwest::Proxy>> {
+    let Some(proxy_url) = setting("PROXY_URL") else {
+        return Ok(None);
+    };
+    let mut proxy = reqwest::Proxy::all(proxy_url.trim())
+        .map_err(|e| anyhow::anyhow!("Invalid PROXY_URL '{}': {}", proxy_url, e))?;
+    if let Some(username) = setting("PROXY_USERNAME") {
+        let password = setting("PROXY_PASSWORD").unwrap_or_default();
+        proxy = proxy.basic_auth(&username, &password);
+    }
+    Ok(Some(proxy))
+}
+
+// An HTTP client for calls outside the Graph SDK, going through the configured proxy.
+// A bad PROXY_URL is reported by validate at startup, so it is not repeated here.
+pub fn http_client() -> reqwest::Client {
+    let mut builder = reqwest::Client::builder();
+    if let Ok(Some(proxy)) = proxy() {
+        builder = builder.proxy(proxy);
+    }
+    // Only fails where reqwest::Client::new() would panic too: no TLS backend.
+    builder.build().expect("failed to build the HTTP client")
+}
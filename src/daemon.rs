@@ -0,0 +1,139 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use chrono::Utc;
+use graph_rs_sdk::*;
+use log::{error, info, warn};
+use tokio::sync::Mutex;
+use tokio_cron_scheduler::{Job, JobScheduler};
+
+use crate::alerts::format_timestamp;
+use crate::cli::{Cli, Command, DaemonArgs};
+use crate::config::Tenant;
+use crate::{callback, delivery, state};
+
+struct Context {
+    cli: Cli,
+    tenants: Vec<Tenant>,
+    clients: Vec<GraphClient>,
+    // Held while a scan runs, so a slow scan is never overlapped by the next one.
+    running: Mutex<()>,
+    // Set while a run is waiting for the delivery window to open.
+    queued: AtomicBool,
+}
+
+async fn scheduled_run(context: &Arc<Context>) {
+    let Ok(_running) = context.running.try_lock() else {
+        warn!("Previous scheduled run is still in progress; skipping this one.");
+        return;
+    };
+
+    info!("Starting scheduled run");
+    if let Some(store) = state::store()
+        && let Err(e) = store.reload().await
+    {
+        warn!("Failed to reload the state store: {}", e);
+    }
+    match crate::run(
+        &context.cli,
+        &Command::Notify,
+        &context.tenants,
+        &context.clients,
+    )
+    .await
+    {
+        Ok(code) => info!("Scheduled run finished with exit code {}", code),
+        Err(e) => error!("Scheduled run failed: {:#}", e),
+    }
+
+    queue_for_window(context);
+}
+
+// Notifications held back outside the delivery window go out with a run as soon as it
+// opens, rather than waiting for the next scheduled run inside it.
+fn queue_for_window(context: &Arc<Context>) {
+    let Ok(Some(window)) = delivery::window() else {
+        return;
+    };
+    let now = Utc::now();
+    if window.is_open(now) || context.queued.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let opens = window.next_open(now);
+    info!(
+        "Queued a run for when the delivery window opens at {}",
+        format_timestamp(opens)
+    );
+    let context = context.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep((opens - Utc::now()).to_std().unwrap_or_default()).await;
+        context.queued.store(false, Ordering::SeqCst);
+        scheduled_run(&context).await;
+    });
+}
+
+// Stay running and scan + notify on the cron schedule in --schedule, until Ctrl-C/SIGTERM.
+// The schedule has a leading seconds field, e.g. "0 0 6 * * Mon-Fri" for 06:00 on weekdays.
+pub async fn run_daemon(
+    cli: &Cli,
+    args: &DaemonArgs,
+    tenants: Vec<Tenant>,
+    clients: Vec<GraphClient>,
+) -> anyhow::Result<()> {
+    let context = Arc::new(Context {
+        cli: cli.clone(),
+        tenants,
+        clients,
+        running: Mutex::new(()),
+        queued: AtomicBool::new(false),
+    });
+
+    let mut scheduler = JobScheduler::new().await?;
+
+    let job_context = context.clone();
+    let job = Job::new_async(args.schedule.as_str(), move |_id, _scheduler| {
+        let context = job_context.clone();
+        Box::pin(async move { scheduled_run(&context).await })
+    })
+    .map_err(|e| anyhow::anyhow!("Invalid schedule '{}': {}", args.schedule, e))?;
+
+    scheduler.add(job).await?;
+    scheduler.start().await?;
+    info!("Daemon started with schedule '{}'", args.schedule);
+
+    if callback::enabled() {
+        tokio::spawn(async {
+            if let Err(e) = callback::serve().await {
+                error!("Acknowledge link endpoint stopped: {:#}", e);
+            }
+        });
+    }
+
+    if args.run_on_start {
+        scheduled_run(&context).await;
+    }
+
+    shutdown_signal().await?;
+    info!("Shutting down");
+    scheduler.shutdown().await?;
+
+    Ok(())
+}
+
+// Wait for Ctrl-C, or SIGTERM from a container runtime.
+async fn shutdown_signal() -> anyhow::Result<()> {
+    #[cfg(unix)]
+    {
+        let mut terminate =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result?,
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await?;
+
+    Ok(())
+}
@@ -0,0 +1,509 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use graph_rs_sdk::*;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::alerts::{Alert, Category, Finding};
+use crate::config::{list_setting, setting};
+use crate::owners::get_manager_email;
+use crate::state;
+
+// When a credential was last notified, and at which reminder tier.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NotificationRecord {
+    pub tier: Option<i64>,
+    pub notified_at: DateTime<Utc>,
+    // How many times it has been notified.
+    #[serde(default)]
+    pub count: u32,
+    // Set once notifications about it started copying managers.
+    #[serde(default)]
+    pub escalated: bool,
+}
+
+// What has been notified, kept between runs so a daily schedule doesn't send the same
+// alerts every day. A credential is notified again once it crosses into the next reminder
+// tier (or expires), or when its reminder cadence (see Cadence) says it is due again.
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct NotificationState {
+    // Keyed by finding_key.
+    pub notified: HashMap<String, NotificationRecord>,
+}
+
+// Identifies a credential across runs: tenant, application and keyId.
+pub fn finding_key(alert: &Alert, finding: &Finding) -> String {
+    format!(
+        "{}:{}:{}",
+        alert.tenant,
+        alert.app_id.as_deref().unwrap_or(&alert.name),
+        finding.key_id.as_deref().unwrap_or(&finding.credential)
+    )
+}
+
+// How often a credential is reminded about between tier changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Interval {
+    EveryRun,
+    // Calendar days, so a daily schedule whose run finishes a little earlier than the day
+    // before still counts as a day later.
+    Days(i64),
+}
+
+// REMINDER_CADENCE: a comma separated list of DAYS=INTERVAL rules, where DAYS is the most
+// days remaining a rule applies to ("expired" for expired credentials, "*" for everything
+// beyond the other rules) and INTERVAL a number of days or "run" for every run. The rule
+// with the fewest DAYS that still covers a credential applies. For weekly reminders, daily
+// ones in the last week and every run once expired:
+//   REMINDER_CADENCE=expired=run,7=1,*=7
+// Credentials no rule covers are only notified again when they reach the next tier.
+// REMINDER_INTERVAL_DAYS=N is short for REMINDER_CADENCE=*=N.
+#[derive(Debug, Default)]
+struct Cadence {
+    expired: Option<Interval>,
+    // Ascending by days remaining.
+    rules: Vec<(i64, Interval)>,
+    beyond: Option<Interval>,
+}
+
+impl Cadence {
+    fn parse(value: &str) -> anyhow::Result<Cadence> {
+        let mut cadence = Cadence::default();
+        for rule in value.split(',').map(str::trim).filter(|r| !r.is_empty()) {
+            let (days, interval) = rule
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("'{}' is not DAYS=INTERVAL", rule))?;
+            let interval = match interval.trim().to_lowercase().as_str() {
+                "run" | "0" => Interval::EveryRun,
+                n => Interval::Days(n.parse::<u32>().map(i64::from).map_err(|_| {
+                    anyhow::anyhow!("'{}' is not a number of days or 'run'", interval.trim())
+                })?),
+            };
+            match days.trim().to_lowercase().as_str() {
+                "expired" => cadence.expired = Some(interval),
+                "*" => cadence.beyond = Some(interval),
+                n => cadence.rules.push((
+                    n.parse::<u32>().map(i64::from).map_err(|_| {
+                        anyhow::anyhow!("'{}' is not a number of days, 'expired' or '*'", n)
+                    })?,
+                    interval,
+                )),
+            }
+        }
+        cadence.rules.sort_by_key(|(days, _)| *days);
+        Ok(cadence)
+    }
+
+    fn from_settings() -> anyhow::Result<Cadence> {
+        if let Some(cadence) = setting("REMINDER_CADENCE") {
+            return Cadence::parse(&cadence);
+        }
+        Ok(Cadence {
+            beyond: setting("REMINDER_INTERVAL_DAYS")
+                .and_then(|d| d.trim().parse::<i64>().ok())
+                .map(Interval::Days),
+            ..Cadence::default()
+        })
+    }
+
+    fn interval(&self, finding: &Finding, now: DateTime<Utc>) -> Option<Interval> {
+        if finding.category == Category::Expired {
+            return self.expired;
+        }
+        let days = finding.days_remaining(now);
+        self.rules
+            .iter()
+            .find(|(up_to, _)| days <= *up_to)
+            .map(|(_, interval)| *interval)
+            .or(self.beyond)
+    }
+}
+
+// Problems with REMINDER_CADENCE, for config::validate.
+pub fn validate() -> Vec<String> {
+    match setting("REMINDER_CADENCE").map(|c| Cadence::parse(&c)) {
+        Some(Err(e)) => vec![format!("REMINDER_CADENCE: {}", e)],
+        _ => Vec::new(),
+    }
+}
+
+impl NotificationState {
+    pub fn load(path: &str) -> NotificationState {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                info!(
+                    "Failed to parse notification state '{}': {}. Starting over.",
+                    path, e
+                );
+                NotificationState::default()
+            }),
+            Err(_) => NotificationState::default(),
+        }
+    }
+
+    pub fn save(&self, path: &str) -> anyhow::Result<()> {
+        std::fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    // Load the state from the --notify-state-file if given, otherwise from the state store.
+    // None when neither is configured.
+    pub async fn open(path: Option<&str>) -> anyhow::Result<Option<NotificationState>> {
+        if let Some(path) = path {
+            return Ok(Some(NotificationState::load(path)));
+        }
+        let Some(store) = state::store() else {
+            return Ok(None);
+        };
+        Ok(Some(NotificationState {
+            notified: state::load_all(store, state::NOTIFICATIONS)
+                .await?
+                .into_iter()
+                .collect(),
+        }))
+    }
+
+    // Save the state back to where `open` loaded it from.
+    pub async fn persist(&self, path: Option<&str>) -> anyhow::Result<()> {
+        if let Some(path) = path {
+            return self.save(path);
+        }
+        let Some(store) = state::store() else {
+            return Ok(());
+        };
+        for (key, _) in store.list(state::NOTIFICATIONS).await? {
+            if !self.notified.contains_key(&key) {
+                store.delete(state::NOTIFICATIONS, &key).await?;
+            }
+        }
+        for (key, record) in &self.notified {
+            state::save(store, state::NOTIFICATIONS, key, record).await?;
+        }
+        Ok(())
+    }
+
+    fn is_due(&self, cadence: &Cadence, key: &str, finding: &Finding, now: DateTime<Utc>) -> bool {
+        let Some(record) = self.notified.get(key) else {
+            return true;
+        };
+        record.tier != finding.tier
+            || match cadence.interval(finding, now) {
+                Some(Interval::EveryRun) => true,
+                Some(Interval::Days(days)) => {
+                    (now.date_naive() - record.notified_at.date_naive()).num_days() >= days
+                }
+                None => false,
+            }
+    }
+
+    // Forget the records of credentials in the `scanned` tenants that are no longer among the
+    // `flagged` ones (rotated or removed), so they are notified afresh if they ever come back.
+    // Only tenants whose every application was scanned may be passed: a credential a partial
+    // run didn't look at is not resolved.
+    pub fn forget_resolved(&mut self, flagged: &HashSet<String>, scanned: &[String]) {
+        self.notified.retain(|key, _| {
+            flagged.contains(key)
+                || !scanned
+                    .iter()
+                    .any(|tenant| key.starts_with(&format!("{}:", tenant)))
+        });
+    }
+
+    // Drop the findings that were already notified at their current tier, and the alerts
+    // left without any.
+    pub fn filter_due(&mut self, alerts: Vec<Alert>, now: DateTime<Utc>) -> Vec<Alert> {
+        // Validated up front; an unparseable cadence only reminds on tier changes.
+        let cadence = Cadence::from_settings().unwrap_or_default();
+
+        let mut suppressed = 0;
+        let due: Vec<Alert> = alerts
+            .into_iter()
+            .filter_map(|mut alert| {
+                let before = alert.findings.len();
+                let findings = std::mem::take(&mut alert.findings);
+                alert.findings = findings
+                    .into_iter()
+                    .filter(|f| self.is_due(&cadence, &finding_key(&alert, f), f, now))
+                    .collect();
+                suppressed += before - alert.findings.len();
+                (!alert.findings.is_empty()).then_some(alert)
+            })
+            .collect();
+
+        if suppressed > 0 {
+            info!(
+                "Suppressed {} credentials already notified at their current tier",
+                suppressed
+            );
+        }
+        due
+    }
+
+    // Remember that the findings of `alerts` were notified.
+    pub fn record(&mut self, notified: Vec<(String, Option<i64>)>, now: DateTime<Utc>) {
+        for (key, tier) in notified {
+            let record = self.notified.entry(key).or_insert(NotificationRecord {
+                tier,
+                notified_at: now,
+                count: 0,
+                escalated: false,
+            });
+            record.tier = tier;
+            record.notified_at = now;
+            record.count += 1;
+        }
+    }
+
+    // Escalate alerts with a credential that has already been notified ESCALATE_AFTER times
+    // without being rotated: the owners' managers (looked up in Graph) and the leadership
+    // list in ESCALATION_EMAIL are copied on its notifications from then on.
+    pub async fn escalate(&mut self, client: &GraphClient, alerts: &mut [Alert]) {
+        let Some(after) = setting("ESCALATE_AFTER").and_then(|n| n.trim().parse::<u32>().ok())
+        else {
+            return;
+        };
+        let leadership = list_setting("ESCALATION_EMAIL").unwrap_or_default();
+        let mut managers: HashMap<String, Option<String>> = HashMap::new();
+
+        for alert in alerts.iter_mut() {
+            if !self.unactioned(alert, after) {
+                continue;
+            }
+
+            let mut escalate_to = leadership.clone();
+            for owner in &alert.owners {
+                if !managers.contains_key(owner) {
+                    let manager = get_manager_email(client, owner).await.unwrap_or_else(|e| {
+                        warn!("Failed to look up the manager of {}: {}", owner, e);
+                        None
+                    });
+                    managers.insert(owner.clone(), manager);
+                }
+                if let Some(manager) = &managers[owner]
+                    && !alert.owners.iter().any(|o| o.eq_ignore_ascii_case(manager))
+                {
+                    escalate_to.push(manager.clone());
+                }
+            }
+            escalate_to.sort_unstable();
+            escalate_to.dedup();
+            alert.escalate_to = escalate_to;
+        }
+    }
+
+    // Whether a credential of `alert` has been notified `after` times or more, marking the
+    // ones escalated for the first time.
+    fn unactioned(&mut self, alert: &Alert, after: u32) -> bool {
+        let mut unactioned = false;
+        for finding in &alert.findings {
+            let key = finding_key(alert, finding);
+            if let Some(record) = self.notified.get_mut(&key)
+                && record.count >= after
+            {
+                if !record.escalated {
+                    info!(
+                        "{} was notified {} times without being rotated; escalating",
+                        key, record.count
+                    );
+                    record.escalated = true;
+                }
+                unactioned = true;
+            }
+        }
+        unactioned
+    }
+}
+
+// The key and tier of every finding in `alerts`, for NotificationState::record.
+pub fn notified_keys(alerts: &[Alert]) -> Vec<(String, Option<i64>)> {
+    alerts
+        .iter()
+        .flat_map(|a| a.findings.iter().map(move |f| (finding_key(a, f), f.tier)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone};
+
+    use super::*;
+    use crate::alerts::Thresholds;
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 6, 1, 12, 0, 0).unwrap()
+    }
+
+    fn finding(days: i64) -> Finding {
+        let thresholds = Thresholds::new(Thresholds::DEFAULT.to_vec()).unwrap();
+        Finding::new(
+            now() + Duration::days(days),
+            now(),
+            &thresholds,
+            "Client secret",
+            String::new(),
+        )
+        .with_key(Some(format!("key-{}", days)), None)
+    }
+
+    fn alert(findings: Vec<Finding>) -> Alert {
+        Alert {
+            tenant: "default".to_string(),
+            name: "app".to_string(),
+            app_id: Some("app-id".to_string()),
+            owners: Vec::new(),
+            escalate_to: Vec::new(),
+            findings,
+        }
+    }
+
+    // State recording `finding` as notified at `tier`, `days_ago` days before now.
+    fn notified(finding: &Finding, tier: Option<i64>, days_ago: i64) -> NotificationState {
+        let mut state = NotificationState::default();
+        state.notified.insert(
+            finding_key(&alert(Vec::new()), finding),
+            NotificationRecord {
+                tier,
+                notified_at: now() - Duration::days(days_ago),
+                count: 1,
+                escalated: false,
+            },
+        );
+        state
+    }
+
+    #[test]
+    fn cadence_rules_are_sorted_by_days() {
+        let cadence = Cadence::parse("*=7, 30=3, expired=run, 7=1").unwrap();
+        assert_eq!(cadence.expired, Some(Interval::EveryRun));
+        assert_eq!(cadence.beyond, Some(Interval::Days(7)));
+        assert_eq!(
+            cadence.rules,
+            vec![(7, Interval::Days(1)), (30, Interval::Days(3))]
+        );
+    }
+
+    #[test]
+    fn cadence_rejects_malformed_rules() {
+        assert!(Cadence::parse("7").is_err());
+        assert!(Cadence::parse("7=weekly").is_err());
+        assert!(Cadence::parse("soon=1").is_err());
+        assert!(Cadence::parse("-1=1").is_err());
+    }
+
+    #[test]
+    fn cadence_applies_the_narrowest_rule_covering_a_finding() {
+        let cadence = Cadence::parse("expired=run,7=1,30=3").unwrap();
+        assert_eq!(
+            cadence.interval(&finding(5), now()),
+            Some(Interval::Days(1))
+        );
+        assert_eq!(
+            cadence.interval(&finding(20), now()),
+            Some(Interval::Days(3))
+        );
+        assert_eq!(cadence.interval(&finding(60), now()), None);
+        assert_eq!(
+            cadence.interval(&finding(-2), now()),
+            Some(Interval::EveryRun)
+        );
+    }
+
+    #[test]
+    fn due_again_once_the_interval_has_passed() {
+        let cadence = Cadence::parse("30=3").unwrap();
+        let f = finding(20);
+        let key = finding_key(&alert(Vec::new()), &f);
+        assert!(!notified(&f, f.tier, 2).is_due(&cadence, &key, &f, now()));
+        assert!(notified(&f, f.tier, 3).is_due(&cadence, &key, &f, now()));
+    }
+
+    #[test]
+    fn filter_due_keeps_only_new_findings_and_tier_changes() {
+        let unchanged = finding(20);
+        let mut state = notified(&unchanged, unchanged.tier, 1);
+        let moved = finding(5);
+        state.notified.insert(
+            finding_key(&alert(Vec::new()), &moved),
+            NotificationRecord {
+                tier: Some(30),
+                notified_at: now(),
+                count: 1,
+                escalated: false,
+            },
+        );
+
+        let due = state.filter_due(vec![alert(vec![unchanged, moved, finding(50)])], now());
+
+        let keys: Vec<_> = due[0]
+            .findings
+            .iter()
+            .map(|f| f.key_id.clone().unwrap())
+            .collect();
+        assert_eq!(keys, vec!["key-5", "key-50"]);
+    }
+
+    // State with a record for each of `keys`.
+    fn recorded(keys: &[&str]) -> NotificationState {
+        let f = finding(20);
+        let record = notified(&f, f.tier, 1)
+            .notified
+            .into_values()
+            .next()
+            .unwrap();
+        NotificationState {
+            notified: keys
+                .iter()
+                .map(|key| (key.to_string(), record.clone()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn resolved_credentials_of_scanned_tenants_are_forgotten() {
+        let mut state = recorded(&["default:app-id:open", "default:app-id:rotated"]);
+        let flagged = HashSet::from(["default:app-id:open".to_string()]);
+        state.forget_resolved(&flagged, &["default".to_string()]);
+        assert!(state.notified.contains_key("default:app-id:open"));
+        assert!(!state.notified.contains_key("default:app-id:rotated"));
+    }
+
+    #[test]
+    fn a_partial_run_forgets_nothing() {
+        let mut state = recorded(&["default:app-id:key", "default:other-app:key"]);
+        state.forget_resolved(&HashSet::new(), &[]);
+        assert_eq!(state.notified.len(), 2);
+    }
+
+    #[test]
+    fn records_of_other_tenants_are_kept() {
+        let mut state = recorded(&["default:app-id:key", "contoso:app-id:key"]);
+        state.forget_resolved(&HashSet::new(), &["contoso".to_string()]);
+        assert!(state.notified.contains_key("default:app-id:key"));
+        assert!(!state.notified.contains_key("contoso:app-id:key"));
+    }
+
+    #[test]
+    fn filtering_a_subset_of_the_alerts_keeps_the_other_records() {
+        let mut state = recorded(&["default:app-id:key-20", "default:other-app:key"]);
+        state.filter_due(vec![alert(vec![finding(20)])], now());
+        assert!(state.notified.contains_key("default:other-app:key"));
+    }
+
+    #[test]
+    fn notifications_are_counted_until_escalation() {
+        let expiring = finding(5);
+        let mut state = NotificationState::default();
+        let alert = alert(vec![expiring.clone()]);
+
+        state.record(notified_keys(std::slice::from_ref(&alert)), now());
+        state.record(notified_keys(std::slice::from_ref(&alert)), now());
+        assert!(!state.unactioned(&alert, 3));
+
+        state.record(notified_keys(std::slice::from_ref(&alert)), now());
+        assert!(state.unactioned(&alert, 3));
+        let record = &state.notified[&finding_key(&alert, &expiring)];
+        assert_eq!(record.count, 3);
+        assert!(record.escalated);
+    }
+}
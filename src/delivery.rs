@@ -0,0 +1,385 @@
+use chrono::{DateTime, Datelike, Duration, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+
+use log::warn;
+
+use crate::alerts::Alert;
+use crate::config::{display_timezone, dry_run, setting};
+use crate::dedup::finding_key;
+use crate::state::{self, HELD};
+
+// The one document in HELD.
+const HELD_KEY: &str = "alerts";
+
+// When notifications may go out, so owners aren't paged at 3am on a Sunday:
+//   DELIVERY_DAYS      days of the week, e.g. "Mon-Fri" or "Mon,Wed,Fri" (default every day)
+//   DELIVERY_HOURS     time of day, e.g. "08:00-18:00"; "22:00-06:00" spans midnight
+//                      (default all day)
+//   DELIVERY_TIMEZONE  IANA timezone the window is in (default DISPLAY_TIMEZONE)
+// Outside the window nothing is sent and nothing is recorded as notified. The alerts are held
+// in the state store and go out with the first run inside the window, along with that run's
+// own findings; the daemon schedules that run itself. Held alerts from a tenant the run
+// scanned again are replaced by what it found, so rotated credentials aren't notified late.
+pub struct DeliveryWindow {
+    days: Vec<Weekday>,
+    start: NaiveTime,
+    end: NaiveTime,
+    timezone: Tz,
+}
+
+fn parse_days(value: &str) -> anyhow::Result<Vec<Weekday>> {
+    let mut days = Vec::new();
+    for part in value.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let parse = |day: &str| {
+            day.trim()
+                .parse::<Weekday>()
+                .map_err(|_| anyhow::anyhow!("'{}' is not a day of the week", day.trim()))
+        };
+        match part.split_once('-') {
+            Some((first, last)) => {
+                let (mut day, last) = (parse(first)?, parse(last)?);
+                // Ranges may wrap around the weekend, e.g. Sat-Mon.
+                while day != last {
+                    days.push(day);
+                    day = day.succ();
+                }
+                days.push(last);
+            }
+            None => days.push(parse(part)?),
+        }
+    }
+    if days.is_empty() {
+        anyhow::bail!("no days given");
+    }
+    Ok(days)
+}
+
+fn parse_hours(value: &str) -> anyhow::Result<(NaiveTime, NaiveTime)> {
+    let (start, end) = value
+        .split_once('-')
+        .ok_or_else(|| anyhow::anyhow!("expected HH:MM-HH:MM"))?;
+    let time = |t: &str| {
+        NaiveTime::parse_from_str(t.trim(), "%H:%M")
+            .map_err(|_| anyhow::anyhow!("'{}' is not a time (HH:MM)", t.trim()))
+    };
+    let (start, end) = (time(start)?, time(end)?);
+    if start == end {
+        anyhow::bail!("the window is empty");
+    }
+    Ok((start, end))
+}
+
+// The configured delivery window, or None to deliver at any time.
+pub fn window() -> anyhow::Result<Option<DeliveryWindow>> {
+    let days = setting("DELIVERY_DAYS");
+    let hours = setting("DELIVERY_HOURS");
+    if days.is_none() && hours.is_none() {
+        return Ok(None);
+    }
+
+    let days = match days {
+        Some(days) => parse_days(&days).map_err(|e| anyhow::anyhow!("DELIVERY_DAYS: {}", e))?,
+        None => parse_days("Mon-Sun")?,
+    };
+    let (start, end) = match hours {
+        Some(hours) => parse_hours(&hours).map_err(|e| anyhow::anyhow!("DELIVERY_HOURS: {}", e))?,
+        None => (NaiveTime::MIN, NaiveTime::MIN),
+    };
+    let timezone = match setting("DELIVERY_TIMEZONE") {
+        Some(tz) => tz.trim().parse::<Tz>().map_err(|_| {
+            anyhow::anyhow!("DELIVERY_TIMEZONE '{}' is not an IANA timezone name", tz)
+        })?,
+        None => display_timezone(),
+    };
+
+    Ok(Some(DeliveryWindow {
+        days,
+        start,
+        end,
+        timezone,
+    }))
+}
+
+// Problems with the delivery window settings, for config::validate.
+pub fn validate() -> Vec<String> {
+    match window() {
+        Ok(_) => Vec::new(),
+        Err(e) => vec![e.to_string()],
+    }
+}
+
+impl DeliveryWindow {
+    fn all_day(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub fn is_open(&self, now: DateTime<Utc>) -> bool {
+        let local = now.with_timezone(&self.timezone);
+        if !self.days.contains(&local.weekday()) {
+            return false;
+        }
+        let time = local.time();
+        if self.all_day() {
+            true
+        } else if self.start < self.end {
+            time >= self.start && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+
+    // When the window next opens: `now` if it is open.
+    pub fn next_open(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        if self.is_open(now) {
+            return now;
+        }
+        let today = now.with_timezone(&self.timezone).date_naive();
+        (0..=7)
+            .map(|offset| today + Duration::days(offset))
+            .filter(|date| self.days.contains(&date.weekday()))
+            .filter_map(|date| {
+                self.timezone
+                    .from_local_datetime(&date.and_time(self.start))
+                    .earliest()
+            })
+            .map(|start| start.with_timezone(&Utc))
+            .find(|start| *start > now)
+            .unwrap_or(now)
+    }
+}
+
+// Add `alerts` to the alerts held until the window opens.
+pub async fn hold(alerts: &[Alert]) {
+    let Some(store) = state::store() else {
+        warn!(
+            "There is no state store to hold alerts in; they go out with the next run inside \
+             the delivery window"
+        );
+        return;
+    };
+    if dry_run() {
+        return;
+    }
+    let result = async {
+        let held: Vec<Alert> = state::load(store, HELD, HELD_KEY)
+            .await?
+            .unwrap_or_default();
+        state::save(store, HELD, HELD_KEY, &merge(alerts.to_vec(), held, &[])).await
+    }
+    .await;
+    if let Err(e) = result {
+        warn!("Failed to hold alerts for the delivery window: {}", e);
+    }
+}
+
+// `alerts` plus the alerts held outside the window, which are then released. `scanned` are
+// the tenants this run scanned.
+pub async fn release_held(alerts: Vec<Alert>, scanned: &[String]) -> Vec<Alert> {
+    let Some(store) = state::store() else {
+        return alerts;
+    };
+    let held: Vec<Alert> = match state::load(store, HELD, HELD_KEY).await {
+        Ok(held) => held.unwrap_or_default(),
+        Err(e) => {
+            warn!(
+                "Failed to load the alerts held for the delivery window: {}",
+                e
+            );
+            return alerts;
+        }
+    };
+    if held.is_empty() {
+        return alerts;
+    }
+    if !dry_run()
+        && let Err(e) = store.delete(HELD, HELD_KEY).await
+    {
+        warn!(
+            "Failed to release the alerts held for the delivery window: {}",
+            e
+        );
+    }
+    merge(alerts, held, scanned)
+}
+
+// `current` with the findings of `held` it doesn't have, leaving out held alerts from the
+// tenants in `rescanned`: the current alerts say what is still open there.
+fn merge(mut current: Vec<Alert>, held: Vec<Alert>, rescanned: &[String]) -> Vec<Alert> {
+    for alert in held {
+        if rescanned.contains(&alert.tenant) {
+            continue;
+        }
+        let existing = current.iter().position(|a| {
+            a.tenant == alert.tenant && a.app_id == alert.app_id && a.name == alert.name
+        });
+        match existing {
+            Some(index) => {
+                let target = &mut current[index];
+                for finding in alert.findings {
+                    let key = finding_key(target, &finding);
+                    if !target
+                        .findings
+                        .iter()
+                        .any(|f| finding_key(target, f) == key)
+                    {
+                        target.findings.push(finding);
+                    }
+                }
+            }
+            None => current.push(alert),
+        }
+    }
+    current
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alerts::{Finding, Thresholds};
+
+    fn at(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn office_hours() -> DeliveryWindow {
+        let (start, end) = parse_hours("08:00-18:00").unwrap();
+        DeliveryWindow {
+            days: parse_days("Mon-Fri").unwrap(),
+            start,
+            end,
+            timezone: chrono_tz::UTC,
+        }
+    }
+
+    #[test]
+    fn days_are_parsed_as_lists_and_ranges() {
+        use Weekday::*;
+        assert_eq!(parse_days("Mon-Wed").unwrap(), [Mon, Tue, Wed]);
+        assert_eq!(parse_days("mon, wed ,Fri").unwrap(), [Mon, Wed, Fri]);
+        assert_eq!(parse_days("Sat-Mon").unwrap(), [Sat, Sun, Mon]);
+        assert_eq!(parse_days("Tue").unwrap(), [Tue]);
+        assert!(parse_days("Mon-Funday").is_err());
+        assert!(parse_days(" , ").is_err());
+    }
+
+    #[test]
+    fn hours_are_parsed_as_a_range() {
+        let (start, end) = parse_hours("22:00 - 06:30").unwrap();
+        assert_eq!(start, NaiveTime::from_hms_opt(22, 0, 0).unwrap());
+        assert_eq!(end, NaiveTime::from_hms_opt(6, 30, 0).unwrap());
+        assert!(parse_hours("08:00").is_err());
+        assert!(parse_hours("8am-6pm").is_err());
+        assert!(parse_hours("09:00-09:00").is_err());
+    }
+
+    #[test]
+    fn the_window_is_open_on_its_days_and_hours() {
+        let window = office_hours();
+        // 2025-03-03 is a Monday.
+        assert!(window.is_open(at("2025-03-03T08:00:00Z")));
+        assert!(window.is_open(at("2025-03-03T17:59:00Z")));
+        assert!(!window.is_open(at("2025-03-03T18:00:00Z")));
+        assert!(!window.is_open(at("2025-03-03T07:59:00Z")));
+        assert!(!window.is_open(at("2025-03-01T12:00:00Z")));
+    }
+
+    #[test]
+    fn a_window_across_midnight_is_open_on_both_sides() {
+        let (start, end) = parse_hours("22:00-06:00").unwrap();
+        let window = DeliveryWindow {
+            days: parse_days("Mon-Sun").unwrap(),
+            start,
+            end,
+            timezone: chrono_tz::UTC,
+        };
+        assert!(window.is_open(at("2025-03-03T23:00:00Z")));
+        assert!(window.is_open(at("2025-03-03T05:00:00Z")));
+        assert!(!window.is_open(at("2025-03-03T12:00:00Z")));
+    }
+
+    #[test]
+    fn the_window_next_opens_on_its_next_day() {
+        let window = office_hours();
+        let monday_morning = at("2025-03-03T08:00:00Z");
+        assert_eq!(window.next_open(at("2025-03-01T10:00:00Z")), monday_morning);
+        assert_eq!(window.next_open(at("2025-03-03T06:00:00Z")), monday_morning);
+        assert_eq!(
+            window.next_open(at("2025-03-03T19:00:00Z")),
+            at("2025-03-04T08:00:00Z")
+        );
+        let open = at("2025-03-04T09:30:00Z");
+        assert_eq!(window.next_open(open), open);
+    }
+
+    fn alert(tenant: &str, keys: &[&str]) -> Alert {
+        let now = Utc::now();
+        let thresholds = Thresholds::new(Thresholds::DEFAULT.to_vec()).unwrap();
+        Alert {
+            tenant: tenant.to_string(),
+            name: "Payroll".to_string(),
+            app_id: Some("app-id".to_string()),
+            owners: Vec::new(),
+            escalate_to: Vec::new(),
+            findings: keys
+                .iter()
+                .map(|key| {
+                    Finding::new(
+                        now + Duration::days(3),
+                        now,
+                        &thresholds,
+                        "Client secret",
+                        String::new(),
+                    )
+                    .with_key(Some(key.to_string()), None)
+                })
+                .collect(),
+        }
+    }
+
+    fn keys(alerts: &[Alert]) -> Vec<(String, Vec<String>)> {
+        alerts
+            .iter()
+            .map(|a| {
+                let keys = a.findings.iter().filter_map(|f| f.key_id.clone()).collect();
+                (a.tenant.clone(), keys)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn held_findings_are_added_to_the_current_alerts() {
+        let merged = merge(
+            vec![alert("contoso", &["a"])],
+            vec![alert("contoso", &["a", "b"]), alert("fabrikam", &["c"])],
+            &[],
+        );
+        assert_eq!(
+            keys(&merged),
+            [
+                (
+                    "contoso".to_string(),
+                    vec!["a".to_string(), "b".to_string()]
+                ),
+                ("fabrikam".to_string(), vec!["c".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn held_alerts_of_rescanned_tenants_are_dropped() {
+        let merged = merge(
+            vec![alert("contoso", &["a"])],
+            vec![alert("contoso", &["b"]), alert("fabrikam", &["c"])],
+            &["contoso".to_string()],
+        );
+        assert_eq!(
+            keys(&merged),
+            [
+                ("contoso".to_string(), vec!["a".to_string()]),
+                ("fabrikam".to_string(), vec!["c".to_string()]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::{HashMap, HashSet};
+
+use graph_rs_sdk::*;
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::config::application_select;
+use crate::filters::AppFilter;
+use crate::models::{App, Owner, Page};
+use crate::owner_cache::OwnerCache;
+use crate::owners::get_application_owners;
+use crate::retry::{PagingError, paging_with_retry, send_with_retry};
+use crate::state::{self, DELTA};
+
+// What is kept between runs for incremental scans: the last delta link and a snapshot
+// of every application (raw JSON) and its resolved owners.
+// Unchanged applications still have to be evaluated every run since expiry is time based,
+// they just don't have to be fetched again.
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct DeltaState {
+    pub delta_link: Option<String>,
+    pub applications: HashMap<String, serde_json::Value>,
+    pub owners: HashMap<String, Vec<Owner>>,
+}
+
+impl DeltaState {
+    pub fn load(path: &str) -> DeltaState {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                info!(
+                    "Failed to parse delta state '{}': {}. Starting over.",
+                    path, e
+                );
+                DeltaState::default()
+            }),
+            Err(_) => DeltaState::default(),
+        }
+    }
+
+    // Written to a temporary file and renamed over the old one, so a crash mid-write can't
+    // leave a truncated snapshot behind.
+    pub fn save(&self, path: &str) -> anyhow::Result<()> {
+        let temporary = format!("{}.tmp", path);
+        std::fs::write(&temporary, serde_json::to_string(self)?)?;
+        std::fs::rename(&temporary, path)?;
+        Ok(())
+    }
+
+    async fn open(location: &DeltaLocation<'_>) -> anyhow::Result<DeltaState> {
+        match location {
+            DeltaLocation::File(path) => Ok(DeltaState::load(path)),
+            DeltaLocation::Store(tenant) => {
+                let store = delta_store()?;
+                Ok(state::load(store, DELTA, tenant).await?.unwrap_or_default())
+            }
+        }
+    }
+
+    async fn persist(&self, location: &DeltaLocation<'_>) -> anyhow::Result<()> {
+        match location {
+            DeltaLocation::File(path) => self.save(path),
+            DeltaLocation::Store(tenant) => state::save(delta_store()?, DELTA, tenant, self).await,
+        }
+    }
+
+    // The $deltatoken query parameter of the stored delta link.
+    fn delta_token(&self) -> Option<String> {
+        let link = url::Url::parse(self.delta_link.as_deref()?).ok()?;
+        link.query_pairs()
+            .find(|(k, _)| k == "$deltatoken")
+            .map(|(_, v)| v.into_owned())
+    }
+}
+
+// Where the delta state is kept: the --delta-state-file, or with --delta the state store,
+// one document per tenant.
+pub enum DeltaLocation<'a> {
+    File(&'a str),
+    Store(&'a str),
+}
+
+fn delta_store() -> anyhow::Result<&'static dyn state::StateStore> {
+    state::store().ok_or_else(|| {
+        anyhow::anyhow!("--delta needs a state store; set STATE_BACKEND or STATE_PATH")
+    })
+}
+
+// Run /applications/delta, with the stored token if there is one.
+// Returns the pages, or None if Graph rejected the token and a full sync is needed. Any other
+// failure, and any failure of a full sync, is an error.
+async fn fetch_delta_pages(
+    client: &GraphClient,
+    token: Option<&str>,
+) -> anyhow::Result<Option<Vec<Page<serde_json::Value>>>> {
+    let select = application_select();
+    let select: Vec<&str> = select.iter().map(String::as_str).collect();
+
+    let pages = paging_with_retry(client, || {
+        let mut request = client.applications().delta().select(&select);
+        if let Some(token) = token {
+            request = request.delta_token(token);
+        }
+        request.paging().json::<serde_json::Value>()
+    })
+    .await;
+
+    match pages {
+        Ok(pages) => Ok(Some(pages)),
+        Err(e) if token.is_some() && e.downcast_ref().is_some_and(resync_required) => {
+            info!("Delta token rejected: {}", e);
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+// Graph answers 410 Gone, or an error with code resyncRequired, when a delta token can't be
+// used any more.
+fn resync_required(error: &PagingError) -> bool {
+    error.status == reqwest::StatusCode::GONE
+        || error
+            .code
+            .as_deref()
+            .is_some_and(|code| code.eq_ignore_ascii_case("resyncRequired"))
+}
+
+// Run /applications/delta without a token. Never None, since there is no token to reject.
+async fn full_sync(client: &GraphClient) -> anyhow::Result<Vec<Page<serde_json::Value>>> {
+    fetch_delta_pages(client, None)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Delta query without a token was rejected"))
+}
+
+// Return all applications with their owners, using Graph delta queries so only applications
+// that changed since the last run are fetched again. The delta token and application snapshot
+// are persisted at `location`. Falls back to a full sync when the token is rejected, e.g.
+// when Graph answers 410 Gone because a resync is required.
+pub async fn get_applications_with_delta(
+    client: &GraphClient,
+    location: DeltaLocation<'_>,
+) -> anyhow::Result<Vec<App>> {
+    let mut state = DeltaState::open(&location).await?;
+    let token = state.delta_token();
+
+    let (pages, incremental) = match token.as_deref() {
+        Some(token) => match fetch_delta_pages(client, Some(token)).await? {
+            Some(pages) => (pages, true),
+            None => {
+                info!("Delta token is no longer valid. Falling back to a full sync.");
+                state = DeltaState::default();
+                (full_sync(client).await?, false)
+            }
+        },
+        None => {
+            info!("No delta token found. Running a full sync.");
+            (full_sync(client).await?, false)
+        }
+    };
+
+    let select = application_select();
+    let select: Vec<&str> = select.iter().map(String::as_str).collect();
+
+    let mut changed: HashSet<String> = HashSet::new();
+
+    for page in &pages {
+        for application in &page.value {
+            let Some(id) = application["id"].as_str() else {
+                continue;
+            };
+
+            if application.get("@removed").is_some() {
+                state.applications.remove(id);
+                state.owners.remove(id);
+                continue;
+            }
+
+            if incremental {
+                // Changed objects may only carry the changed properties; fetch them in full.
+                let response = send_with_retry(|| {
+                    client
+                        .application(id)
+                        .get_application()
+                        .select(&select)
+                        .send()
+                })
+                .await?;
+                let status = response.status();
+                if status == reqwest::StatusCode::NOT_FOUND {
+                    // Deleted since the delta was taken; the next delta reports the removal.
+                    state.applications.remove(id);
+                    state.owners.remove(id);
+                    continue;
+                }
+                if !status.is_success() {
+                    let text = response.text().await.unwrap_or_default();
+                    anyhow::bail!(
+                        "Fetching changed application '{}' failed with status {}: {}",
+                        id,
+                        status,
+                        text
+                    );
+                }
+                let full = response.json::<serde_json::Value>().await?;
+                state.applications.insert(id.to_string(), full);
+            } else {
+                state
+                    .applications
+                    .insert(id.to_string(), application.clone());
+            }
+            changed.insert(id.to_string());
+        }
+
+        if let Some(link) = &page.deltaLink {
+            state.delta_link = Some(link.clone());
+        }
+    }
+
+    info!(
+        "Delta scan: {} changed applications, {} total",
+        changed.len(),
+        state.applications.len()
+    );
+
+    let filter = AppFilter::from_env()?;
+    let cache = OwnerCache::load().await;
+    let mut apps: Vec<App> = Vec::new();
+
+    for (id, application) in &state.applications {
+        let mut app: App = match serde_json::from_value(application.clone()) {
+            Ok(a) => a,
+            Err(e) => {
+                info!("Failed to parse application: {}. Skipping.", e);
+                continue;
+            }
+        };
+
+        // Filtered apps stay in the snapshot so changing the filters doesn't need a full sync.
+        if !filter.allows(app.displayName.as_deref(), app.appId.as_deref()) {
+            continue;
+        }
+
+        if changed.contains(id) || !state.owners.contains_key(id) || cache.is_stale(id).await {
+            let Some(owners) = get_application_owners(client, &app).await? else {
+                continue;
+            };
+            cache.put(id, &owners).await;
+            state.owners.insert(id.clone(), owners);
+        }
+
+        app.insert_owners(state.owners[id].clone());
+        apps.push(app);
+    }
+
+    cache.log_hits();
+    state.persist(&location).await?;
+
+    Ok(apps)
+}
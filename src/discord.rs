@@ -0,0 +1,107 @@
+use log::error;
+
+use crate::alerts::{Alert, Severity, format_expiry};
+use crate::audit::alert_findings;
+use crate::config::setting;
+use crate::notifiers::{alerts_at_or_above, post_json};
+use crate::templates::portal_url;
+
+// Discord allows 10 embeds per message and 25 fields per embed.
+const EMBEDS_PER_MESSAGE: usize = 10;
+const MAX_FIELDS: usize = 25;
+
+// Discord notifications are enabled by DISCORD_WEBHOOK_URL; DISCORD_MIN_SEVERITY leaves out
+// less urgent applications.
+pub fn enabled() -> bool {
+    setting("DISCORD_WEBHOOK_URL").is_some()
+}
+
+// Embed colour (RGB) for a severity.
+fn color(severity: Severity) -> u32 {
+    match severity {
+        Severity::Critical => 0xD32F2F,
+        Severity::High => 0xF57C00,
+        Severity::Medium => 0xFBC02D,
+        Severity::Low => 0x9E9E9E,
+    }
+}
+
+// One embed per application: a field per credential with its expiry countdown, then the
+// tenant and owners.
+fn embed(alert: &Alert) -> serde_json::Value {
+    let now = chrono::Utc::now();
+    let mut fields: Vec<serde_json::Value> = alert
+        .findings
+        .iter()
+        .take(MAX_FIELDS - 2)
+        .map(|f| {
+            serde_json::json!({
+                "name": f.credential,
+                "value": format!(
+                    "[{}] {}",
+                    f.severity.label(),
+                    format_expiry(f.end_date_time, now)
+                ),
+                "inline": false
+            })
+        })
+        .collect();
+    fields.push(serde_json::json!({ "name": "Tenant", "value": alert.tenant, "inline": true }));
+    fields.push(serde_json::json!({
+        "name": "Owners",
+        "value": if alert.owners.is_empty() {
+            "None".to_string()
+        } else {
+            alert.owners.join(", ")
+        },
+        "inline": true
+    }));
+
+    let mut embed = serde_json::json!({
+        "title": format!("[{}] {}", alert.severity().label(), alert.name),
+        "color": color(alert.severity()),
+        "fields": fields
+    });
+    if let Some(app_id) = &alert.app_id {
+        embed["url"] = serde_json::Value::String(portal_url(app_id));
+    }
+    embed
+}
+
+// Post the findings to DISCORD_WEBHOOK_URL, most urgent first, in as many messages as the
+// embed limit needs.
+pub async fn post_messages(alerts: &[Alert]) -> anyhow::Result<()> {
+    let url = setting("DISCORD_WEBHOOK_URL")
+        .ok_or_else(|| anyhow::anyhow!("DISCORD_WEBHOOK_URL is not set"))?;
+
+    let mut sorted = alerts_at_or_above(alerts, "DISCORD_MIN_SEVERITY");
+    if sorted.is_empty() {
+        return Ok(());
+    }
+    sorted.sort_by(|a, b| b.severity().cmp(&a.severity()).then(a.name.cmp(&b.name)));
+
+    let chunks: Vec<&[&Alert]> = sorted.chunks(EMBEDS_PER_MESSAGE).collect();
+    let mut failures = 0;
+    for (i, chunk) in chunks.iter().enumerate() {
+        let mut message = serde_json::json!({
+            "embeds": chunk.iter().map(|a| embed(a)).collect::<Vec<serde_json::Value>>()
+        });
+        if i == 0 {
+            message["content"] = serde_json::Value::String(format!(
+                "{} applications have credentials that need attention",
+                sorted.len()
+            ));
+        }
+
+        let findings = alert_findings(chunk.iter().copied());
+        if let Err(e) = post_json("Discord", &url, &message, &findings).await {
+            error!("{}", e);
+            failures += 1;
+        }
+    }
+
+    if failures > 0 {
+        anyhow::bail!("{} of {} Discord messages failed", failures, chunks.len());
+    }
+    Ok(())
+}
@@ -0,0 +1,656 @@
+use std::collections::BTreeMap;
+
+use base64::Engine;
+use graph_rs_sdk::*;
+use log::{error, info};
+
+use crate::alerts::{Alert, Category, Severity, Thresholds};
+use crate::audit::{alert_findings, record_notification, response_id};
+use crate::calendar;
+use crate::cli::NotifyOptions;
+use crate::config::{dry_run, list_setting, setting};
+use crate::report;
+use crate::retry::send_with_retry;
+use crate::smtp;
+use crate::stats::{NOTIFICATIONS_FAILED, NOTIFICATIONS_SENT, count};
+use crate::templates::{alerts_context, render_custom_body, render_html_alerts, render_subject};
+
+// Render alerts as a plain text body, expired credentials in their own section.
+pub fn render_alerts(alerts: &[Alert], thresholds: &Thresholds) -> String {
+    render_alert_refs(&alerts.iter().collect::<Vec<&Alert>>(), thresholds)
+}
+
+fn render_alert_refs(alerts: &[&Alert], thresholds: &Thresholds) -> String {
+    let mut content = String::new();
+
+    for (category, intro) in [
+        (
+            Category::Expired,
+            "The following applications have credentials that have already expired and should be removed or replaced:".to_string(),
+        ),
+        (
+            Category::ExpiringSoon,
+            format!(
+                "The following applications have credentials expiring within the next {} days:",
+                thresholds.max_days()
+            ),
+        ),
+    ] {
+        let section = alerts
+            .iter()
+            .filter(|alert| alert.has_category(category))
+            .map(|alert| {
+                format!(
+                    "Tenant: {}\nApplication: {}\nOwners: {}\n{} (Severity: {}):\n{}\n",
+                    alert.tenant,
+                    alert.name,
+                    if alert.owners.is_empty() {
+                        "None".to_string()
+                    } else {
+                        alert.owners.join(", ")
+                    },
+                    category.heading(),
+                    alert
+                        .findings_in(category)
+                        .map(|f| f.severity)
+                        .max()
+                        .map(|s| s.label())
+                        .unwrap_or_default(),
+                    alert
+                        .findings_in(category)
+                        .map(|f| f.summary())
+                        .collect::<Vec<String>>()
+                        .join("\n")
+                )
+            })
+            .collect::<Vec<String>>();
+
+        if !section.is_empty() {
+            content.push_str(&format!("{}\n\n{}\n", intro, section.join("\n")));
+        }
+    }
+
+    content
+}
+
+// Subject suffix naming the tenants the alerts came from, e.g. " [contoso, fabrikam]".
+fn tenant_tag<'a>(alerts: impl IntoIterator<Item = &'a Alert>) -> String {
+    let mut tenants: Vec<&str> = alerts.into_iter().map(|a| a.tenant.as_str()).collect();
+    tenants.sort_unstable();
+    tenants.dedup();
+    format!(" [{}]", tenants.join(", "))
+}
+
+// An outgoing notification email. `text` is always rendered; `html` is used as the body
+// unless EMAIL_FORMAT=text.
+// EMAIL_SUBJECT_TEMPLATE and EMAIL_BODY_TEMPLATE name Tera template files that replace the
+// built-in subject and body; see templates::alerts_context for the variables available.
+pub struct Mail {
+    pub to: Vec<String>,
+    pub cc: Vec<String>,
+    pub bcc: Vec<String>,
+    pub reply_to: Vec<String>,
+    pub subject: String,
+    pub importance: &'static str,
+    pub text: String,
+    pub html: Option<String>,
+    pub attachments: Vec<Attachment>,
+    // The findings it is about, for the audit log.
+    pub findings: Vec<String>,
+}
+
+// A file attached to a Mail.
+pub struct Attachment {
+    pub name: String,
+    pub content_type: String,
+    pub content: Vec<u8>,
+}
+
+impl Mail {
+    // A mail about `alerts`, rendered as text and from the HTML template with `intro` leading.
+    fn for_alerts(
+        to: Vec<String>,
+        subject: String,
+        importance: &'static str,
+        intro: &str,
+        alerts: &[&Alert],
+        thresholds: &Thresholds,
+    ) -> anyhow::Result<Mail> {
+        let context = alerts_context(intro, &to, alerts, thresholds);
+        let subject = render_subject(&subject, &context)?;
+
+        let text = match render_custom_body(&context, false)? {
+            Some(body) if !html_enabled() => body,
+            _ if intro.is_empty() => render_alert_refs(alerts, thresholds),
+            _ => format!("{}\n\n{}", intro, render_alert_refs(alerts, thresholds)),
+        };
+
+        let html = match render_custom_body(&context, true)? {
+            Some(body) => body,
+            None => render_html_alerts(&context)?,
+        };
+
+        let severity = alerts
+            .iter()
+            .map(|a| a.severity())
+            .max()
+            .unwrap_or(Severity::Low);
+
+        // Anything at or above HIGH_IMPORTANCE_SEVERITY is flagged, whatever the caller chose.
+        let importance = if severity >= high_importance_severity() {
+            "high"
+        } else {
+            importance
+        };
+
+        // Escalated alerts copy the owners' managers and ESCALATION_EMAIL.
+        let mut cc = copy_recipients("EMAIL_CC", severity);
+        for address in alerts.iter().flat_map(|a| &a.escalate_to) {
+            if !cc
+                .iter()
+                .chain(&to)
+                .any(|c| c.eq_ignore_ascii_case(address))
+            {
+                cc.push(address.clone());
+            }
+        }
+
+        Ok(Mail {
+            to,
+            cc,
+            bcc: copy_recipients("EMAIL_BCC", severity),
+            reply_to: list_setting("EMAIL_REPLY_TO").unwrap_or_default(),
+            subject,
+            importance,
+            text,
+            html: Some(html),
+            attachments: Vec::new(),
+            findings: alert_findings(alerts.iter().copied()),
+        })
+    }
+
+    pub fn use_html(&self) -> bool {
+        self.html.is_some() && html_enabled()
+    }
+}
+
+// `mail`, counting a mail that couldn't be put together (a template that fails to render,
+// a report that can't be attached) as a failed notification, so its alerts aren't recorded
+// as notified.
+fn prepared(mail: anyhow::Result<Mail>) -> anyhow::Result<Mail> {
+    if mail.is_err() {
+        count(&NOTIFICATIONS_FAILED, 1);
+    }
+    mail
+}
+
+// CC/BCC addresses for an alert mail (comma separated): EMAIL_CC / EMAIL_BCC, unless the
+// mail's highest severity has its own list, e.g. EMAIL_CC_CRITICAL or EMAIL_BCC_HIGH.
+fn copy_recipients(var: &str, severity: Severity) -> Vec<String> {
+    list_setting(&format!("{}_{}", var, severity.label().to_uppercase()))
+        .or_else(|| list_setting(var))
+        .unwrap_or_default()
+}
+
+// The findings report attached to the admin-facing summary mails, from
+// EMAIL_REPORT_ATTACHMENT: csv, html and/or xlsx (comma separated), rendered like
+// `report --format`.
+fn report_attachments(alerts: &[Alert]) -> anyhow::Result<Vec<Attachment>> {
+    let stamp = chrono::Utc::now().format("%Y-%m-%d");
+    list_setting("EMAIL_REPORT_ATTACHMENT")
+        .unwrap_or_default()
+        .iter()
+        .map(|format| {
+            let (extension, content_type, content) = match format.to_lowercase().as_str() {
+                "csv" => ("csv", "text/csv", report::render_csv(alerts)?.into_bytes()),
+                "html" => (
+                    "html",
+                    "text/html",
+                    report::render_html(alerts).into_bytes(),
+                ),
+                "xlsx" => (
+                    "xlsx",
+                    "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+                    report::render_xlsx(alerts)?,
+                ),
+                other => anyhow::bail!(
+                    "EMAIL_REPORT_ATTACHMENT format must be csv, html or xlsx, got '{}'",
+                    other
+                ),
+            };
+            Ok(Attachment {
+                name: format!("credential-findings-{}.{}", stamp, extension),
+                content_type: content_type.to_string(),
+                content,
+            })
+        })
+        .collect()
+}
+
+// Lowest severity sent with high importance, from HIGH_IMPORTANCE_SEVERITY (default high).
+fn high_importance_severity() -> Severity {
+    setting("HIGH_IMPORTANCE_SEVERITY")
+        .and_then(|v| Severity::parse(&v).ok())
+        .unwrap_or(Severity::High)
+}
+
+fn email_addresses(addresses: &[String]) -> Vec<serde_json::Value> {
+    addresses
+        .iter()
+        .map(|address| {
+            serde_json::json!({
+                "emailAddress": {
+                    "address": address
+                }
+            })
+        })
+        .collect()
+}
+
+// HTML mail unless EMAIL_FORMAT=text.
+fn html_enabled() -> bool {
+    !setting("EMAIL_FORMAT").is_some_and(|f| f.eq_ignore_ascii_case("text"))
+}
+
+// Number of body lines shown for a mail in dry-run mode.
+const DRY_RUN_PREVIEW_LINES: usize = 20;
+
+// The shared mailbox alerts are sent as, from EMAIL_FROM (with an optional EMAIL_FROM_NAME
+// display name), if it differs from the ALERTING_EMAIL mailbox that sends them.
+// Sending as another mailbox needs the Send As permission on it in Exchange Online.
+fn send_as(alerting_email: &str) -> Option<serde_json::Value> {
+    let from = setting("EMAIL_FROM").filter(|f| !f.eq_ignore_ascii_case(alerting_email))?;
+    let mut address = serde_json::json!({ "address": from });
+    if let Some(name) = setting("EMAIL_FROM_NAME") {
+        address["name"] = serde_json::Value::String(name);
+    }
+    Some(serde_json::json!({ "emailAddress": address }))
+}
+
+// Print what send_mail would have sent. The text body is previewed even for HTML mail.
+fn print_dry_run_mail(from: &str, mail: &Mail) {
+    println!("[dry-run] Would send mail");
+    if smtp::enabled() {
+        println!("Via: {}", smtp::describe());
+    }
+    match send_as(from) {
+        Some(send_as) => println!(
+            "From: {} (sent as, via {})",
+            send_as["emailAddress"]["address"]
+                .as_str()
+                .unwrap_or_default(),
+            from
+        ),
+        None => println!("From: {}", from),
+    }
+    println!("To: {}", mail.to.join(", "));
+    if !mail.cc.is_empty() {
+        println!("Cc: {}", mail.cc.join(", "));
+    }
+    if !mail.bcc.is_empty() {
+        println!("Bcc: {}", mail.bcc.join(", "));
+    }
+    if !mail.reply_to.is_empty() {
+        println!("Reply-To: {}", mail.reply_to.join(", "));
+    }
+    println!("Subject: {}", mail.subject);
+    println!("Importance: {}", mail.importance);
+    println!("Format: {}", if mail.use_html() { "HTML" } else { "Text" });
+    if !mail.attachments.is_empty() {
+        println!(
+            "Attachments: {}",
+            mail.attachments
+                .iter()
+                .map(|a| format!("{} ({} bytes)", a.name, a.content.len()))
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+    }
+    println!();
+
+    let lines: Vec<&str> = mail.text.lines().collect();
+    for line in lines.iter().take(DRY_RUN_PREVIEW_LINES) {
+        println!("{}", line);
+    }
+    if lines.len() > DRY_RUN_PREVIEW_LINES {
+        println!("... ({} more lines)", lines.len() - DRY_RUN_PREVIEW_LINES);
+    }
+    println!();
+}
+
+// Send a mail from ALERTING_EMAIL, or as EMAIL_FROM through it, over Graph or SMTP
+// (EMAIL_TRANSPORT=smtp). With --dry-run the mail is printed instead.
+pub async fn send_mail(client: &GraphClient, mail: &Mail) -> anyhow::Result<()> {
+    let alerting_email =
+        setting("ALERTING_EMAIL").ok_or_else(|| anyhow::anyhow!("ALERTING_EMAIL is not set"))?;
+
+    if dry_run() {
+        print_dry_run_mail(&alerting_email, mail);
+        return Ok(());
+    }
+
+    let result = if smtp::enabled() {
+        smtp::send(&alerting_email, mail).await
+    } else {
+        send_graph_mail(client, &alerting_email, mail).await
+    };
+
+    match result {
+        Ok(message_id) => {
+            count(&NOTIFICATIONS_SENT, 1);
+            let recipients: Vec<String> = mail
+                .to
+                .iter()
+                .chain(&mail.cc)
+                .chain(&mail.bcc)
+                .cloned()
+                .collect();
+            record_notification("email", &recipients, &mail.findings, message_id).await;
+            Ok(())
+        }
+        Err(e) => {
+            count(&NOTIFICATIONS_FAILED, 1);
+            Err(e)
+        }
+    }
+}
+
+// Returns the request id Graph answered with; sendMail doesn't return the message itself.
+async fn send_graph_mail(
+    client: &GraphClient,
+    alerting_email: &str,
+    mail: &Mail,
+) -> anyhow::Result<Option<String>> {
+    let (content_type, content) = match &mail.html {
+        Some(html) if mail.use_html() => ("HTML", html),
+        _ => ("Text", &mail.text),
+    };
+
+    let mut body = serde_json::json!({
+        "message": {
+            "subject": mail.subject,
+            "importance": mail.importance,
+            "body": {
+                "contentType": content_type,
+                "content": content
+            },
+            "toRecipients": email_addresses(&mail.to),
+            "ccRecipients": email_addresses(&mail.cc),
+            "bccRecipients": email_addresses(&mail.bcc),
+            "replyTo": email_addresses(&mail.reply_to)
+        },
+        "saveToSentItems": "true"
+    });
+
+    if !mail.attachments.is_empty() {
+        body["message"]["attachments"] = mail
+            .attachments
+            .iter()
+            .map(|a| {
+                serde_json::json!({
+                    "@odata.type": "#microsoft.graph.fileAttachment",
+                    "name": a.name,
+                    "contentType": a.content_type,
+                    "contentBytes": base64::engine::general_purpose::STANDARD.encode(&a.content)
+                })
+            })
+            .collect();
+    }
+
+    // Send-as: the request still goes to ALERTING_EMAIL's sendMail, with the shared mailbox
+    // as the message's from address.
+    if let Some(from) = send_as(alerting_email) {
+        body["message"]["from"] = from;
+    }
+
+    let response = send_with_retry(|| client.user(alerting_email).send_mail(&body).send()).await?;
+    if !response.status().is_success() {
+        anyhow::bail!(
+            "Sending mail '{}' failed with status {}",
+            mail.subject,
+            response.status()
+        );
+    }
+
+    info!("Email sent with response: {:?}", response);
+
+    Ok(response_id(response.headers()))
+}
+
+// Send email alert for expired and expiring credentials.
+// The email is sent from ALERTING_EMAIL to RECIEVER_EMAIL with the list of credentials,
+// expired ones in their own section. If anything has already expired the subject says so
+// and the message is sent with high importance, as are high severity findings.
+// The full report is attached as set by EMAIL_REPORT_ATTACHMENT.
+pub async fn send_email_alert(
+    client: &GraphClient,
+    alerts: &[Alert],
+    thresholds: &Thresholds,
+) -> anyhow::Result<()> {
+    let reciever_email =
+        setting("RECIEVER_EMAIL").ok_or_else(|| anyhow::anyhow!("RECIEVER_EMAIL is not set"))?;
+
+    let any_expired = alerts.iter().any(|a| a.has_category(Category::Expired));
+
+    let (subject, importance) = if any_expired {
+        (
+            "Action Required: Expired Credentials for Applications",
+            "high",
+        )
+    } else {
+        ("Alert: Expiring Credentials for Applications", "normal")
+    };
+
+    let mail = Mail::for_alerts(
+        vec![reciever_email],
+        format!("{}{}", subject, tenant_tag(alerts)),
+        importance,
+        "",
+        &alerts.iter().collect::<Vec<&Alert>>(),
+        thresholds,
+    )
+    .and_then(|mut mail| {
+        mail.attachments = report_attachments(alerts)?;
+        Ok(mail)
+    });
+    send_mail(client, &prepared(mail)?).await
+}
+
+// Send the report of applications with expired/expiring credentials but nobody to notify
+// to the admin/security distribution list in ADMIN_EMAIL (comma separated).
+pub async fn send_ownerless_report(
+    client: &GraphClient,
+    alerts: &[Alert],
+    thresholds: &Thresholds,
+) -> anyhow::Result<()> {
+    let admin_emails = match list_setting("ADMIN_EMAIL") {
+        Some(emails) => emails,
+        None => {
+            info!(
+                "ADMIN_EMAIL is not set; not sending the report of {} ownerless applications.",
+                alerts.len()
+            );
+            return Ok(());
+        }
+    };
+
+    let mail = Mail::for_alerts(
+        admin_emails,
+        format!(
+            "Alert: Ownerless Applications with Expiring Credentials{}",
+            tenant_tag(alerts)
+        ),
+        "high",
+        "The following applications have no owners that can be notified. Please assign owners or take care of these credentials.",
+        &alerts.iter().collect::<Vec<&Alert>>(),
+        thresholds,
+    )
+    .and_then(|mut mail| {
+        mail.attachments = report_attachments(alerts)?;
+        Ok(mail)
+    });
+    send_mail(client, &prepared(mail)?).await
+}
+
+// Send every owner a single digest listing all of the applications they own that need
+// attention. A digest that fails to render or send is logged and the remaining owners are
+// still notified.
+pub async fn send_owner_digests(
+    client: &GraphClient,
+    alerts: &[Alert],
+    thresholds: &Thresholds,
+) -> anyhow::Result<()> {
+    // Keyed by lower-cased address so the same owner isn't mailed twice.
+    let mut by_owner: BTreeMap<String, (String, Vec<&Alert>)> = BTreeMap::new();
+    for alert in alerts {
+        for owner in &alert.owners {
+            by_owner
+                .entry(owner.to_lowercase())
+                .or_insert_with(|| (owner.clone(), Vec::new()))
+                .1
+                .push(alert);
+        }
+    }
+
+    info!("Sending digests to {} owners", by_owner.len());
+
+    for (owner, owned) in by_owner.values() {
+        let any_expired = owned.iter().any(|a| a.has_category(Category::Expired));
+        let (subject, importance) = if any_expired {
+            (
+                "Action Required: Expired Credentials for Your Applications",
+                "high",
+            )
+        } else {
+            (
+                "Alert: Expiring Credentials for Your Applications",
+                "normal",
+            )
+        };
+
+        let intro = format!(
+            "You are listed as an owner of {} applications with credentials that need attention.",
+            owned.len()
+        );
+        let mail = Mail::for_alerts(
+            vec![owner.clone()],
+            format!("{}{}", subject, tenant_tag(owned.iter().copied())),
+            importance,
+            &intro,
+            owned,
+            thresholds,
+        );
+        let mut mail = match prepared(mail) {
+            Ok(mail) => mail,
+            Err(e) => {
+                error!("Failed to prepare the digest for {}: {}", owner, e);
+                continue;
+            }
+        };
+        if calendar::enabled() {
+            mail.attachments.extend(calendar::renewal_reminders(owned));
+        }
+
+        if let Err(e) = send_mail(client, &mail).await {
+            error!("Failed to send digest to {}: {}", owner, e);
+        }
+    }
+
+    Ok(())
+}
+
+// Send one email per application to its owners, optionally CC'ing RECIEVER_EMAIL.
+// A mail that fails to render or send is logged and the remaining applications are still
+// notified.
+pub async fn send_owner_alerts(
+    client: &GraphClient,
+    alerts: &[Alert],
+    thresholds: &Thresholds,
+    cc_central: bool,
+) -> anyhow::Result<()> {
+    let central = if cc_central {
+        Some(
+            setting("RECIEVER_EMAIL")
+                .ok_or_else(|| anyhow::anyhow!("RECIEVER_EMAIL is not set"))?,
+        )
+    } else {
+        None
+    };
+
+    for alert in alerts {
+        let (subject, importance) = if alert.has_category(Category::Expired) {
+            ("Action Required: Expired Credentials for", "high")
+        } else {
+            ("Alert: Expiring Credentials for", "normal")
+        };
+
+        let mail = Mail::for_alerts(
+            alert.owners.clone(),
+            format!(
+                "{} {}{}",
+                subject,
+                alert.name,
+                tenant_tag(std::slice::from_ref(alert))
+            ),
+            importance,
+            "You are listed as an owner of this application, which has credentials that need attention.",
+            &[alert],
+            thresholds,
+        );
+        let mut mail = match prepared(mail) {
+            Ok(mail) => mail,
+            Err(e) => {
+                error!("Failed to prepare the alert for {}: {}", alert.name, e);
+                continue;
+            }
+        };
+        if let Some(central) = &central
+            && !mail.cc.iter().any(|c| c.eq_ignore_ascii_case(central))
+        {
+            mail.cc.push(central.clone());
+        }
+        if calendar::enabled() {
+            mail.attachments
+                .extend(calendar::renewal_reminders(&[alert]));
+        }
+
+        if let Err(e) = send_mail(client, &mail).await {
+            error!("Failed to notify the owners of {}: {}", alert.name, e);
+        }
+    }
+
+    Ok(())
+}
+
+// Send the notifications for a run: owned alerts go to RECIEVER_EMAIL, or with --digest /
+// --notify-owners to the owners themselves, and ownerless alerts go to ADMIN_EMAIL.
+// A failed send is logged and counted so the other notifications still go out.
+pub async fn notify(
+    client: &GraphClient,
+    alerts: Vec<Alert>,
+    thresholds: &Thresholds,
+    options: &NotifyOptions,
+) {
+    // Alerts nobody owns go to the admin distribution list instead.
+    let (owned, ownerless): (Vec<Alert>, Vec<Alert>) =
+        alerts.into_iter().partition(|a| !a.owners.is_empty());
+
+    if !owned.is_empty() {
+        let result = if options.digest {
+            send_owner_digests(client, &owned, thresholds).await
+        } else if options.notify_owners {
+            send_owner_alerts(client, &owned, thresholds, options.cc_central).await
+        } else {
+            send_email_alert(client, &owned, thresholds).await
+        };
+        if let Err(e) = result {
+            error!("Failed to send the expiring credentials alert: {}", e);
+        }
+    }
+
+    if !ownerless.is_empty()
+        && let Err(e) = send_ownerless_report(client, &ownerless, thresholds).await
+    {
+        error!("Failed to send the ownerless applications report: {}", e);
+    }
+}
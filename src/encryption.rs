@@ -0,0 +1,102 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::{Deserialize, Serialize};
+
+use crate::config::setting;
+
+const ALGORITHM: &str = "aes-256-gcm";
+
+// The state file (json and azure-blob backends) can be encrypted at rest with
+// STATE_ENCRYPTION_KEY, a base64-encoded 32 byte key, e.g. from `openssl rand -base64 32`.
+// It is read like any other setting, so it can come from a Key Vault backed environment
+// variable. An unencrypted file is still read, and encrypted the next time it is written.
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    algorithm: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+fn cipher() -> anyhow::Result<Option<Aes256Gcm>> {
+    let Some(key) = setting("STATE_ENCRYPTION_KEY") else {
+        return Ok(None);
+    };
+    let key = STANDARD
+        .decode(key.trim())
+        .map_err(|e| anyhow::anyhow!("STATE_ENCRYPTION_KEY is not base64: {}", e))?;
+    if key.len() != 32 {
+        anyhow::bail!("STATE_ENCRYPTION_KEY must be 32 bytes, got {}", key.len());
+    }
+    Ok(Some(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))))
+}
+
+// Problems with the encryption settings, for config::validate.
+pub fn validate() -> Vec<String> {
+    let mut problems = Vec::new();
+    if let Err(e) = cipher() {
+        problems.push(e.to_string());
+    }
+    let backend = setting("STATE_BACKEND").map(|b| b.trim().to_lowercase());
+    if setting("STATE_ENCRYPTION_KEY").is_some()
+        && !matches!(backend.as_deref(), Some("json" | "azure-blob"))
+    {
+        problems.push(
+            "STATE_ENCRYPTION_KEY only applies to STATE_BACKEND=json or azure-blob".to_string(),
+        );
+    }
+    problems
+}
+
+// Encrypt the contents of a state file, if a key is configured.
+pub fn seal(plaintext: String) -> anyhow::Result<String> {
+    let Some(cipher) = cipher()? else {
+        return Ok(plaintext);
+    };
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext.as_bytes())
+        .map_err(|_| anyhow::anyhow!("Failed to encrypt the state"))?;
+    Ok(serde_json::to_string_pretty(&Envelope {
+        algorithm: ALGORITHM.to_string(),
+        nonce: STANDARD.encode(nonce),
+        ciphertext: STANDARD.encode(ciphertext),
+    })?)
+}
+
+// The contents of the state file read from `source`, decrypted if it was encrypted.
+pub fn unseal(text: String, source: &str) -> anyhow::Result<String> {
+    let Ok(envelope) = serde_json::from_str::<Envelope>(&text) else {
+        return Ok(text);
+    };
+    if envelope.algorithm != ALGORITHM {
+        anyhow::bail!(
+            "State file '{}' is encrypted with unsupported algorithm '{}'",
+            source,
+            envelope.algorithm
+        );
+    }
+    let Some(cipher) = cipher()? else {
+        anyhow::bail!(
+            "State file '{}' is encrypted; set STATE_ENCRYPTION_KEY to read it",
+            source
+        );
+    };
+    let nonce = STANDARD.decode(&envelope.nonce)?;
+    if nonce.len() != 12 {
+        anyhow::bail!("State file '{}' has a malformed nonce", source);
+    }
+    let plaintext = cipher
+        .decrypt(
+            Nonce::from_slice(&nonce),
+            STANDARD.decode(&envelope.ciphertext)?.as_slice(),
+        )
+        .map_err(|_| {
+            anyhow::anyhow!(
+                "Failed to decrypt state file '{}'; wrong STATE_ENCRYPTION_KEY?",
+                source
+            )
+        })?;
+    Ok(String::from_utf8(plaintext)?)
+}
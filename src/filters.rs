@@ -0,0 +1,64 @@
+use regex::Regex;
+
+use crate::config::list_setting;
+
+// Include/exclude filters on application display name and appId.
+// INCLUDE_APPS and EXCLUDE_APPS are comma separated patterns. A pattern is a glob
+// ("prod-*", "*-test") unless prefixed with "re:", in which case it is a regex.
+// An application is scanned if it matches any include pattern (or there are none)
+// and matches no exclude pattern. Patterns are matched case-insensitively against
+// both the display name and the appId.
+#[derive(Debug, Default)]
+pub struct AppFilter {
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+}
+
+impl AppFilter {
+    pub fn from_env() -> anyhow::Result<AppFilter> {
+        Ok(AppFilter {
+            include: patterns_from_env("INCLUDE_APPS")?,
+            exclude: patterns_from_env("EXCLUDE_APPS")?,
+        })
+    }
+
+    pub fn allows(&self, display_name: Option<&str>, app_id: Option<&str>) -> bool {
+        let matches = |patterns: &[Regex]| {
+            patterns.iter().any(|p| {
+                display_name.is_some_and(|n| p.is_match(n)) || app_id.is_some_and(|a| p.is_match(a))
+            })
+        };
+
+        (self.include.is_empty() || matches(&self.include)) && !matches(&self.exclude)
+    }
+}
+
+fn patterns_from_env(var: &str) -> anyhow::Result<Vec<Regex>> {
+    list_setting(var)
+        .unwrap_or_default()
+        .iter()
+        .map(|p| {
+            let pattern = match p.strip_prefix("re:") {
+                Some(regex) => format!("(?i){}", regex),
+                None => glob_to_regex(p),
+            };
+            Regex::new(&pattern)
+                .map_err(|e| anyhow::anyhow!("Invalid {} pattern '{}': {}", var, p, e))
+        })
+        .collect()
+}
+
+// Translate a glob ("*" any run of characters, "?" any single character) to an anchored,
+// case-insensitive regex.
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::from("(?i)^");
+    for c in glob.chars() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    regex
+}
@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+use log::{error, info};
+
+use crate::alerts::{Alert, Finding, Severity, format_expiry};
+use crate::config::{dry_run, http_client, setting};
+use crate::stats::{NOTIFICATIONS_FAILED, NOTIFICATIONS_SENT, count};
+use crate::templates::portal_url;
+
+const DEFAULT_API_URL: &str = "https://api.github.com";
+// Every issue this tool opens carries this label, plus a severity:<level> label.
+const LABEL: &str = "secret-manager";
+
+// GitHub issues are enabled by GITHUB_REPOSITORY ("owner/repo") with a GITHUB_TOKEN that
+// can write issues. One issue is opened per finding, identified by a marker comment in its
+// body. When a credential no longer shows up in a run (it was rotated or removed), its
+// issue is closed; set GITHUB_CLOSE_STALE=false when runs only scan part of the estate,
+// e.g. with --application.
+// GITHUB_API_URL points at GitHub Enterprise Server and GITHUB_MIN_SEVERITY leaves out
+// less urgent credentials.
+pub fn enabled() -> bool {
+    setting("GITHUB_REPOSITORY").is_some()
+}
+
+fn finding_key(alert: &Alert, finding: &Finding) -> String {
+    format!(
+        "{}:{}:{}",
+        alert.tenant,
+        alert.app_id.as_deref().unwrap_or(&alert.name),
+        finding.key_id.as_deref().unwrap_or(&finding.credential)
+    )
+}
+
+fn marker(key: &str) -> String {
+    format!("<!-- secret-manager:{} -->", key)
+}
+
+// The finding key in an issue body's marker comment.
+fn key_from_body(body: &str) -> Option<&str> {
+    let start = body.find("<!-- secret-manager:")? + "<!-- secret-manager:".len();
+    let end = body[start..].find(" -->")?;
+    Some(&body[start..start + end])
+}
+
+fn severity_label(severity: Severity) -> String {
+    format!("severity:{}", severity.label().to_lowercase())
+}
+
+// An open issue this tool created.
+struct Issue {
+    number: u64,
+    labels: Vec<String>,
+}
+
+struct GitHub {
+    http: reqwest::Client,
+    repo_url: String,
+}
+
+impl GitHub {
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        self.http
+            .request(method, format!("{}{}", self.repo_url, path))
+            .bearer_auth(setting("GITHUB_TOKEN").unwrap_or_default())
+            .header(reqwest::header::ACCEPT, "application/vnd.github+json")
+            .header(reqwest::header::USER_AGENT, "secret-manager")
+    }
+
+    // Open issues with the tool's label, keyed by the finding in their marker.
+    async fn open_issues(&self) -> anyhow::Result<HashMap<String, Issue>> {
+        let mut issues = HashMap::new();
+        for page in 1.. {
+            let batch: Vec<serde_json::Value> = self
+                .request(reqwest::Method::GET, "/issues")
+                .query(&[
+                    ("labels", LABEL.to_string()),
+                    ("state", "open".to_string()),
+                    ("per_page", "100".to_string()),
+                    ("page", page.to_string()),
+                ])
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+
+            for issue in &batch {
+                let Some(key) = issue["body"].as_str().and_then(key_from_body) else {
+                    continue;
+                };
+                issues.insert(
+                    key.to_string(),
+                    Issue {
+                        number: issue["number"].as_u64().unwrap_or_default(),
+                        labels: issue["labels"]
+                            .as_array()
+                            .into_iter()
+                            .flatten()
+                            .filter_map(|l| l["name"].as_str().map(|n| n.to_string()))
+                            .collect(),
+                    },
+                );
+            }
+
+            if batch.len() < 100 {
+                break;
+            }
+        }
+        Ok(issues)
+    }
+
+    async fn open_issue(&self, alert: &Alert, finding: &Finding) -> anyhow::Result<u64> {
+        let key = finding_key(alert, finding);
+        let mut body = format!(
+            "{}\n\n| | |\n|---|---|\n| Tenant | {} |\n| Application | {} |\n| Credential | {} |\n| Expiry | {} |\n| Severity | {} |\n| Owners | {} |\n",
+            marker(&key),
+            alert.tenant,
+            alert.name,
+            finding.credential,
+            format_expiry(finding.end_date_time, Utc::now()),
+            finding.severity.label(),
+            if alert.owners.is_empty() {
+                "None".to_string()
+            } else {
+                alert.owners.join(", ")
+            }
+        );
+        if let Some(app_id) = &alert.app_id {
+            body.push_str(&format!(
+                "| App ID | [{}]({}) |\n",
+                app_id,
+                portal_url(app_id)
+            ));
+        }
+        body.push_str("\nThis issue is closed automatically once the credential is rotated.\n");
+
+        let title = format!(
+            "{} of {} expires {}",
+            finding.credential,
+            alert.name,
+            finding.end_date_time.format("%Y-%m-%d")
+        );
+
+        let response: serde_json::Value = self
+            .request(reqwest::Method::POST, "/issues")
+            .json(&serde_json::json!({
+                "title": title,
+                "body": body,
+                "labels": [LABEL, severity_label(finding.severity)]
+            }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(response["number"].as_u64().unwrap_or_default())
+    }
+
+    // Swap the issue's severity label as the expiry gets closer.
+    async fn relabel(&self, issue: &Issue, severity: Severity) -> anyhow::Result<()> {
+        let mut labels: Vec<String> = issue
+            .labels
+            .iter()
+            .filter(|l| !l.starts_with("severity:"))
+            .cloned()
+            .collect();
+        labels.push(severity_label(severity));
+
+        self.request(reqwest::Method::PATCH, &format!("/issues/{}", issue.number))
+            .json(&serde_json::json!({ "labels": labels }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn close(&self, issue: &Issue) -> anyhow::Result<()> {
+        self.request(
+            reqwest::Method::POST,
+            &format!("/issues/{}/comments", issue.number),
+        )
+        .json(&serde_json::json!({
+            "body": "The credential no longer shows up as expiring, so it has been rotated \
+                     or removed. Closing."
+        }))
+        .send()
+        .await?
+        .error_for_status()?;
+
+        self.request(reqwest::Method::PATCH, &format!("/issues/{}", issue.number))
+            .json(&serde_json::json!({ "state": "closed", "state_reason": "completed" }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+// Open an issue for every new finding at or above GITHUB_MIN_SEVERITY, keep open issues'
+// severity labels current and close the issues of credentials that have been rotated.
+pub async fn sync_issues(alerts: &[Alert]) -> anyhow::Result<()> {
+    let repository = setting("GITHUB_REPOSITORY")
+        .ok_or_else(|| anyhow::anyhow!("GITHUB_REPOSITORY is not set"))?;
+    let github = GitHub {
+        http: http_client(),
+        repo_url: format!(
+            "{}/repos/{}",
+            setting("GITHUB_API_URL")
+                .unwrap_or_else(|| DEFAULT_API_URL.to_string())
+                .trim_end_matches('/'),
+            repository.trim()
+        ),
+    };
+    let min = setting("GITHUB_MIN_SEVERITY")
+        .and_then(|v| Severity::parse(&v).ok())
+        .unwrap_or(Severity::Low);
+
+    if dry_run() {
+        for alert in alerts {
+            for finding in alert.findings.iter().filter(|f| f.severity >= min) {
+                println!(
+                    "[dry-run] Would open or update a GitHub issue in {} for {}",
+                    repository,
+                    finding_key(alert, finding)
+                );
+            }
+        }
+        return Ok(());
+    }
+
+    let mut open = github.open_issues().await?;
+    let mut failures = 0;
+
+    for alert in alerts {
+        for finding in &alert.findings {
+            let key = finding_key(alert, finding);
+            // Still expiring, so its issue (if any) stays open.
+            let existing = open.remove(&key);
+            if finding.severity < min {
+                continue;
+            }
+
+            let result = match &existing {
+                Some(issue) if issue.labels.contains(&severity_label(finding.severity)) => {
+                    continue;
+                }
+                Some(issue) => github.relabel(issue, finding.severity).await.map(|()| {
+                    info!("Updated the severity of GitHub issue #{}", issue.number);
+                }),
+                None => github.open_issue(alert, finding).await.map(|number| {
+                    info!("Opened GitHub issue #{} for {}", number, key);
+                }),
+            };
+            match result {
+                Ok(()) => count(&NOTIFICATIONS_SENT, 1),
+                Err(e) => {
+                    count(&NOTIFICATIONS_FAILED, 1);
+                    error!("Failed to sync the GitHub issue for {}: {}", key, e);
+                    failures += 1;
+                }
+            }
+        }
+    }
+
+    // What's left open no longer has a finding.
+    if setting("GITHUB_CLOSE_STALE").is_none_or(|v| !v.eq_ignore_ascii_case("false")) {
+        for (key, issue) in &open {
+            match github.close(issue).await {
+                Ok(()) => info!("Closed GitHub issue #{} for rotated {}", issue.number, key),
+                Err(e) => {
+                    error!("Failed to close GitHub issue #{}: {}", issue.number, e);
+                    failures += 1;
+                }
+            }
+        }
+    }
+
+    if failures > 0 {
+        anyhow::bail!("{} GitHub issue updates failed", failures);
+    }
+    Ok(())
+}
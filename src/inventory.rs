@@ -0,0 +1,75 @@
+use graph_rs_sdk::*;
+
+use crate::models::{App, FederatedIdentityCredential};
+use crate::retry::paging_with_retry;
+
+// Print an inventory of every application's credentials: password and key credential counts
+// alongside its federated identity credentials (issuer, subject, audiences).
+// Apps with only federated credentials have moved to secretless auth; the rest still need
+// rotation alerts.
+pub async fn print_credential_inventory(client: &GraphClient, apps: &[App]) -> anyhow::Result<()> {
+    let mut secretless = 0;
+    let mut mixed = 0;
+    let mut secrets_only = 0;
+
+    for app in apps {
+        let pages = paging_with_retry(client, || {
+            client
+                .application(&app.id)
+                .list_federated_identity_credentials()
+                .select(&["name", "issuer", "subject", "audiences"])
+                .paging()
+                .json::<serde_json::Value>()
+        })
+        .await?;
+
+        let federated: Vec<FederatedIdentityCredential> = pages
+            .into_iter()
+            .flat_map(|page| page.parse_items("federated identity credential"))
+            .collect();
+
+        let has_secrets = !app.passwordCredentials.is_empty() || !app.keyCredentials.is_empty();
+        let status = match (has_secrets, federated.is_empty()) {
+            (false, false) => {
+                secretless += 1;
+                "secretless"
+            }
+            (true, false) => {
+                mixed += 1;
+                "mixed"
+            }
+            (true, true) => {
+                secrets_only += 1;
+                "secrets only"
+            }
+            (false, true) => "no credentials",
+        };
+
+        println!(
+            "Application: {} (App ID: {})",
+            app.displayName.as_deref().unwrap_or("No Name"),
+            app.appId.as_deref().unwrap_or("-")
+        );
+        println!("  Status: {}", status);
+        println!("  Password credentials: {}", app.passwordCredentials.len());
+        println!("  Key credentials: {}", app.keyCredentials.len());
+        println!("  Federated identity credentials: {}", federated.len());
+        for credential in &federated {
+            println!(
+                "    - {}: issuer={}, subject={}, audiences={}",
+                credential.name.as_deref().unwrap_or("No Name"),
+                credential.issuer,
+                credential.subject,
+                credential.audiences.join(", ")
+            );
+        }
+    }
+
+    println!();
+    println!(
+        "Secretless: {}, Mixed: {}, Secrets only: {}",
+        secretless, mixed, secrets_only
+    );
+
+    Ok(())
+}
@@ -0,0 +1,273 @@
+use chrono::Utc;
+use log::{error, info};
+
+use crate::alerts::{Alert, format_expiry};
+use crate::config::{dry_run, http_client, setting};
+use crate::notifiers::alerts_at_or_above;
+use crate::stats::{NOTIFICATIONS_FAILED, NOTIFICATIONS_SENT, count};
+use crate::templates::portal_url;
+
+// Jira issues are enabled by JIRA_URL and JIRA_PROJECT. One issue is opened per
+// application, labelled with its appId and the keyIds of its findings so later runs find
+// it again instead of opening duplicates.
+//
+//     JIRA_URL             e.g. https://example.atlassian.net
+//     JIRA_PROJECT         project key issues are created in
+//     JIRA_ISSUE_TYPE      defaults to Task
+//     JIRA_USER            with JIRA_API_TOKEN for basic auth (Jira Cloud); without it
+//                          JIRA_API_TOKEN is sent as a bearer token (Data Center PATs)
+//     JIRA_MIN_SEVERITY    leave out less urgent applications
+pub fn enabled() -> bool {
+    setting("JIRA_URL").is_some() && setting("JIRA_PROJECT").is_some()
+}
+
+struct Jira {
+    http: reqwest::Client,
+    base_url: String,
+    project: String,
+    issue_type: String,
+}
+
+// Labels can't contain spaces.
+fn label(prefix: &str, value: &str) -> String {
+    format!(
+        "{}-{}",
+        prefix,
+        value.split_whitespace().collect::<Vec<&str>>().join("-")
+    )
+}
+
+fn app_label(alert: &Alert) -> String {
+    label(
+        "secret-manager-app",
+        alert.app_id.as_deref().unwrap_or(&alert.name),
+    )
+}
+
+// A label per credential, so an issue is only updated when a new credential shows up.
+fn key_labels(alert: &Alert) -> Vec<String> {
+    alert
+        .findings
+        .iter()
+        .map(|f| {
+            label(
+                "secret-manager-key",
+                f.key_id.as_deref().unwrap_or(&f.credential),
+            )
+        })
+        .collect()
+}
+
+fn description(alert: &Alert) -> String {
+    let now = Utc::now();
+    let mut text = format!(
+        "Tenant: {}\nApplication: {}\nOwners: {}\n",
+        alert.tenant,
+        alert.name,
+        if alert.owners.is_empty() {
+            "None".to_string()
+        } else {
+            alert.owners.join(", ")
+        }
+    );
+    if let Some(app_id) = &alert.app_id {
+        text.push_str(&format!(
+            "App ID: {}\nPortal: {}\n",
+            app_id,
+            portal_url(app_id)
+        ));
+    }
+    text.push('\n');
+    for finding in &alert.findings {
+        text.push_str(&format!(
+            "* [{}] {} {}\n",
+            finding.severity.label(),
+            finding.credential,
+            format_expiry(finding.end_date_time, now)
+        ));
+    }
+    text
+}
+
+impl Jira {
+    fn from_settings() -> anyhow::Result<Jira> {
+        let base_url = setting("JIRA_URL").ok_or_else(|| anyhow::anyhow!("JIRA_URL is not set"))?;
+        let project =
+            setting("JIRA_PROJECT").ok_or_else(|| anyhow::anyhow!("JIRA_PROJECT is not set"))?;
+        Ok(Jira {
+            http: http_client(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            project,
+            issue_type: setting("JIRA_ISSUE_TYPE").unwrap_or_else(|| "Task".to_string()),
+        })
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let request = self
+            .http
+            .request(method, format!("{}{}", self.base_url, path));
+        let token = setting("JIRA_API_TOKEN").unwrap_or_default();
+        match setting("JIRA_USER") {
+            Some(user) => request.basic_auth(user, Some(token)),
+            None => request.bearer_auth(token),
+        }
+    }
+
+    // The open issue for an application, with its labels, if there is one.
+    async fn find_open_issue(
+        &self,
+        alert: &Alert,
+    ) -> anyhow::Result<Option<(String, Vec<String>)>> {
+        let jql = format!(
+            "project = \"{}\" AND labels = \"{}\" AND statusCategory != Done ORDER BY created DESC",
+            self.project,
+            app_label(alert)
+        );
+        // Jira Cloud replaced /search with /search/jql; Data Center only has the former.
+        let path = if self.base_url.ends_with(".atlassian.net") {
+            "/rest/api/2/search/jql"
+        } else {
+            "/rest/api/2/search"
+        };
+        let response: serde_json::Value = self
+            .request(reqwest::Method::POST, path)
+            .json(&serde_json::json!({ "jql": jql, "fields": ["labels"], "maxResults": 1 }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(response["issues"].get(0).map(|issue| {
+            (
+                issue["key"].as_str().unwrap_or_default().to_string(),
+                issue["fields"]["labels"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|l| l.as_str().map(|l| l.to_string()))
+                    .collect(),
+            )
+        }))
+    }
+
+    async fn create_issue(&self, alert: &Alert) -> anyhow::Result<String> {
+        let mut labels = vec!["secret-manager".to_string(), app_label(alert)];
+        labels.extend(key_labels(alert));
+
+        let response: serde_json::Value = self
+            .request(reqwest::Method::POST, "/rest/api/2/issue")
+            .json(&serde_json::json!({
+                "fields": {
+                    "project": { "key": self.project },
+                    "issuetype": { "name": self.issue_type },
+                    "summary": format!(
+                        "[{}] Credentials expiring for {} ({})",
+                        alert.severity().label(),
+                        alert.name,
+                        alert.tenant
+                    ),
+                    "description": description(alert),
+                    "labels": labels
+                }
+            }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(response["key"].as_str().unwrap_or_default().to_string())
+    }
+
+    // Add the new credentials' labels to an existing issue and comment with the findings.
+    async fn update_issue(
+        &self,
+        key: &str,
+        alert: &Alert,
+        new_labels: &[String],
+    ) -> anyhow::Result<()> {
+        self.request(reqwest::Method::PUT, &format!("/rest/api/2/issue/{}", key))
+            .json(&serde_json::json!({
+                "update": {
+                    "labels": new_labels
+                        .iter()
+                        .map(|l| serde_json::json!({ "add": l }))
+                        .collect::<Vec<serde_json::Value>>()
+                }
+            }))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        self.request(
+            reqwest::Method::POST,
+            &format!("/rest/api/2/issue/{}/comment", key),
+        )
+        .json(&serde_json::json!({
+            "body": format!("More credentials need attention:\n\n{}", description(alert))
+        }))
+        .send()
+        .await?
+        .error_for_status()?;
+
+        Ok(())
+    }
+
+    // Open an issue for the application, or update its open issue when it has credentials
+    // the issue doesn't cover yet.
+    async fn sync(&self, alert: &Alert) -> anyhow::Result<()> {
+        match self.find_open_issue(alert).await? {
+            None => {
+                let key = self.create_issue(alert).await?;
+                info!("Opened Jira issue {} for {}", key, alert.name);
+            }
+            Some((key, labels)) => {
+                let new_labels: Vec<String> = key_labels(alert)
+                    .into_iter()
+                    .filter(|l| !labels.contains(l))
+                    .collect();
+                if new_labels.is_empty() {
+                    info!("Jira issue {} already covers {}", key, alert.name);
+                    return Ok(());
+                }
+                self.update_issue(&key, alert, &new_labels).await?;
+                info!("Updated Jira issue {} for {}", key, alert.name);
+            }
+        }
+        Ok(())
+    }
+}
+
+// Open or update a Jira issue for every application at or above JIRA_MIN_SEVERITY.
+pub async fn sync_issues(alerts: &[Alert]) -> anyhow::Result<()> {
+    let jira = Jira::from_settings()?;
+    let alerts = alerts_at_or_above(alerts, "JIRA_MIN_SEVERITY");
+
+    if dry_run() {
+        for alert in &alerts {
+            println!(
+                "[dry-run] Would open or update a Jira issue in {} for {} ({})",
+                jira.project, alert.name, alert.tenant
+            );
+        }
+        return Ok(());
+    }
+
+    let mut failures = 0;
+    for alert in &alerts {
+        match jira.sync(alert).await {
+            Ok(()) => count(&NOTIFICATIONS_SENT, 1),
+            Err(e) => {
+                count(&NOTIFICATIONS_FAILED, 1);
+                error!("Failed to sync the Jira issue for {}: {}", alert.name, e);
+                failures += 1;
+            }
+        }
+    }
+
+    if failures > 0 {
+        anyhow::bail!("{} of {} Jira issues failed", failures, alerts.len());
+    }
+    Ok(())
+}
@@ -0,0 +1,257 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::encryption;
+use crate::state::StateStore;
+
+// Bumped whenever the layout of the file changes, so an older build refuses a newer file
+// instead of overwriting it.
+const SCHEMA_VERSION: u32 = 1;
+
+// Every document of the state in one JSON document. Also the layout of the Azure blob
+// backend.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct StateFile {
+    version: u32,
+    // Namespace, then key.
+    namespaces: BTreeMap<String, BTreeMap<String, serde_json::Value>>,
+}
+
+impl Default for StateFile {
+    fn default() -> StateFile {
+        StateFile {
+            version: SCHEMA_VERSION,
+            namespaces: BTreeMap::new(),
+        }
+    }
+}
+
+impl StateFile {
+    // Parse the contents of `source`, decrypting them if needed and refusing a schema version
+    // newer than this build's.
+    pub fn parse(text: String, source: &str) -> anyhow::Result<StateFile> {
+        let text = encryption::unseal(text, source)?;
+        let file: StateFile = serde_json::from_str(&text)
+            .map_err(|e| anyhow::anyhow!("Failed to parse state file '{}': {}", source, e))?;
+        if file.version > SCHEMA_VERSION {
+            anyhow::bail!(
+                "State file '{}' has schema version {}; this build supports up to {}",
+                source,
+                file.version,
+                SCHEMA_VERSION
+            );
+        }
+        Ok(file)
+    }
+
+    // The file's contents, encrypted if STATE_ENCRYPTION_KEY is set.
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        encryption::seal(self.to_plain_json()?)
+    }
+
+    pub fn get(&self, namespace: &str, key: &str) -> Option<serde_json::Value> {
+        self.namespaces
+            .get(namespace)
+            .and_then(|documents| documents.get(key))
+            .cloned()
+    }
+
+    pub fn insert(&mut self, namespace: &str, key: &str, value: &serde_json::Value) {
+        self.version = SCHEMA_VERSION;
+        self.namespaces
+            .entry(namespace.to_string())
+            .or_default()
+            .insert(key.to_string(), value.clone());
+    }
+
+    // Whether there was anything to remove.
+    pub fn remove(&mut self, namespace: &str, key: &str) -> bool {
+        self.namespaces
+            .get_mut(namespace)
+            .and_then(|documents| documents.remove(key))
+            .is_some()
+    }
+
+    // How many documents there are across every namespace.
+    pub fn len(&self) -> usize {
+        self.namespaces.values().map(BTreeMap::len).sum()
+    }
+
+    pub fn namespaces(&self) -> Vec<String> {
+        self.namespaces.keys().cloned().collect()
+    }
+
+    // The file's contents unencrypted, for `state export`.
+    pub fn to_plain_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn list(&self, namespace: &str) -> Vec<(String, serde_json::Value)> {
+        self.namespaces
+            .get(namespace)
+            .map(|documents| {
+                documents
+                    .iter()
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+// STATE_BACKEND=json: everything in one JSON file (STATE_PATH, default
+// secret-manager-state.json), for environments where SQLite isn't an option. The file is
+// read once and rewritten on every change by writing a temporary file next to it and
+// renaming it into place, so a crash never leaves it half-written. Since every change
+// rewrites (and with STATE_ENCRYPTION_KEY, re-encrypts) the whole file, a run that records
+// N findings costs O(N²): the backend is meant for small tenants, up to a few thousand
+// credentials. Use sqlite, or one of the server backends, beyond that.
+pub struct JsonStore {
+    path: PathBuf,
+    contents: Mutex<StateFile>,
+}
+
+// Past this many documents, rewriting the file on every change noticeably slows runs down.
+const LARGE_STATE_DOCUMENTS: usize = 5000;
+
+impl JsonStore {
+    pub fn open(path: &str) -> anyhow::Result<JsonStore> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(text) => StateFile::parse(text, path)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => StateFile::default(),
+            Err(e) => anyhow::bail!("Failed to read state file '{}': {}", path, e),
+        };
+        if contents.len() > LARGE_STATE_DOCUMENTS {
+            warn!(
+                "State file '{}' holds {} documents and is rewritten in full on every change; \
+                 consider STATE_BACKEND=sqlite",
+                path,
+                contents.len()
+            );
+        }
+        Ok(JsonStore {
+            path: PathBuf::from(path),
+            contents: Mutex::new(contents),
+        })
+    }
+
+    fn contents(&self) -> std::sync::MutexGuard<'_, StateFile> {
+        self.contents.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self, contents: &StateFile) -> anyhow::Result<()> {
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        let temporary = Path::new(&temporary);
+        std::fs::write(temporary, contents.to_json()?)?;
+        std::fs::rename(temporary, &self.path)?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl StateStore for JsonStore {
+    async fn get(&self, namespace: &str, key: &str) -> anyhow::Result<Option<serde_json::Value>> {
+        Ok(self.contents().get(namespace, key))
+    }
+
+    async fn put(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        let mut contents = self.contents();
+        contents.insert(namespace, key, value);
+        self.write(&contents)
+    }
+
+    async fn delete(&self, namespace: &str, key: &str) -> anyhow::Result<()> {
+        let mut contents = self.contents();
+        if contents.remove(namespace, key) {
+            self.write(&contents)?;
+        }
+        Ok(())
+    }
+
+    async fn list(&self, namespace: &str) -> anyhow::Result<Vec<(String, serde_json::Value)>> {
+        Ok(self.contents().list(namespace))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::Lock;
+
+    // A state file path of the test's own in the temporary directory.
+    fn state_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!(
+            "secret-manager-{}-{}.json",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        path.to_string_lossy().into_owned()
+    }
+
+    #[tokio::test]
+    async fn documents_survive_reopening_the_file() {
+        let path = state_path("reopen");
+        let store = JsonStore::open(&path).unwrap();
+        store
+            .put("notifications", "b", &serde_json::json!({ "n": 2 }))
+            .await
+            .unwrap();
+        store
+            .put("notifications", "a", &serde_json::json!({ "n": 1 }))
+            .await
+            .unwrap();
+        store
+            .put("scans", "a", &serde_json::json!(true))
+            .await
+            .unwrap();
+        store.delete("scans", "a").await.unwrap();
+
+        let store = JsonStore::open(&path).unwrap();
+        let keys: Vec<String> = store
+            .list("notifications")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(keys, ["a", "b"]);
+        assert_eq!(
+            store.get("notifications", "b").await.unwrap(),
+            Some(serde_json::json!({ "n": 2 }))
+        );
+        assert_eq!(store.get("scans", "a").await.unwrap(), None);
+        assert_eq!(store.contents().len(), 2);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_lock_is_only_taken_by_one_holder_until_it_expires() {
+        let path = state_path("lock");
+        let store = JsonStore::open(&path).unwrap();
+        let lock = |holder: &str, minutes: i64| Lock {
+            holder: holder.to_string(),
+            expires: chrono::Utc::now() + chrono::Duration::minutes(minutes),
+        };
+
+        assert!(store.try_lock("run", &lock("one", 5)).await.unwrap());
+        assert!(!store.try_lock("run", &lock("two", 5)).await.unwrap());
+        store.unlock("run", "two").await.unwrap();
+        assert!(!store.try_lock("run", &lock("two", 5)).await.unwrap());
+        store.unlock("run", "one").await.unwrap();
+        assert!(store.try_lock("run", &lock("two", -1)).await.unwrap());
+        assert!(store.try_lock("run", &lock("one", 5)).await.unwrap());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,164 @@
+use chrono::{DateTime, Utc};
+use log::info;
+
+use crate::alerts::{Alert, Finding, Thresholds, format_expiry};
+use crate::auth::client_credentials_token;
+use crate::config::{Tenant, http_client};
+use crate::models::KeyVaultItems;
+
+const KEY_VAULT_API_VERSION: &str = "7.4";
+
+// Follow nextLink until every item of a collection ("secrets", "keys", "certificates") is listed.
+async fn list_collection(
+    http: &reqwest::Client,
+    token: &str,
+    vault_url: &str,
+    collection: &str,
+) -> anyhow::Result<Vec<crate::models::KeyVaultItem>> {
+    let mut items = Vec::new();
+    let mut next = Some(format!(
+        "{}/{}?api-version={}",
+        vault_url, collection, KEY_VAULT_API_VERSION
+    ));
+
+    while let Some(url) = next {
+        let page: KeyVaultItems = http
+            .get(&url)
+            .bearer_auth(token)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        items.extend(page.value);
+        next = page.nextLink.filter(|link| !link.is_empty());
+    }
+
+    Ok(items)
+}
+
+// Scan the tenant's Key Vaults (KEY_VAULT_NAMES, comma separated) for secrets, keys and
+// certificates that have expired or are expiring within the threshold tiers.
+// Returns one alert per vault; owners are taken from an "owner" tag on the items, if present.
+pub async fn check_key_vaults(
+    tenant: &Tenant,
+    thresholds: &Thresholds,
+) -> anyhow::Result<Vec<Alert>> {
+    if tenant.key_vault_names.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let http = http_client();
+    let scope = format!("https://{}/.default", tenant.cloud.key_vault_suffix());
+    let token = client_credentials_token(&http, tenant, &scope).await?;
+
+    let now = Utc::now();
+    let threshold = thresholds.cutoff(now);
+
+    let mut alerts: Vec<Alert> = Vec::new();
+
+    for vault in &tenant.key_vault_names {
+        let vault_url = format!("https://{}.{}", vault, tenant.cloud.key_vault_suffix());
+        let mut findings: Vec<Finding> = Vec::new();
+        let mut owners: Vec<String> = Vec::new();
+
+        for (collection, label) in [
+            ("secrets", "Secret"),
+            ("keys", "Key"),
+            ("certificates", "Certificate"),
+        ] {
+            let items = match list_collection(&http, &token, &vault_url, collection).await {
+                Ok(i) => i,
+                Err(e) => {
+                    info!(
+                        "Failed to list {} in Key Vault '{}': {}. Skipping.",
+                        collection, vault, e
+                    );
+                    continue;
+                }
+            };
+
+            for item in items {
+                if item.attributes.enabled == Some(false) {
+                    continue;
+                }
+
+                let Some(expiry) = item
+                    .attributes
+                    .exp
+                    .and_then(|exp| DateTime::<Utc>::from_timestamp(exp, 0))
+                else {
+                    continue;
+                };
+
+                if expiry >= threshold {
+                    continue;
+                }
+
+                // The last path segment of the id is the item name.
+                let name = item.id.rsplit('/').next().unwrap_or(&item.id);
+
+                info!(
+                    "Key Vault '{}' has a {} expiring on {} (Name: {})",
+                    vault,
+                    label.to_lowercase(),
+                    expiry,
+                    name
+                );
+
+                findings.push(
+                    Finding::new(
+                        expiry,
+                        now,
+                        thresholds,
+                        &format!("Key Vault {}", label.to_lowercase()),
+                        match &item.x5t {
+                            Some(thumbprint) => format!(
+                                "{}: {}, Thumbprint: {}, Expiry: {}",
+                                label,
+                                name,
+                                thumbprint,
+                                format_expiry(expiry, now)
+                            ),
+                            None => format!(
+                                "{}: {}, Expiry: {}",
+                                label,
+                                name,
+                                format_expiry(expiry, now)
+                            ),
+                        },
+                    )
+                    .with_key(Some(item.id.clone()), None),
+                );
+
+                if let Some(owner) = item
+                    .tags
+                    .iter()
+                    .find(|(k, _)| k.eq_ignore_ascii_case("owner"))
+                    .map(|(_, v)| v)
+                {
+                    for o in owner.split([',', ';']).map(|s| s.trim()) {
+                        if !o.is_empty() && !owners.iter().any(|x| x == o) {
+                            owners.push(o.to_string());
+                        }
+                    }
+                }
+            }
+        }
+
+        if !findings.is_empty() {
+            findings.sort_by_key(|f| f.end_date_time);
+            alerts.push(Alert {
+                tenant: String::new(),
+                name: format!("{} (Key Vault)", vault),
+                app_id: None,
+                owners,
+                escalate_to: Vec::new(),
+                findings,
+            });
+        }
+    }
+
+    Ok(alerts)
+}
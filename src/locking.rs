@@ -0,0 +1,75 @@
+use std::sync::OnceLock;
+
+use chrono::{Duration, Utc};
+use log::{info, warn};
+
+use crate::config::{dry_run, setting};
+use crate::state::{self, Lock};
+
+// Held while notifications are sent.
+pub const NOTIFY: &str = "notify";
+
+// When several instances share a state store, e.g. two daemons deployed for redundancy, only
+// the one holding the notify lock sends a run's notifications. The others scan as usual and
+// skip sending; by the time they run again, the notification records show what was sent.
+// Locks expire after LOCK_TTL_MINUTES (default 30), so a crashed instance can't hold one
+// forever. Without a state store there is nothing to coordinate through, and no locking.
+fn ttl() -> Duration {
+    Duration::minutes(
+        setting("LOCK_TTL_MINUTES")
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(30),
+    )
+}
+
+// This instance, as "host:pid".
+fn holder() -> &'static str {
+    static HOLDER: OnceLock<String> = OnceLock::new();
+    HOLDER.get_or_init(|| {
+        let host = std::env::var("HOSTNAME")
+            .or_else(|_| std::env::var("COMPUTERNAME"))
+            .unwrap_or_else(|_| "unknown".to_string());
+        format!("{}:{}", host, std::process::id())
+    })
+}
+
+// Take the lock `name`, returning whether this instance should go ahead. If the store fails
+// the answer is yes: a duplicate notification is better than none.
+pub async fn try_acquire(name: &str) -> bool {
+    let Some(store) = state::store() else {
+        return true;
+    };
+    if dry_run() {
+        return true;
+    }
+    let lock = Lock {
+        holder: holder().to_string(),
+        expires: Utc::now() + ttl(),
+    };
+    match store.try_lock(name, &lock).await {
+        Ok(true) => true,
+        Ok(false) => {
+            info!("Another instance holds the {} lock", name);
+            false
+        }
+        Err(e) => {
+            warn!(
+                "Failed to take the {} lock, going ahead without it: {}",
+                name, e
+            );
+            true
+        }
+    }
+}
+
+pub async fn release(name: &str) {
+    let Some(store) = state::store() else {
+        return;
+    };
+    if dry_run() {
+        return;
+    }
+    if let Err(e) = store.unlock(name, holder()).await {
+        warn!("Failed to release the {} lock: {}", name, e);
+    }
+}
@@ -0,0 +1,99 @@
+use chrono::Utc;
+use log::info;
+
+use crate::alerts::Alert;
+use crate::auth::client_credentials_token;
+use crate::config::{Tenant, dry_run, http_client, setting};
+use crate::report::finding_records;
+
+// Every run's findings can be sent to a Log Analytics workspace as custom log records, through
+// the Logs Ingestion API, to build Azure Monitor workbooks and alert rules on:
+//
+//     LOG_ANALYTICS_ENDPOINT  the data collection endpoint, e.g.
+//                             https://my-dce-a1b2.westeurope-1.ingest.monitor.azure.com
+//     LOG_ANALYTICS_RULE_ID   the immutable ID of the data collection rule (dcr-...)
+//     LOG_ANALYTICS_STREAM    the rule's input stream (default Custom-SecretManagerFindings)
+//
+// Records have the fields of --output json plus TimeGenerated. The first tenant's app
+// registration needs the Monitoring Metrics Publisher role on the data collection rule.
+const DEFAULT_STREAM: &str = "Custom-SecretManagerFindings";
+const API_VERSION: &str = "2023-01-01";
+// Requests are limited to 1 MB; a finding is well under 2 KB.
+const BATCH_SIZE: usize = 500;
+
+pub fn enabled() -> bool {
+    setting("LOG_ANALYTICS_ENDPOINT").is_some()
+}
+
+// Check the Log Analytics settings, returning a description of each problem.
+pub fn validate() -> Vec<String> {
+    let mut problems = Vec::new();
+    if setting("LOG_ANALYTICS_ENDPOINT").is_some() != setting("LOG_ANALYTICS_RULE_ID").is_some() {
+        problems.push(
+            "LOG_ANALYTICS_ENDPOINT and LOG_ANALYTICS_RULE_ID must be set together to send \
+             findings to Log Analytics"
+                .to_string(),
+        );
+    }
+    if let Some(url) = setting("LOG_ANALYTICS_ENDPOINT")
+        && let Err(e) = url::Url::parse(&url)
+    {
+        problems.push(format!("LOG_ANALYTICS_ENDPOINT is not a valid URL: {}", e));
+    }
+    problems
+}
+
+// Send every finding of the run to the data collection rule, authenticating as `tenant`'s
+// app registration. Unlike the notifiers this isn't deduplicated: each run is a complete
+// picture, so queries can chart the findings over time.
+pub async fn export(alerts: &[Alert], tenant: &Tenant) -> anyhow::Result<()> {
+    let endpoint = setting("LOG_ANALYTICS_ENDPOINT")
+        .ok_or_else(|| anyhow::anyhow!("LOG_ANALYTICS_ENDPOINT is not set"))?;
+    let rule_id = setting("LOG_ANALYTICS_RULE_ID")
+        .ok_or_else(|| anyhow::anyhow!("LOG_ANALYTICS_RULE_ID is not set"))?;
+    let stream = setting("LOG_ANALYTICS_STREAM").unwrap_or_else(|| DEFAULT_STREAM.to_string());
+
+    let now = Utc::now();
+    let mut records = Vec::new();
+    for record in finding_records(alerts, now) {
+        let mut value = serde_json::to_value(&record)?;
+        value["TimeGenerated"] = serde_json::json!(now);
+        records.push(value);
+    }
+    if records.is_empty() {
+        return Ok(());
+    }
+
+    if dry_run() {
+        println!(
+            "[dry-run] Would send {} findings to Log Analytics stream {}",
+            records.len(),
+            stream
+        );
+        return Ok(());
+    }
+
+    let http = http_client();
+    let token = client_credentials_token(&http, tenant, tenant.cloud.monitor_scope()).await?;
+    let url = format!(
+        "{}/dataCollectionRules/{}/streams/{}",
+        endpoint.trim_end_matches('/'),
+        rule_id.trim(),
+        stream
+    );
+    for batch in records.chunks(BATCH_SIZE) {
+        http.post(&url)
+            .query(&[("api-version", API_VERSION)])
+            .bearer_auth(&token)
+            .json(batch)
+            .send()
+            .await?
+            .error_for_status()?;
+    }
+    info!(
+        "Sent {} findings to Log Analytics stream {}",
+        records.len(),
+        stream
+    );
+    Ok(())
+}
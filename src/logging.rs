@@ -0,0 +1,181 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use clap::ValueEnum;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{EnvFilter, Layer, Registry};
+
+use crate::cli::LogOptions;
+
+// Log output format, chosen with --log-format / LOG_FORMAT.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// Colored, human readable lines.
+    #[default]
+    Text,
+    /// One JSON object per line with the tenant, app_id and request_id of the current
+    /// scan step as fields, for log pipelines.
+    Json,
+}
+
+// When the log file is rotated, chosen with --log-rotation / LOG_ROTATION.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogRotation {
+    /// Start a new file every day.
+    #[default]
+    Daily,
+    /// Start a new file every hour.
+    Hourly,
+    /// Start a new file once the current one reaches --log-max-size-mb.
+    Size,
+}
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+fn fmt_layer<W>(format: LogFormat, writer: W, ansi: bool) -> BoxedLayer
+where
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    match format {
+        LogFormat::Text => tracing_subscriber::fmt::layer()
+            .with_ansi(ansi)
+            .with_writer(writer)
+            .boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(false)
+            .with_writer(writer)
+            .boxed(),
+    }
+}
+
+// A log file that is renamed to <path>.1 (shifting older files up, keeping `max_files`)
+// once it would grow past `max_bytes`.
+struct SizeRotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    file: File,
+    size: u64,
+}
+
+impl SizeRotatingFile {
+    fn open(path: PathBuf, max_bytes: u64, max_files: usize) -> std::io::Result<SizeRotatingFile> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(SizeRotatingFile {
+            path,
+            max_bytes,
+            max_files,
+            file,
+            size,
+        })
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        self.file.flush()?;
+        let _ = std::fs::remove_file(self.rotated(self.max_files));
+        for n in (1..self.max_files).rev() {
+            let _ = std::fs::rename(self.rotated(n), self.rotated(n + 1));
+        }
+        std::fs::rename(&self.path, self.rotated(1))?;
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for SizeRotatingFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn file_layer(options: &LogOptions, path: &str) -> anyhow::Result<BoxedLayer> {
+    let path = PathBuf::from(path);
+
+    if options.log_rotation == LogRotation::Size {
+        let file = SizeRotatingFile::open(
+            path.clone(),
+            options.log_max_size_mb.max(1) * 1024 * 1024,
+            options.log_max_files.max(1),
+        )
+        .map_err(|e| anyhow::anyhow!("Failed to open log file '{}': {}", path.display(), e))?;
+        return Ok(fmt_layer(options.log_format, Mutex::new(file), false));
+    }
+
+    let directory = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let file_name = path
+        .file_name()
+        .and_then(|f| f.to_str())
+        .ok_or_else(|| anyhow::anyhow!("Invalid log file path '{}'", path.display()))?;
+
+    let appender = tracing_appender::rolling::Builder::new()
+        .rotation(match options.log_rotation {
+            LogRotation::Hourly => tracing_appender::rolling::Rotation::HOURLY,
+            _ => tracing_appender::rolling::Rotation::DAILY,
+        })
+        .filename_prefix(file_name)
+        .max_log_files(options.log_max_files.max(1))
+        .build(&directory)
+        .map_err(|e| anyhow::anyhow!("Failed to open log file '{}': {}", path.display(), e))?;
+
+    Ok(fmt_layer(options.log_format, appender, false))
+}
+
+// Install the logger. `log` records are forwarded into tracing unless plain colored text
+// on stderr is all that is needed, so existing log lines pick up the fields of the
+// enclosing tenant/application spans. With --log-file, logs also go to a rotated file.
+// -v/-vv/--quiet override the level, which otherwise comes from RUST_LOG (default info).
+pub fn init_logging(options: &LogOptions) -> anyhow::Result<()> {
+    if options.log_format == LogFormat::Text && options.log_file.is_none() {
+        let mut builder = colog::default_builder();
+        if let Some(level) = options.level() {
+            builder.filter_level(level);
+        }
+        builder.init();
+        return Ok(());
+    }
+
+    let filter = match options.level() {
+        Some(level) => EnvFilter::new(level.to_string().to_lowercase()),
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+    };
+
+    let mut layers: Vec<BoxedLayer> = vec![fmt_layer(options.log_format, std::io::stderr, true)];
+    if let Some(path) = &options.log_file {
+        layers.push(file_layer(options, path)?);
+    }
+
+    tracing_subscriber::registry()
+        .with(layers)
+        .with(filter)
+        .try_init()
+        .map_err(|e| anyhow::anyhow!("Failed to set up logging: {}", e))?;
+    Ok(())
+}
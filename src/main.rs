@@ -1,244 +1,773 @@
+use std::process::ExitCode;
+
+use clap::Parser;
 use dotenv::dotenv;
-use graph_rs_sdk::{
-    http::HttpResponseExt,
-    identity::{ConfidentialClientApplication, EnvironmentCredential},
-    *,
-};
-use log::info;
+use futures::{StreamExt, TryStreamExt};
+use graph_rs_sdk::{identity::ConfidentialClientApplication, *};
+use log::{info, warn};
+use tracing::Instrument;
+mod acknowledgments;
+mod alerts;
+mod audit;
+mod auth;
+mod azure_devops;
+mod azure_store;
+mod batch;
+mod calendar;
+mod callback;
+mod changes;
+mod check;
+mod checkpoint;
+mod cleanup;
+mod cli;
+mod config;
+mod daemon;
+mod dedup;
+mod delivery;
+mod delta;
+mod discord;
+mod email;
+mod encryption;
+mod filters;
+mod github;
+mod inventory;
+mod jira;
+mod json_store;
+mod key_vault;
+mod locking;
+mod log_analytics;
+mod logging;
 mod models;
-use crate::models::{App, Owners};
+mod notifiers;
+mod ntfy;
+mod opsgenie;
+mod owner_cache;
+mod owners;
+mod pagerduty;
+mod postgres_store;
+mod progress;
+mod redis_store;
+mod report;
+mod retention;
+mod retry;
+mod rotate;
+mod service_principals;
+mod servicenow;
+mod slack;
+mod smtp;
+mod sqlite_store;
+mod state;
+mod state_transfer;
+mod stats;
+mod teams;
+mod templates;
+mod trends;
+mod tui;
+mod twilio;
+mod webhook;
+use crate::acknowledgments::{acknowledge, annotate, snooze};
+use crate::alerts::{Alert, Finding, Thresholds, exit_code, format_expiry};
+use crate::audit::{print_audit, print_history};
+use crate::batch::get_applications_by_app_id;
+use crate::changes::write_diff_report;
+use crate::check::{check_config, check_settings};
+use crate::checkpoint::Checkpoint;
+use crate::cleanup::{print_removed, remove_expired};
+use crate::cli::{
+    Cli, Command, ConfigCommand, OutputFormat, ReportArgs, ReportCommand, ScanOptions, StateCommand,
+};
+use crate::config::{
+    AzureCloud, Tenant, application_select, fetch_concurrency, flag_setting, ignore_marker,
+    load_config_file, page_size, proxy, set_dry_run, setting, tenants, validate,
+};
+use crate::daemon::run_daemon;
+use crate::delta::{DeltaLocation, get_applications_with_delta};
+use crate::email::render_alerts;
+use crate::filters::AppFilter;
+use crate::inventory::print_credential_inventory;
+use crate::key_vault::check_key_vaults;
+use crate::logging::init_logging;
+use crate::models::{App, CredentialHolder, Page};
+use crate::notifiers::notify;
+use crate::owner_cache::OwnerCache;
+use crate::owners::{complete_application_owners, get_application_owners, list_application_owners};
+use crate::progress::Progress;
+use crate::report::{print_json, write_report};
+use crate::retention::{apply_retention, prune_command};
+use crate::retry::{paging_with_retry, throttled_count};
+use crate::rotate::rotate;
+use crate::service_principals::{check_saml_signing_certificates, get_all_service_principals};
+use crate::state::{ScanRecord, record_scan};
+use crate::stats::{
+    APPLICATIONS_SCANNED, CERTIFICATES_EVALUATED, CREDENTIALS_EVALUATED, NOTIFICATIONS_FAILED,
+    count, get, log_summary, severity_counts,
+};
+use crate::trends::{print_metrics, print_trends};
+use crate::tui::run_dashboard;
 use reqwest::header::HeaderName;
 use reqwest::header::HeaderValue;
 
-// Return a list of applications with passwordCredentials and their owners.
-pub async fn get_all_applications_with_filter(client: &GraphClient) -> anyhow::Result<Vec<App>> {
-    let mut apps: Vec<App> = Vec::new();
+// Owner fields requested when owners are expanded inline with the applications.
+const OWNERS_EXPAND: &str = "owners($select=id,displayName,mail,userPrincipalName)";
+// Graph returns at most this many items for an expanded relationship.
+const EXPAND_LIMIT: usize = 20;
+
+// List all application pages, optionally with owners expanded inline.
+async fn list_application_pages(
+    client: &GraphClient,
+    expand_owners: bool,
+) -> anyhow::Result<Vec<Page<serde_json::Value>>> {
+    let select = application_select();
+    let select: Vec<&str> = select.iter().map(String::as_str).collect();
+    let top = page_size();
 
-    // filter for application with passwordCredentials and owners.
     // ConsistencyLevel header must be set to "eventual" when using $count in filter.
-    let all_applications_response = client
-        .applications()
-        .list_application()
-        .header(
-            HeaderName::from_static("consistencylevel"),
-            HeaderValue::from_static("eventual"),
-        )
-        .select(&["id", "appId", "displayName", "passwordCredentials"])
-        .count("true")
-        .paging()
-        .json::<serde_json::Value>()
-        .await?;
+    paging_with_retry(client, || {
+        let mut request = client
+            .applications()
+            .list_application()
+            .header(
+                HeaderName::from_static("consistencylevel"),
+                HeaderValue::from_static("eventual"),
+            )
+            .select(&select)
+            .count("true");
+
+        if let Some(top) = &top {
+            request = request.top(top);
+        }
 
-    // all_application_response is a VecDeque of pages.
-    for page in all_applications_response {
-        for application_response in page.json() {
-            for application in application_response["value"].as_array().unwrap() {
-                let mut app: App = match serde_json::from_value(application.clone()) {
-                    Ok(a) => a,
-                    Err(e) => {
-                        info!("Failed to parse application: {}. Skipping.", e);
-                        continue;
-                    }
-                };
+        if expand_owners {
+            request = request.expand(&[OWNERS_EXPAND]);
+        }
 
-                let owners_response = client
-                    .application(&app.id)
-                    .owners()
-                    .list_owners()
-                    .select(&["id", "displayName", "mail", "userPrincipalName"])
-                    .send()
-                    .await?;
-
-                // If reading json fails, skip this application.
-                let owners: Owners = match owners_response.json::<Owners>().await {
-                    Ok(o) => o,
-                    Err(_) => {
-                        info!(
-                            "Failed to parse owners for application '{:?}'. Skipping.",
-                            app.displayName
-                        );
-                        continue;
-                    }
-                };
+        request.paging().json::<serde_json::Value>()
+    })
+    .await
+}
 
-                app.insert_owners(owners.value);
-                apps.push(app);
-            }
+// Return a list of applications with passwordCredentials and their owners.
+// Owners are fetched with $expand=owners so they arrive with the application payload;
+// if expansion fails the owners are requested per application instead.
+pub async fn get_all_applications_with_filter(
+    client: &GraphClient,
+    checkpoint: Option<&Checkpoint>,
+) -> anyhow::Result<Vec<App>> {
+    let (pages, expanded) = match list_application_pages(client, true).await {
+        Ok(pages) => (pages, true),
+        Err(e) => {
+            info!(
+                "Expanding owners failed: {}. Falling back to one owners request per application.",
+                e
+            );
+            (list_application_pages(client, false).await?, false)
         }
-    }
+    };
+
+    info!("Fetched {} pages of applications", pages.len());
+
+    let filter = AppFilter::from_env()?;
+    let parsed: Vec<App> = pages
+        .into_iter()
+        .flat_map(|page| page.parse_items::<App>("application"))
+        .filter(|app| filter.allows(app.displayName.as_deref(), app.appId.as_deref()))
+        .collect();
+
+    // Resolve owners for up to FETCH_CONCURRENCY applications at a time.
+    let progress = Progress::new("Resolving application owners", parsed.len());
+    let progress = &progress;
+    let cache = OwnerCache::load().await;
+    let cache = &cache;
+    let resolved: Vec<Option<App>> = futures::stream::iter(parsed)
+        .map(|mut app| async move {
+            if let Some(checkpoint) = checkpoint
+                && let Some(owners) = checkpoint.owners(&app.id).await
+            {
+                app.insert_owners(owners);
+                progress.inc();
+                return anyhow::Ok(Some(app));
+            }
+            if let Some(owners) = cache.get(&app.id).await {
+                if let Some(checkpoint) = checkpoint {
+                    checkpoint.resolved(&app.id, &owners).await;
+                }
+                app.insert_owners(owners);
+                progress.inc();
+                return anyhow::Ok(Some(app));
+            }
+            let owners = if expanded {
+                let mut owners = std::mem::take(&mut app.owners);
+                // $expand returns at most 20 owners; page through the full list if it may be cut off.
+                if owners.len() >= EXPAND_LIMIT {
+                    owners = list_application_owners(client, &app.id).await?;
+                }
+                complete_application_owners(client, &app, owners).await?
+            } else {
+                // If reading owners fails, skip this application.
+                let Some(owners) = get_application_owners(client, &app).await? else {
+                    progress.inc();
+                    return anyhow::Ok(None);
+                };
+                owners
+            };
+            cache.put(&app.id, &owners).await;
+            if let Some(checkpoint) = checkpoint {
+                checkpoint.resolved(&app.id, &owners).await;
+            }
+            app.insert_owners(owners);
+            progress.inc();
+            anyhow::Ok(Some(app))
+        })
+        .buffered(fetch_concurrency())
+        .try_collect()
+        .await?;
+    progress.finish();
+    cache.log_hits();
+
+    let apps: Vec<App> = resolved.into_iter().flatten().collect();
 
     info!("Fetched filtered applications");
 
     Ok(apps)
 }
 
-// Check for expiring credentials within 30 days and return a list of alerts.
-// Each alert contains the application name, owner emails, and expiring credential info.
-pub async fn check_expiring_credentials(
-    apps: &Vec<App>,
-) -> anyhow::Result<Vec<(String, Vec<String>, Vec<String>)>> {
-    // (App Name, Owner Emails, Expiring Credentials)
-    let mut alerts: Vec<(String, Vec<String>, Vec<String>)> = Vec::new();
+// Check for expired credentials and credentials expiring within the largest threshold tier
+// and return a list of alerts. Each finding records the tier it currently falls in.
+// Alerts are returned even when no owner could be found, so they can be routed to admins.
+// Each alert contains the application name, owner emails, and the expired/expiring credential findings.
+// Works for anything holding credentials, e.g. app registrations and service principals.
+pub async fn check_expiring_credentials<T: CredentialHolder>(
+    apps: &[T],
+    thresholds: &Thresholds,
+) -> anyhow::Result<Vec<Alert>> {
+    let mut alerts: Vec<Alert> = Vec::new();
 
     let now = chrono::Utc::now();
-    let threshold = now + chrono::Duration::days(30);
+    let threshold = thresholds.cutoff(now);
 
     for app in apps {
+        // Tags every log line about this object with its app_id in JSON logs.
+        let _span =
+            tracing::info_span!("application", app_id = app.app_id().unwrap_or("")).entered();
+        count(&APPLICATIONS_SCANNED, 1);
+        count(
+            &CREDENTIALS_EVALUATED,
+            app.password_credentials().len() + app.key_credentials().len(),
+        );
+        count(&CERTIFICATES_EVALUATED, app.key_credentials().len());
         let mut owner_emails: Vec<String> = Vec::new();
-        let mut expiring_credential_info: Vec<String> = Vec::new();
+        let mut findings: Vec<Finding> = Vec::new();
 
-        if app.passwordCredentials.is_empty() {
+        if app.password_credentials().is_empty() && app.key_credentials().is_empty() {
             info!(
-                "Application '{:?}' (App ID: {:?}) has no password credentials.",
-                app.displayName, app.appId
+                "{} '{:?}' (App ID: {:?}) has no password or key credentials.",
+                app.kind(),
+                app.display_name(),
+                app.app_id()
             );
             continue;
         }
 
-
-        for credential in &app.passwordCredentials {
+        for credential in app.password_credentials() {
             if credential.endDateTime < threshold {
                 info!(
-                    "Application '{:?}' (App ID: {:?}) has a credential expiring on {} (Key ID: {:?}, Hint: {:?})",
-                    app.displayName,
-                    app.appId,
+                    "{} '{:?}' (App ID: {:?}) has a credential expiring on {} (Key ID: {:?}, Hint: {:?})",
+                    app.kind(),
+                    app.display_name(),
+                    app.app_id(),
                     credential.endDateTime,
                     credential.keyId,
                     credential.hint
                 );
                 // Collect expiring credential info.
-                expiring_credential_info.push(format!(
-                    "Key ID: {:?}, Hint: {:?}, Expiry: {}",
-                    credential.keyId, credential.hint, credential.endDateTime
-                ));
-
-                // Collect owner emails.
-                if !app.owners.is_empty() {
-                    info!("  Owners:");
-                    for owner in &app.owners {
-                        if let Some(mail) = &owner.mail {
-                            owner_emails.push(mail.clone());
-                            info!(
-                                "    - {} ({})",
-                                owner.displayName.as_deref().unwrap_or("No Name"),
-                                mail
-                            );
-                        } else if let Some(user_principal_name) = &owner.userPrincipalName {
-                            owner_emails.push(user_principal_name.clone());
-                            info!(
-                                "    - {} ({})",
-                                owner.displayName.as_deref().unwrap_or("No Name"),
-                                user_principal_name
-                            );
-                        } else {
-                            info!(
-                                "    - {} (No contact info)",
-                                owner.displayName.as_deref().unwrap_or("No Name")
-                            );
-                        }
+                findings.push(
+                    Finding::new(
+                        credential.endDateTime,
+                        now,
+                        thresholds,
+                        "Client secret",
+                        format!(
+                            "Key ID: {:?}, Hint: {:?}, Expiry: {}",
+                            credential.keyId,
+                            credential.hint,
+                            format_expiry(credential.endDateTime, now)
+                        ),
+                    )
+                    .with_key(credential.keyId.clone(), credential.hint.clone()),
+                );
+            }
+        }
+
+        for credential in app.key_credentials() {
+            if credential.endDateTime < threshold {
+                info!(
+                    "{} '{:?}' (App ID: {:?}) has a certificate expiring on {} (Key ID: {:?}, Thumbprint: {:?}, Usage: {:?})",
+                    app.kind(),
+                    app.display_name(),
+                    app.app_id(),
+                    credential.endDateTime,
+                    credential.keyId,
+                    credential.customKeyIdentifier,
+                    credential.usage
+                );
+                // Collect expiring certificate info.
+                findings.push(
+                    Finding::new(
+                        credential.endDateTime,
+                        now,
+                        thresholds,
+                        "Certificate",
+                        format!(
+                            "Certificate: {:?}, Key ID: {:?}, Thumbprint: {:?}, Type: {:?}, Usage: {:?}, Expiry: {}",
+                            credential.displayName,
+                            credential.keyId,
+                            credential.customKeyIdentifier,
+                            credential.keyType,
+                            credential.usage,
+                            format_expiry(credential.endDateTime, now)
+                        ),
+                    )
+                    .with_key(credential.keyId.clone(), None),
+                );
+            }
+        }
+
+        // Collect owner emails.
+        if !findings.is_empty() {
+            if !app.owners().is_empty() {
+                info!("  Owners:");
+                for owner in app.owners() {
+                    if let Some(mail) = &owner.mail {
+                        owner_emails.push(mail.clone());
+                        info!(
+                            "    - {} ({})",
+                            owner.displayName.as_deref().unwrap_or("No Name"),
+                            mail
+                        );
+                    } else if let Some(user_principal_name) = &owner.userPrincipalName {
+                        owner_emails.push(user_principal_name.clone());
+                        info!(
+                            "    - {} ({})",
+                            owner.displayName.as_deref().unwrap_or("No Name"),
+                            user_principal_name
+                        );
+                    } else {
+                        info!(
+                            "    - {} (No contact info)",
+                            owner.displayName.as_deref().unwrap_or("No Name")
+                        );
                     }
-                } else {
-                    info!("  No owners found for this application.");
                 }
+            } else {
+                info!("  No owners found for this {}.", app.kind());
             }
         }
 
-        // If there are both expiring credentials and owner emails, add to alerts.
-        if !expiring_credential_info.is_empty() && !owner_emails.is_empty() {
-            alerts.push((
-                app.displayName
-                    .clone()
-                    .unwrap_or_else(|| "No Name".to_string()),
-                owner_emails,
-                expiring_credential_info,
-            ));
-        } else {
+        // If there are expiring credentials, add to alerts.
+        if !findings.is_empty() {
+            if owner_emails.is_empty() {
+                info!(
+                    "No owners to notify for {} '{:?}' (App ID: {:?})",
+                    app.kind(),
+                    app.display_name(),
+                    app.app_id()
+                );
+            }
+            // Soonest (or longest expired) first.
+            findings.sort_by_key(|f| f.end_date_time);
+            let alert = Alert {
+                tenant: String::new(),
+                name: format!(
+                    "{} ({})",
+                    app.display_name().unwrap_or("No Name"),
+                    app.kind()
+                ),
+                app_id: app.app_id().map(|id| id.to_string()),
+                owners: owner_emails,
+                escalate_to: Vec::new(),
+                findings,
+            };
             info!(
-                "No expiring credentials or no owners to notify for application '{:?}' (App ID: {:?})",
-                app.displayName, app.appId
+                "{} '{:?}' has {} findings, severity {}",
+                app.kind(),
+                app.display_name(),
+                alert.findings.len(),
+                alert.severity().label()
             );
+            alerts.push(alert);
         }
     }
 
     Ok(alerts)
 }
 
-// Send email alert for expiring credentials.
-// The email is sent from ALERTING_EMAIL to RECIEVER_EMAIL with the list of expiring credentials.
-pub async fn send_email_alert(
+// Build a Graph client from a tenant's client secret credentials,
+// pointed at the Graph and login endpoints of the tenant's cloud.
+// Both the token requests and the Graph requests go through the configured proxy.
+pub fn client_secret_credential(tenant: &Tenant) -> anyhow::Result<GraphClient> {
+    let mut config = GraphClientConfiguration::new();
+    if let Some(proxy) = proxy()? {
+        config = config.proxy(proxy);
+    }
+    let confidential_client = ConfidentialClientApplication::builder(tenant.client_id.trim())
+        .with_config(config.clone())
+        .with_client_secret(&tenant.client_secret)
+        .with_tenant(&tenant.tenant_id)
+        .with_azure_cloud_instance(tenant.cloud.cloud_instance())
+        .build();
+    let mut client = GraphClient::from(config.client_application(confidential_client));
+    if tenant.cloud != AzureCloud::Public {
+        client.custom_endpoint(&url::Url::parse(tenant.cloud.graph_endpoint())?);
+    }
+    Ok(client)
+}
+
+// Split off the objects whose owners opted them out of alerting via tags or notes,
+// returning their names for the run summary.
+fn remove_ignored<T: CredentialHolder>(items: &mut Vec<T>, marker: &str) -> Vec<String> {
+    let mut ignored = Vec::new();
+    items.retain(|item| {
+        if item.is_ignored(marker) {
+            ignored.push(format!(
+                "{} ({})",
+                item.display_name().unwrap_or("No Name"),
+                item.kind()
+            ));
+            false
+        } else {
+            true
+        }
+    });
+    ignored
+}
+
+// Fetch and evaluate everything in one tenant and return its alerts, tagged with the tenant name,
+// along with the names of the objects skipped because they were opted out of alerting.
+async fn scan_tenant(
+    tenant: &Tenant,
     client: &GraphClient,
-    alerts: Vec<(String, Vec<String>, Vec<String>)>,
-) -> anyhow::Result<()> {
-    
-    let alerting_email = std::env::var("ALERTING_EMAIL")?;
-    let reciever_email = std::env::var("RECIEVER_EMAIL")?;
-
-    let mail = client.user(&alerting_email)
-        .send_mail(&serde_json::json!({
-                "message": {
-                "subject": "Alert: Expiring Credentials for Applications",
-                "body": {
-                    "contentType": "Text",
-                    "content": format!(
-                        "The following applications have credentials expiring within the next 30 days: \n\n {}",
-                        alerts.iter().map(|(app_name, owners, creds)| {
-                            format!("Application: {}\nOwners: {}\nExpiring Credentials:\n{}\n", 
-                                app_name, 
-                                owners.iter().map(|s| s.as_str()).collect::<Vec<&str>>().join(", "), 
-                                creds.iter().map(|s| s.as_str()).collect::<Vec<&str>>().join("\n")
-                            )
-                        })
-                        .collect::<Vec<String>>().join("\n")
-                    )
-                },
-                "toRecipients":[
-              {
-                  "emailAddress":{
-                      "address": &reciever_email
-                  }
-              }
-          ]
-            },
-            "saveToSentItems": "true"
-        }
-        )).send().await?;
-
-    info!("Email sent with response: {:?}", mail);
-
-    Ok(())
-    
+    options: &ScanOptions,
+    thresholds: &Thresholds,
+) -> anyhow::Result<(Vec<Alert>, Vec<String>)> {
+    info!("Scanning tenant '{}'", tenant.name);
+
+    let checkpoint = Checkpoint::open(&tenant.name, options.resume).await;
+    let mut apps = get_applications(client, &tenant.name, options, Some(&checkpoint)).await?;
+
+    info!("Fetched {:?} applications with owners", apps);
+
+    let mut service_principals = get_all_service_principals(client).await?;
+
+    info!(
+        "Fetched {} service principals with credentials",
+        service_principals.len()
+    );
+
+    let marker = ignore_marker();
+    let mut ignored = remove_ignored(&mut apps, &marker);
+    ignored.extend(remove_ignored(&mut service_principals, &marker));
+
+    let mut alerts = check_expiring_credentials(&apps, thresholds).await?;
+
+    // With --check-saml, SAML enterprise apps get a dedicated signing
+    // certificate check instead of the generic credential check.
+    if options.check_saml {
+        let (saml, others): (Vec<_>, Vec<_>) =
+            service_principals.into_iter().partition(|sp| sp.is_saml());
+        alerts.extend(check_saml_signing_certificates(&saml, thresholds));
+        alerts.extend(check_expiring_credentials(&others, thresholds).await?);
+    } else {
+        alerts.extend(check_expiring_credentials(&service_principals, thresholds).await?);
+    }
+
+    // Key Vault secrets, keys and certificates, if the tenant has KEY_VAULT_NAMES set.
+    alerts.extend(check_key_vaults(tenant, thresholds).await?);
+
+    for alert in &mut alerts {
+        alert.tenant = tenant.name.clone();
+    }
+
+    Ok((alerts, ignored))
 }
 
-pub fn client_secret_credential() -> anyhow::Result<GraphClient> {
-    let confidential_client = EnvironmentCredential::client_secret_credential()?;
-    Ok(GraphClient::from(&confidential_client))
+// Fetch the applications to evaluate.
+// --application, --apps-file and --stdin limit the scan to a list of IDs, fetched with $batch.
+// With --delta-state-file or --delta, only applications changed since the last run are fetched.
+// --delta keeps the delta state in the state store under `tenant`.
+// A full listing checkpoints its progress in `checkpoint` (see checkpoint.rs).
+async fn get_applications(
+    client: &GraphClient,
+    tenant: &str,
+    options: &ScanOptions,
+    checkpoint: Option<&Checkpoint>,
+) -> anyhow::Result<Vec<App>> {
+    if !options.applications.is_empty() {
+        get_applications_by_app_id(client, &options.applications).await
+    } else if let Some(path) = &options.delta_state_file {
+        get_applications_with_delta(client, DeltaLocation::File(path)).await
+    } else if options.delta {
+        get_applications_with_delta(client, DeltaLocation::Store(tenant)).await
+    } else {
+        get_all_applications_with_filter(client, checkpoint).await
+    }
 }
 
+// Exits with 0 when nothing needs attention, 2 for expiring and 3 for expired credentials
+// (see alerts::exit_code); errors exit with 1.
 #[tokio::main]
-async fn main() -> anyhow::Result<()> {
+async fn main() -> anyhow::Result<ExitCode> {
     dotenv().ok();
 
+    // Parsed after loading .env so it can provide defaults for the flags.
+    let mut cli = Cli::parse();
+    let command = cli.command.clone().unwrap_or(Command::Notify);
+
+    load_config_file(cli.config.as_deref(), cli.profile.as_deref())?;
+    cli.scan.apply_config_file();
+    cli.notify.apply_config_file();
+    cli.scan.read_app_ids()?;
+    set_dry_run(cli.dry_run || flag_setting("DRY_RUN"));
+
+    if command == Command::Config(ConfigCommand::Check) {
+        check_settings()?;
+    } else {
+        validate(matches!(command, Command::Notify | Command::Daemon(_)))?;
+    }
+
     // setup logging
-    colog::init();
+    init_logging(&cli.logging)?;
+
+    // One Graph client per configured tenant. Notifications go out through the first one.
+    let tenants = tenants()?;
+    let clients = tenants
+        .iter()
+        .map(client_secret_credential)
+        .collect::<anyhow::Result<Vec<GraphClient>>>()?;
+
+    state::open(&tenants[0]).await?;
+
+    if let Command::Report(ReportArgs {
+        command: Some(report_command),
+        ..
+    }) = &command
+    {
+        match report_command {
+            ReportCommand::Trends(args) => print_trends(args, cli.output).await?,
+            ReportCommand::Metrics(args) => print_metrics(args, cli.output).await?,
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
 
-    // let app_ids = std::env::var("APPLICATION")?;
-    // let app_ids: Vec<String> = app_ids.split(',').map(|s| s.trim().to_string()).collect();
+    match &command {
+        Command::Ack(args) => {
+            acknowledge(args).await?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Command::Snooze(args) => {
+            snooze(args).await?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Command::Audit(args) => {
+            print_audit(args, cli.output).await?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Command::History(args) => {
+            print_history(args, cli.output).await?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Command::State(state_command) => {
+            match state_command {
+                StateCommand::Prune(args) => prune_command(args).await?,
+                StateCommand::Export(args) => state_transfer::export(args).await?,
+                StateCommand::Import(args) => state_transfer::import(args).await?,
+            }
+            return Ok(ExitCode::SUCCESS);
+        }
+        _ => {}
+    }
 
-    // Initialize Graph client
-    let client = client_secret_credential()?;
+    if command == Command::Config(ConfigCommand::Check) {
+        check_config(&tenants, &clients).await?;
+        return Ok(ExitCode::SUCCESS);
+    }
 
-    let apps = get_all_applications_with_filter(&client).await?;
-    // let apps = get_applications_with_owners(&client, app_ids).await?;
+    if let Command::Rotate(args) = &command {
+        rotate(args, &tenants, &clients, cli.output).await?;
+        return Ok(ExitCode::SUCCESS);
+    }
 
-    info!("Fetched {:?} applications with owners", apps);
+    if let Command::Cleanup(args) = &command {
+        let mut removed = Vec::new();
+        let mut failed = 0;
+        for (tenant, client) in tenants.iter().zip(&clients) {
+            let apps = get_applications(client, &tenant.name, &cli.scan, None).await?;
+            let (tenant_removed, tenant_failed) =
+                remove_expired(args, tenant, client, &apps).await?;
+            removed.extend(tenant_removed);
+            failed += tenant_failed;
+        }
+        print_removed(&removed, cli.output)?;
+        if failed > 0 {
+            anyhow::bail!("Failed to remove {} expired client secrets", failed);
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
 
-    let alerts = check_expiring_credentials(&apps).await?;
+    if command == Command::Inventory {
+        for (tenant, client) in tenants.iter().zip(&clients) {
+            println!("Tenant: {}", tenant.name);
+            let apps = get_applications(client, &tenant.name, &cli.scan, None).await?;
+            print_credential_inventory(client, &apps).await?;
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let Command::Daemon(args) = &command {
+        run_daemon(&cli, args, tenants, clients).await?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let code = run(&cli, &command, &tenants, &clients).await?;
+    Ok(ExitCode::from(code))
+}
+
+// Scan all tenants and act on the findings according to `command`.
+// Returns the exit code for the findings (see alerts::exit_code).
+pub async fn run(
+    cli: &Cli,
+    command: &Command,
+    tenants: &[Tenant],
+    clients: &[GraphClient],
+) -> anyhow::Result<u8> {
+    stats::reset();
+    let started = chrono::Utc::now();
+
+    let mut thresholds = Thresholds::from_env()?;
+    if let Some(days) = cli
+        .threshold_days
+        .or(setting("THRESHOLD_DAYS").and_then(|v| v.parse().ok()))
+    {
+        thresholds = thresholds.with_max_days(days)?;
+    }
+
+    let mut alerts: Vec<Alert> = Vec::new();
+    let mut ignored: Vec<String> = Vec::new();
+    for (tenant, client) in tenants.iter().zip(clients) {
+        let (tenant_alerts, tenant_ignored) = scan_tenant(tenant, client, &cli.scan, &thresholds)
+            .instrument(tracing::info_span!("tenant", tenant = %tenant.name))
+            .await?;
+        info!(
+            "Tenant '{}' scanned: {} applications need attention ({} so far)",
+            tenant.name,
+            tenant_alerts.len(),
+            alerts.len() + tenant_alerts.len()
+        );
+        alerts.extend(tenant_alerts);
+        ignored.extend(
+            tenant_ignored
+                .into_iter()
+                .map(|name| format!("{}: {}", tenant.name, name)),
+        );
+    }
+    // Every tenant was scanned, so there is nothing left to resume.
+    checkpoint::clear().await;
+
+    annotate(&mut alerts).await;
+
+    if log_analytics::enabled()
+        && let Err(e) = log_analytics::export(&alerts, &tenants[0]).await
+    {
+        warn!("Failed to send the findings to Log Analytics: {}", e);
+    }
 
     info!("Alerts!: {:?}", &alerts);
 
-    // Send emails to reciever email with expiring credentials for all applications.
+    let code = exit_code(&alerts);
+    let severities = severity_counts(&alerts);
+    let tenant_names: Vec<String> = tenants.iter().map(|t| t.name.clone()).collect();
+    let mut record = ScanRecord::from_run(&alerts, &tenant_names, started);
+
+    if cli.output == OutputFormat::Json {
+        print_json(&alerts)?;
+    }
+
+    match command {
+        Command::Report(args) => {
+            // --output json already printed the findings; only write a text report to a file.
+            if cli.output == OutputFormat::Text || args.out.is_some() {
+                if args.diff {
+                    write_diff_report(&alerts, &tenant_names, cli.scan.scope().as_deref(), args)
+                        .await?;
+                } else {
+                    write_report(&alerts, &thresholds, args)?;
+                }
+            }
+        }
+        Command::Notify => {
+            if locking::try_acquire(locking::NOTIFY).await {
+                // A run limited to some applications says nothing about the others.
+                let scanned = match cli.scan.scope() {
+                    Some(_) => Vec::new(),
+                    None => tenant_names.clone(),
+                };
+                notify(&clients[0], &alerts, &scanned, &thresholds, &cli.notify).await;
+                locking::release(locking::NOTIFY).await;
+            } else {
+                info!("Skipping notifications; another instance is sending them");
+            }
+        }
+        Command::Tui => run_dashboard(&alerts)?,
+        // Findings are only logged on a scan; --quiet suppresses that, so print them instead.
+        Command::Scan if cli.logging.quiet && cli.output == OutputFormat::Text => {
+            print!("{}", render_alerts(&alerts, &thresholds));
+        }
+        Command::Scan
+        | Command::Inventory
+        | Command::Config(_)
+        | Command::Daemon(_)
+        | Command::Ack(_)
+        | Command::Snooze(_)
+        | Command::Audit(_)
+        | Command::History(_)
+        | Command::State(_)
+        | Command::Rotate(_)
+        | Command::Cleanup(_) => {}
+    }
+
+    if !ignored.is_empty() {
+        info!(
+            "Skipped {} objects opted out of alerting: {}",
+            ignored.len(),
+            ignored.join(", ")
+        );
+    }
+
+    if throttled_count() > 0 {
+        warn!(
+            "Graph throttled {} requests during this run; consider lowering FETCH_CONCURRENCY",
+            throttled_count()
+        );
+    }
+
+    log_summary(&severities);
+
+    record.finish();
+    if let Err(e) = record_scan(&record).await {
+        warn!("Failed to record the scan history: {}", e);
+    }
+    if let Err(e) =
+        changes::record(&alerts, &tenant_names, cli.scan.scope().as_deref(), started).await
+    {
+        warn!("Failed to record this run's findings: {}", e);
+    }
+    apply_retention().await;
+
+    if get(&NOTIFICATIONS_FAILED) > 0 {
+        anyhow::bail!(
+            "{} notifications failed to send",
+            get(&NOTIFICATIONS_FAILED)
+        );
+    }
 
-    let email_response = send_email_alert(&client, alerts).await?;
-    
-    Ok(())
+    Ok(code)
 }
@@ -1,5 +1,39 @@
+// Fields are named as in Graph's JSON so they deserialize without renames.
+#![allow(non_snake_case)]
+
 use chrono::DateTime;
-use serde::Deserialize;
+use log::info;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::collections::HashMap;
+
+// One page of a Graph collection response.
+#[derive(Deserialize, Debug)]
+pub struct Page<T> {
+    #[serde(default = "Vec::new")]
+    pub value: Vec<T>,
+    #[serde(rename = "@odata.nextLink")]
+    pub nextLink: Option<String>,
+    // Only present on the last page of a delta query.
+    #[serde(rename = "@odata.deltaLink")]
+    pub deltaLink: Option<String>,
+}
+
+impl Page<serde_json::Value> {
+    // Parse every item of the page, logging and skipping the ones that don't fit `T`
+    // so one malformed object doesn't fail the whole scan.
+    pub fn parse_items<T: DeserializeOwned>(self, kind: &str) -> Vec<T> {
+        self.value
+            .into_iter()
+            .filter_map(|item| match serde_json::from_value::<T>(item) {
+                Ok(parsed) => Some(parsed),
+                Err(e) => {
+                    info!("Failed to parse {}: {}. Skipping.", kind, e);
+                    None
+                }
+            })
+            .collect()
+    }
+}
 
 #[derive(Deserialize, Debug)]
 pub struct PasswordCredential {
@@ -10,45 +44,267 @@ pub struct PasswordCredential {
 }
 
 #[derive(Deserialize, Debug)]
-pub struct Users {
-    pub value: Vec<User>,
+pub struct KeyCredential {
+    // For certificates this is the thumbprint.
+    pub customKeyIdentifier: Option<String>,
+    pub displayName: Option<String>,
+    pub endDateTime: DateTime<chrono::Utc>,
+    pub keyId: Option<String>,
+    // "AsymmetricX509Cert", "X509CertAndPassword", ...
+    #[serde(rename = "type")]
+    pub keyType: Option<String>,
+    // "Verify" or "Sign".
+    pub usage: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
-pub struct User {
-    pub id: String,
-
-    pub signInType: String,
+pub struct FederatedIdentityCredential {
+    pub name: Option<String>,
     pub issuer: String,
-    pub issuerAssignedId: String,
+    pub subject: String,
+    #[serde(default)]
+    pub audiences: Vec<String>,
 }
 
-#[derive(Deserialize, Debug)]
-pub struct Owners {
-    pub value: Vec<Owner>,
-} 
-
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Owner {
     pub id: String,
+    // e.g. "#microsoft.graph.user", "#microsoft.graph.group", "#microsoft.graph.servicePrincipal".
+    #[serde(rename = "@odata.type")]
+    pub odataType: Option<String>,
     pub displayName: Option<String>,
     pub userPrincipalName: Option<String>,
     pub mail: Option<String>,
 }
 
-#[derive(Deserialize, Debug)] 
+#[derive(Deserialize, Debug)]
 pub struct App {
     pub id: String,
     pub appId: Option<String>,
     pub displayName: Option<String>,
     pub passwordCredentials: Vec<PasswordCredential>,
-    #[serde(skip)]
-    pub owners: Vec<Owner>, 
+    #[serde(default)]
+    pub keyCredentials: Vec<KeyCredential>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub notes: Option<String>,
+    // Filled in from $expand=owners when the applications are listed with their owners,
+    // otherwise by insert_owners once they are resolved.
+    #[serde(default)]
+    pub owners: Vec<Owner>,
+}
+
+impl Owner {
+    pub fn is_group(&self) -> bool {
+        self.odataType.as_deref() == Some("#microsoft.graph.group")
+    }
+
+    pub fn is_user(&self) -> bool {
+        self.odataType.as_deref() == Some("#microsoft.graph.user")
+    }
 }
 
 impl App {
+    // Fields requested for every application.
+    pub const SELECT: &'static [&'static str] = &[
+        "id",
+        "appId",
+        "displayName",
+        "passwordCredentials",
+        "keyCredentials",
+        "tags",
+        "notes",
+    ];
+
+    pub fn insert_owners(&mut self, owners: Vec<Owner>) {
+        self.owners = owners;
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ServicePrincipal {
+    pub id: String,
+    pub appId: Option<String>,
+    pub displayName: Option<String>,
+    #[serde(default)]
+    pub passwordCredentials: Vec<PasswordCredential>,
+    #[serde(default)]
+    pub keyCredentials: Vec<KeyCredential>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub notes: Option<String>,
+    // "saml", "password", "oidc", ... or None when SSO isn't configured.
+    pub preferredSingleSignOnMode: Option<String>,
+    // Thumbprint of the certificate currently used to sign SAML tokens.
+    pub preferredTokenSigningKeyThumbprint: Option<String>,
+    #[serde(skip)]
+    pub owners: Vec<Owner>,
+}
+
+impl ServicePrincipal {
+    // Fields requested for every service principal.
+    pub const SELECT: &'static [&'static str] = &[
+        "id",
+        "appId",
+        "displayName",
+        "passwordCredentials",
+        "keyCredentials",
+        "tags",
+        "notes",
+        "preferredSingleSignOnMode",
+        "preferredTokenSigningKeyThumbprint",
+    ];
+
+    pub fn is_saml(&self) -> bool {
+        self.preferredSingleSignOnMode
+            .as_deref()
+            .is_some_and(|mode| mode.eq_ignore_ascii_case("saml"))
+    }
+
     pub fn insert_owners(&mut self, owners: Vec<Owner>) {
         self.owners = owners;
     }
+}
+
+// Anything in the directory that carries credentials and owners.
+// Lets app registrations and service principals share the same expiry checks.
+pub trait CredentialHolder {
+    // Used in logs and alerts, e.g. "Application" or "Service Principal".
+    fn kind(&self) -> &'static str;
+    fn display_name(&self) -> Option<&str>;
+    fn app_id(&self) -> Option<&str>;
+    fn password_credentials(&self) -> &[PasswordCredential];
+    fn key_credentials(&self) -> &[KeyCredential];
+    fn owners(&self) -> &[Owner];
+    fn tags(&self) -> &[String];
+    fn notes(&self) -> Option<&str>;
+
+    // Owners can opt an object out of alerting by putting `marker`
+    // (e.g. "secret-manager:ignore") in its tags or notes.
+    fn is_ignored(&self, marker: &str) -> bool {
+        self.tags().iter().any(|t| t.eq_ignore_ascii_case(marker))
+            || self
+                .notes()
+                .is_some_and(|n| n.to_lowercase().contains(&marker.to_lowercase()))
+    }
+}
 
-}
\ No newline at end of file
+impl CredentialHolder for App {
+    fn kind(&self) -> &'static str {
+        "Application"
+    }
+    fn display_name(&self) -> Option<&str> {
+        self.displayName.as_deref()
+    }
+    fn app_id(&self) -> Option<&str> {
+        self.appId.as_deref()
+    }
+    fn password_credentials(&self) -> &[PasswordCredential] {
+        &self.passwordCredentials
+    }
+    fn key_credentials(&self) -> &[KeyCredential] {
+        &self.keyCredentials
+    }
+    fn owners(&self) -> &[Owner] {
+        &self.owners
+    }
+    fn tags(&self) -> &[String] {
+        &self.tags
+    }
+    fn notes(&self) -> Option<&str> {
+        self.notes.as_deref()
+    }
+}
+
+impl CredentialHolder for ServicePrincipal {
+    fn kind(&self) -> &'static str {
+        "Service Principal"
+    }
+    fn display_name(&self) -> Option<&str> {
+        self.displayName.as_deref()
+    }
+    fn app_id(&self) -> Option<&str> {
+        self.appId.as_deref()
+    }
+    fn password_credentials(&self) -> &[PasswordCredential] {
+        &self.passwordCredentials
+    }
+    fn key_credentials(&self) -> &[KeyCredential] {
+        &self.keyCredentials
+    }
+    fn owners(&self) -> &[Owner] {
+        &self.owners
+    }
+    fn tags(&self) -> &[String] {
+        &self.tags
+    }
+    fn notes(&self) -> Option<&str> {
+        self.notes.as_deref()
+    }
+}
+
+// Key Vault REST models. Secrets, keys and certificates share the same list item shape.
+
+#[derive(Deserialize, Debug)]
+pub struct KeyVaultItems {
+    pub value: Vec<KeyVaultItem>,
+    pub nextLink: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct KeyVaultItem {
+    // Secrets and certificates use "id", keys use "kid".
+    #[serde(alias = "kid")]
+    pub id: String,
+    pub attributes: KeyVaultAttributes,
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+    // Certificate thumbprint (base64url), only present on certificates.
+    pub x5t: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct KeyVaultAttributes {
+    pub enabled: Option<bool>,
+    // Expiry as seconds since the Unix epoch.
+    pub exp: Option<i64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AccessToken {
+    pub access_token: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expanded_owners_are_kept_on_the_application() {
+        let page: Page<serde_json::Value> = serde_json::from_value(serde_json::json!({
+            "value": [
+                {
+                    "id": "object-id",
+                    "appId": "app-id",
+                    "displayName": "app",
+                    "passwordCredentials": [],
+                    "owners": [
+                        {
+                            "@odata.type": "#microsoft.graph.user",
+                            "id": "owner-id",
+                            "mail": "owner@example.com"
+                        }
+                    ]
+                },
+                { "id": "no-owners", "passwordCredentials": [] }
+            ]
+        }))
+        .unwrap();
+        let apps: Vec<App> = page.parse_items("application");
+        assert_eq!(apps.len(), 2);
+        assert_eq!(apps[0].owners.len(), 1);
+        assert_eq!(apps[0].owners[0].mail.as_deref(), Some("owner@example.com"));
+        assert!(apps[0].owners[0].is_user());
+        assert!(apps[1].owners.is_empty());
+    }
+}
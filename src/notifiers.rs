@@ -0,0 +1,287 @@
+use std::collections::HashSet;
+
+use chrono::Utc;
+use graph_rs_sdk::*;
+use log::{error, info, warn};
+
+use crate::acknowledgments::without_acknowledged;
+use crate::alerts::{Alert, Severity, Thresholds, format_timestamp};
+use crate::audit::{body_id, record_notification, response_id};
+use crate::cli::NotifyOptions;
+use crate::config::{dry_run, http_client, setting};
+use crate::dedup::{NotificationState, notified_keys};
+use crate::delivery;
+use crate::stats::{NOTIFICATIONS_FAILED, NOTIFICATIONS_SENT, count, get};
+use crate::{
+    azure_devops, discord, email, github, jira, ntfy, opsgenie, pagerduty, servicenow, slack,
+    teams, twilio, webhook,
+};
+
+// A setting that can be overridden per severity, e.g. SLACK_WEBHOOK_URL_CRITICAL over
+// SLACK_WEBHOOK_URL.
+pub fn severity_setting(name: &str, severity: Severity) -> Option<String> {
+    setting(&format!("{}_{}", name, severity.label().to_uppercase())).or_else(|| setting(name))
+}
+
+// The alerts at or above the severity in `name` (e.g. SLACK_MIN_SEVERITY), or all of them
+// if it isn't set.
+pub fn alerts_at_or_above<'a>(alerts: &'a [Alert], name: &str) -> Vec<&'a Alert> {
+    let min = setting(name)
+        .and_then(|v| Severity::parse(&v).ok())
+        .unwrap_or(Severity::Low);
+    alerts.iter().filter(|a| a.severity() >= min).collect()
+}
+
+// POST a JSON payload to a webhook-style notification endpoint, counting the notification
+// as sent or failed and recording it in the audit log with the `findings` it is about.
+// With --dry-run the payload is printed instead.
+pub async fn post_json(
+    channel: &str,
+    url: &str,
+    payload: &serde_json::Value,
+    findings: &[String],
+) -> anyhow::Result<()> {
+    post_json_with_headers(channel, url, &[], payload, findings).await
+}
+
+// post_json with extra request headers, e.g. for authentication.
+pub async fn post_json_with_headers(
+    channel: &str,
+    url: &str,
+    headers: &[(String, String)],
+    payload: &serde_json::Value,
+    findings: &[String],
+) -> anyhow::Result<()> {
+    if dry_run() {
+        println!("[dry-run] Would post to {}", channel);
+        println!("{}", serde_json::to_string_pretty(payload)?);
+        println!();
+        return Ok(());
+    }
+
+    let mut request = http_client().post(url).json(payload);
+    for (name, value) in headers {
+        request = request.header(name, value);
+    }
+
+    let result = request.send().await.and_then(|r| r.error_for_status());
+
+    match result {
+        Ok(response) => {
+            count(&NOTIFICATIONS_SENT, 1);
+            info!(
+                "Posted to {} with response: {:?}",
+                channel,
+                response.status()
+            );
+            let header_id = response_id(response.headers());
+            let message_id = response
+                .text()
+                .await
+                .ok()
+                .and_then(|body| body_id(&body))
+                .or(header_id);
+            record_notification(channel, &[], findings, message_id).await;
+            Ok(())
+        }
+        Err(e) => {
+            count(&NOTIFICATIONS_FAILED, 1);
+            // The error's URL may carry the webhook's secret, so it is left out.
+            anyhow::bail!("Posting to {} failed: {}", channel, e.without_url())
+        }
+    }
+}
+
+// Send a run's notifications through every configured channel (see send_all) and sync the
+// issue trackers (see sync_trackers).
+// With --notify-state-file or a state store, credentials already notified at their current
+// tier are left out of the notifications. The trackers always see every finding: they keep
+// their own record of what has been filed, and closing rotated GitHub issues needs the full
+// picture. Acknowledged and snoozed findings are left out of the notifications too.
+// Outside the delivery window (see delivery.rs) nothing is sent, not even to the trackers;
+// the alerts are held until it opens. `scanned` are the tenants whose every application was
+// scanned, none when the run was limited to some of them (see ScanOptions::scope); only
+// there do held alerts and notification records of credentials no longer flagged go away.
+pub async fn notify(
+    client: &GraphClient,
+    alerts: &[Alert],
+    scanned: &[String],
+    thresholds: &Thresholds,
+    options: &NotifyOptions,
+) {
+    let now = Utc::now();
+    match delivery::window() {
+        Ok(Some(window)) if !window.is_open(now) => {
+            delivery::hold(alerts).await;
+            info!(
+                "Outside the delivery window; holding {} alerts until {}",
+                alerts.len(),
+                format_timestamp(window.next_open(now))
+            );
+            return;
+        }
+        Ok(_) => {}
+        Err(e) => error!("Ignoring the delivery window: {}", e),
+    }
+
+    let alerts = delivery::release_held(alerts.to_vec(), scanned).await;
+    let path = options.notify_state_file.as_deref();
+    let mut state = match NotificationState::open(path).await {
+        Ok(state) => state,
+        Err(e) => {
+            error!("Failed to load the notification state: {}", e);
+            None
+        }
+    };
+    sync_trackers(&alerts).await;
+
+    // Taken before acknowledged findings are left out, so their records are kept.
+    let flagged: HashSet<String> = notified_keys(&alerts)
+        .into_iter()
+        .map(|(key, _)| key)
+        .collect();
+    let alerts = without_acknowledged(alerts);
+    let due = match &mut state {
+        Some(state) => {
+            state.forget_resolved(&flagged, scanned);
+            let mut due = state.filter_due(alerts, now);
+            state.escalate(client, &mut due).await;
+            due
+        }
+        None => alerts,
+    };
+    if due.is_empty() {
+        info!("Nothing new to notify");
+    }
+    let notified = notified_keys(&due);
+    let failed_before = get(&NOTIFICATIONS_FAILED);
+
+    send_all(client, due, thresholds, options).await;
+
+    // Dry runs send nothing, so there is nothing to remember.
+    if dry_run() {
+        return;
+    }
+
+    if let Some(mut state) = state {
+        // Only remember the run if everything went out, so failed notifications are retried.
+        if get(&NOTIFICATIONS_FAILED) > failed_before {
+            warn!("Some notifications failed; not updating the notification state");
+        } else {
+            state.record(notified, now);
+        }
+        if let Err(e) = state.persist(path).await {
+            error!("Failed to save the notification state: {}", e);
+        }
+    }
+}
+
+// Send a run's notifications through every configured channel. Email always goes out; the
+// other channels are enabled by their own settings:
+// - Teams channel message: TEAMS_TEAM_ID and TEAMS_CHANNEL_ID
+// - Teams Adaptive Card: TEAMS_WEBHOOK_URL
+// - Slack: SLACK_WEBHOOK_URL (or a per-severity SLACK_WEBHOOK_URL_<SEVERITY>)
+// - Discord: DISCORD_WEBHOOK_URL
+// - Generic JSON webhook: WEBHOOK_URL
+// - PagerDuty (credentials expiring within a week): PAGERDUTY_ROUTING_KEY
+// - Opsgenie: OPSGENIE_API_KEY
+// - SMS through Twilio (credentials expiring within 3 days): TWILIO_ACCOUNT_SID
+// - ntfy push notifications: NTFY_TOPIC_URL
+// A failure on one channel is logged and counted without stopping the others.
+async fn send_all(
+    client: &GraphClient,
+    alerts: Vec<Alert>,
+    thresholds: &Thresholds,
+    options: &NotifyOptions,
+) {
+    if alerts.is_empty() {
+        return;
+    }
+
+    if teams::channel_enabled()
+        && let Err(e) = teams::post_channel_message(client, &alerts, thresholds).await
+    {
+        error!("Failed to post to the Teams channel: {}", e);
+    }
+
+    if teams::webhook_enabled()
+        && let Err(e) = teams::post_webhook_card(&alerts, thresholds).await
+    {
+        error!("Failed to post to the Teams webhook: {}", e);
+    }
+
+    if slack::enabled()
+        && let Err(e) = slack::post_messages(&alerts).await
+    {
+        error!("Failed to post to Slack: {}", e);
+    }
+
+    if discord::enabled()
+        && let Err(e) = discord::post_messages(&alerts).await
+    {
+        error!("Failed to post to Discord: {}", e);
+    }
+
+    if webhook::enabled()
+        && let Err(e) = webhook::post_findings(&alerts).await
+    {
+        error!("Failed to post to the webhook: {}", e);
+    }
+
+    if pagerduty::enabled()
+        && let Err(e) = pagerduty::trigger_events(&alerts).await
+    {
+        error!("Failed to trigger PagerDuty events: {}", e);
+    }
+
+    if opsgenie::enabled()
+        && let Err(e) = opsgenie::create_alerts(&alerts).await
+    {
+        error!("Failed to create Opsgenie alerts: {}", e);
+    }
+
+    if twilio::enabled()
+        && let Err(e) = twilio::send_sms(&alerts).await
+    {
+        error!("Failed to send SMS: {}", e);
+    }
+
+    if ntfy::enabled()
+        && let Err(e) = ntfy::publish(&alerts).await
+    {
+        error!("Failed to publish to ntfy: {}", e);
+    }
+
+    email::notify(client, alerts, thresholds, options).await;
+}
+
+// Open, update and close issues in the configured trackers, each enabled by its own settings:
+// - Jira issues: JIRA_URL and JIRA_PROJECT
+// - ServiceNow incidents: SERVICENOW_URL
+// - Azure DevOps work items: AZDO_ORG_URL and AZDO_PROJECT
+// - GitHub issues: GITHUB_REPOSITORY
+async fn sync_trackers(alerts: &[Alert]) {
+    if jira::enabled()
+        && let Err(e) = jira::sync_issues(alerts).await
+    {
+        error!("Failed to sync Jira issues: {}", e);
+    }
+
+    if servicenow::enabled()
+        && let Err(e) = servicenow::file_incidents(alerts).await
+    {
+        error!("Failed to file ServiceNow incidents: {}", e);
+    }
+
+    if azure_devops::enabled()
+        && let Err(e) = azure_devops::sync_work_items(alerts).await
+    {
+        error!("Failed to sync Azure DevOps work items: {}", e);
+    }
+
+    if github::enabled()
+        && let Err(e) = github::sync_issues(alerts).await
+    {
+        error!("Failed to sync GitHub issues: {}", e);
+    }
+}
@@ -0,0 +1,106 @@
+use chrono::Utc;
+use log::info;
+
+use crate::alerts::{Alert, Severity, format_expiry};
+use crate::audit::{alert_findings, body_id, record_notification};
+use crate::config::{dry_run, http_client, setting};
+use crate::notifiers::alerts_at_or_above;
+use crate::stats::{NOTIFICATIONS_FAILED, NOTIFICATIONS_SENT, count};
+use crate::templates::portal_url;
+
+// Push notifications through ntfy are enabled by NTFY_TOPIC_URL, e.g.
+// https://ntfy.sh/contoso-credential-expiry or a topic on a self-hosted server. Protected
+// topics take NTFY_TOKEN (an access token) or NTFY_USER and NTFY_PASSWORD.
+// NTFY_MIN_SEVERITY leaves out less urgent applications.
+pub fn enabled() -> bool {
+    setting("NTFY_TOPIC_URL").is_some()
+}
+
+// ntfy priority from 1 (min) to 5 (max, which makes phones buzz through do-not-disturb).
+fn priority(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Critical => "5",
+        Severity::High => "4",
+        Severity::Medium => "3",
+        Severity::Low => "2",
+    }
+}
+
+fn render_message(alerts: &[&Alert]) -> String {
+    let now = Utc::now();
+    alerts
+        .iter()
+        .flat_map(|alert| {
+            alert.findings.iter().map(move |f| {
+                format!(
+                    "{}: {} {}",
+                    alert.name,
+                    f.credential,
+                    format_expiry(f.end_date_time, now)
+                )
+            })
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+// Publish a single notification summarising the findings to the ntfy topic.
+pub async fn publish(alerts: &[Alert]) -> anyhow::Result<()> {
+    let url =
+        setting("NTFY_TOPIC_URL").ok_or_else(|| anyhow::anyhow!("NTFY_TOPIC_URL is not set"))?;
+
+    let mut alerts = alerts_at_or_above(alerts, "NTFY_MIN_SEVERITY");
+    if alerts.is_empty() {
+        return Ok(());
+    }
+    alerts.sort_by(|a, b| b.severity().cmp(&a.severity()).then(a.name.cmp(&b.name)));
+
+    let severity = alerts[0].severity();
+    let title = format!("{} applications have expiring credentials", alerts.len());
+    let message = render_message(&alerts);
+
+    if dry_run() {
+        println!("[dry-run] Would publish to ntfy: {}", title);
+        println!("{}", message);
+        println!();
+        return Ok(());
+    }
+
+    let mut request = http_client()
+        .post(&url)
+        .header("Title", &title)
+        .header("Priority", priority(severity))
+        .header("Tags", "key,warning")
+        .body(message);
+    // With a single application, tapping the notification opens it in the portal.
+    if let [alert] = alerts.as_slice()
+        && let Some(app_id) = &alert.app_id
+    {
+        request = request.header("Click", portal_url(app_id));
+    }
+    if let Some(token) = setting("NTFY_TOKEN") {
+        request = request.bearer_auth(token);
+    } else if let Some(user) = setting("NTFY_USER") {
+        request = request.basic_auth(user, setting("NTFY_PASSWORD"));
+    }
+
+    match request.send().await.and_then(|r| r.error_for_status()) {
+        Ok(response) => {
+            count(&NOTIFICATIONS_SENT, 1);
+            info!("Published to ntfy: {}", title);
+            let message_id = response.text().await.ok().and_then(|body| body_id(&body));
+            record_notification(
+                "ntfy",
+                &[],
+                &alert_findings(alerts.iter().copied()),
+                message_id,
+            )
+            .await;
+            Ok(())
+        }
+        Err(e) => {
+            count(&NOTIFICATIONS_FAILED, 1);
+            anyhow::bail!("Publishing to ntfy failed: {}", e.without_url())
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use chrono::Utc;
+use log::error;
+
+use crate::alerts::{Alert, Category, Finding, Severity, format_expiry};
+use crate::config::setting;
+use crate::dedup::finding_key;
+use crate::notifiers::post_json_with_headers;
+use crate::templates::portal_url;
+
+const DEFAULT_API_URL: &str = "https://api.opsgenie.com";
+
+// Opsgenie alerts are enabled by OPSGENIE_API_KEY, the key of an API integration.
+// OPSGENIE_API_URL selects another instance, e.g. https://api.eu.opsgenie.com, and
+// OPSGENIE_MIN_SEVERITY leaves out less urgent credentials.
+pub fn enabled() -> bool {
+    setting("OPSGENIE_API_KEY").is_some()
+}
+
+// Opsgenie priority for a finding: expired credentials are P1, then by severity.
+fn priority(finding: &Finding) -> &'static str {
+    match (finding.category, finding.severity) {
+        (Category::Expired, _) | (_, Severity::Critical) => "P1",
+        (_, Severity::High) => "P2",
+        (_, Severity::Medium) => "P3",
+        (_, Severity::Low) => "P4",
+    }
+}
+
+fn alert_body(alert: &Alert, finding: &Finding) -> serde_json::Value {
+    let app_id = alert.app_id.as_deref().unwrap_or(&alert.name);
+    let key_id = finding.key_id.as_deref().unwrap_or(&finding.credential);
+
+    let mut message = format!(
+        "{} of {} {}",
+        finding.credential,
+        alert.name,
+        format_expiry(finding.end_date_time, Utc::now())
+    );
+    // Opsgenie truncates messages over 130 characters.
+    if message.chars().count() > 130 {
+        message = message.chars().take(127).collect::<String>() + "...";
+    }
+
+    let mut details = serde_json::json!({
+        "tenant": alert.tenant,
+        "application": alert.name,
+        "credential": finding.credential,
+        "expiry": finding.end_date_time.to_rfc3339(),
+        "severity": finding.severity.label(),
+        "owners": alert.owners.join(", ")
+    });
+    if let Some(id) = &alert.app_id {
+        details["appId"] = serde_json::Value::String(id.clone());
+        details["portal"] = serde_json::Value::String(portal_url(id));
+    }
+    if let Some(id) = &finding.key_id {
+        details["keyId"] = serde_json::Value::String(id.clone());
+    }
+
+    serde_json::json!({
+        "message": message,
+        // Opsgenie de-duplicates open alerts with the same alias.
+        "alias": format!("secret-manager:{}:{}:{}", alert.tenant, app_id, key_id),
+        "description": finding.summary(),
+        "priority": priority(finding),
+        "source": "secret-manager",
+        "entity": alert.name,
+        "tags": [
+            "secret-manager",
+            format!("appId:{}", app_id),
+            format!("tenant:{}", alert.tenant),
+            format!("severity:{}", finding.severity.label().to_lowercase())
+        ],
+        "details": details
+    })
+}
+
+// Create an Opsgenie alert for every finding at or above OPSGENIE_MIN_SEVERITY.
+pub async fn create_alerts(alerts: &[Alert]) -> anyhow::Result<()> {
+    let api_key = setting("OPSGENIE_API_KEY")
+        .ok_or_else(|| anyhow::anyhow!("OPSGENIE_API_KEY is not set"))?;
+    let url = format!(
+        "{}/v2/alerts",
+        setting("OPSGENIE_API_URL")
+            .unwrap_or_else(|| DEFAULT_API_URL.to_string())
+            .trim_end_matches('/')
+    );
+    let headers = [("Authorization".to_string(), format!("GenieKey {}", api_key))];
+    let min = setting("OPSGENIE_MIN_SEVERITY")
+        .and_then(|v| Severity::parse(&v).ok())
+        .unwrap_or(Severity::Low);
+
+    let mut sent = 0;
+    let mut failures = 0;
+    for alert in alerts {
+        for finding in alert.findings.iter().filter(|f| f.severity >= min) {
+            sent += 1;
+            let body = alert_body(alert, finding);
+            let findings = [finding_key(alert, finding)];
+            if let Err(e) =
+                post_json_with_headers("Opsgenie", &url, &headers, &body, &findings).await
+            {
+                error!("{}", e);
+                failures += 1;
+            }
+        }
+    }
+
+    if failures > 0 {
+        anyhow::bail!("{} of {} Opsgenie alerts failed", failures, sent);
+    }
+    Ok(())
+}
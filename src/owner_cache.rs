@@ -0,0 +1,101 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use chrono::{DateTime, Duration, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::config::setting;
+use crate::models::Owner;
+use crate::state::{self, OWNERS};
+
+const DEFAULT_TTL_HOURS: i64 = 24;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct CachedOwners {
+    fetched_at: DateTime<Utc>,
+    owners: Vec<Owner>,
+}
+
+// Owners by application object id, kept in the state store between runs. Owner sets rarely
+// change, so a daily run can skip the owner lookups of every application resolved within
+// OWNER_CACHE_HOURS (default 24; 0 turns the cache off). Applications a delta query reports
+// as changed are always looked up again. Without a state store nothing is cached.
+pub struct OwnerCache {
+    ttl: Option<Duration>,
+    entries: Mutex<HashMap<String, CachedOwners>>,
+    hits: AtomicUsize,
+}
+
+impl OwnerCache {
+    pub async fn load() -> OwnerCache {
+        let ttl = setting("OWNER_CACHE_HOURS")
+            .and_then(|h| h.trim().parse::<i64>().ok())
+            .unwrap_or(DEFAULT_TTL_HOURS);
+        let (ttl, entries) = match state::store() {
+            Some(store) if ttl > 0 => match state::load_all(store, OWNERS).await {
+                Ok(entries) => (Some(Duration::hours(ttl)), entries.into_iter().collect()),
+                Err(e) => {
+                    warn!("Failed to load the owner cache: {}", e);
+                    (None, HashMap::new())
+                }
+            },
+            _ => (None, HashMap::new()),
+        };
+        OwnerCache {
+            ttl,
+            entries: Mutex::new(entries),
+            hits: AtomicUsize::new(0),
+        }
+    }
+
+    fn enabled(&self) -> bool {
+        self.ttl.is_some()
+    }
+
+    // The cached owners of an application, unless they are older than the TTL.
+    pub async fn get(&self, id: &str) -> Option<Vec<Owner>> {
+        let ttl = self.ttl?;
+        let owners = self
+            .entries
+            .lock()
+            .await
+            .get(id)
+            .filter(|cached| Utc::now() - cached.fetched_at < ttl)
+            .map(|cached| cached.owners.clone());
+        if owners.is_some() {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        }
+        owners
+    }
+
+    // Whether owners kept elsewhere (the delta snapshot) are due to be looked up again.
+    pub async fn is_stale(&self, id: &str) -> bool {
+        self.enabled() && self.get(id).await.is_none()
+    }
+
+    // Remember freshly looked up owners.
+    pub async fn put(&self, id: &str, owners: &[Owner]) {
+        let Some(store) = state::store().filter(|_| self.enabled()) else {
+            return;
+        };
+        let cached = CachedOwners {
+            fetched_at: Utc::now(),
+            owners: owners.to_vec(),
+        };
+        if let Err(e) = state::save(store, OWNERS, id, &cached).await {
+            warn!("Failed to cache the owners of {}: {}", id, e);
+        }
+        self.entries.lock().await.insert(id.to_string(), cached);
+    }
+
+    pub fn log_hits(&self) {
+        if self.enabled() {
+            info!(
+                "Owners of {} applications taken from the owner cache",
+                self.hits.load(Ordering::Relaxed)
+            );
+        }
+    }
+}
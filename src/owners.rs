@@ -0,0 +1,255 @@
+use graph_rs_sdk::*;
+use log::info;
+
+use crate::config::flag_setting;
+use crate::models::{App, Owner};
+use crate::retry::{paging_with_retry, send_with_retry};
+
+// Owner fields requested wherever owners are listed.
+pub const OWNER_SELECT: &[&str] = &["id", "displayName", "mail", "userPrincipalName"];
+
+// List every owner of an application registration, following @odata.nextLink.
+pub async fn list_application_owners(client: &GraphClient, id: &str) -> anyhow::Result<Vec<Owner>> {
+    let pages = paging_with_retry(client, || {
+        client
+            .application(id)
+            .owners()
+            .list_owners()
+            .select(OWNER_SELECT)
+            .paging()
+            .json::<serde_json::Value>()
+    })
+    .await?;
+
+    Ok(pages
+        .into_iter()
+        .flat_map(|page| page.parse_items::<Owner>("owner"))
+        .collect())
+}
+
+// List every owner of a service principal, following @odata.nextLink.
+pub async fn list_service_principal_owners(
+    client: &GraphClient,
+    id: &str,
+) -> anyhow::Result<Vec<Owner>> {
+    let pages = paging_with_retry(client, || {
+        client
+            .service_principal(id)
+            .owners()
+            .list_owners()
+            .select(OWNER_SELECT)
+            .paging()
+            .json::<serde_json::Value>()
+    })
+    .await?;
+
+    Ok(pages
+        .into_iter()
+        .flat_map(|page| page.parse_items::<Owner>("owner"))
+        .collect())
+}
+
+// Replace group owners with the users that are (transitively) members of the group,
+// so notifications reach real mailboxes. Users already listed are not duplicated.
+pub async fn expand_group_owners(
+    client: &GraphClient,
+    owners: Vec<Owner>,
+) -> anyhow::Result<Vec<Owner>> {
+    if !owners.iter().any(|o| o.is_group()) {
+        return Ok(owners);
+    }
+
+    let mut expanded: Vec<Owner> = Vec::new();
+
+    for owner in owners {
+        if !owner.is_group() {
+            if !expanded.iter().any(|o| o.id == owner.id) {
+                expanded.push(owner);
+            }
+            continue;
+        }
+
+        info!(
+            "Expanding group owner '{}' to its members",
+            owner.displayName.as_deref().unwrap_or("No Name")
+        );
+
+        let members_response = paging_with_retry(client, || {
+            client
+                .group(&owner.id)
+                .transitive_members()
+                .list_transitive_members()
+                .select(OWNER_SELECT)
+                .paging()
+                .json::<serde_json::Value>()
+        })
+        .await?;
+
+        for page in members_response {
+            for member in page.parse_items::<Owner>("group member") {
+                // Nested groups are already flattened by transitiveMembers;
+                // devices and service principals have no mailbox.
+                if member.is_user() && !expanded.iter().any(|o| o.id == member.id) {
+                    expanded.push(member);
+                }
+            }
+        }
+    }
+
+    Ok(expanded)
+}
+
+// For owners without a mail or userPrincipalName (e.g. service accounts), look up their manager
+// (/users/{id}/manager) and notify them instead. Enabled with OWNER_MANAGER_FALLBACK=true.
+pub async fn add_manager_fallback(
+    client: &GraphClient,
+    owners: Vec<Owner>,
+) -> anyhow::Result<Vec<Owner>> {
+    if !flag_setting("OWNER_MANAGER_FALLBACK") {
+        return Ok(owners);
+    }
+
+    let mut resolved: Vec<Owner> = Vec::new();
+
+    for owner in owners {
+        if owner.mail.is_some() || owner.userPrincipalName.is_some() || !owner.is_user() {
+            resolved.push(owner);
+            continue;
+        }
+
+        let manager_response = send_with_retry(|| {
+            client
+                .user(&owner.id)
+                .get_manager()
+                .select(&["id", "displayName", "mail", "userPrincipalName"])
+                .send()
+        })
+        .await?;
+
+        match manager_response.json::<Owner>().await {
+            Ok(manager) => {
+                info!(
+                    "Owner '{}' has no contact info, falling back to manager '{}'",
+                    owner.displayName.as_deref().unwrap_or("No Name"),
+                    manager.displayName.as_deref().unwrap_or("No Name")
+                );
+                if !resolved.iter().any(|o| o.id == manager.id) {
+                    resolved.push(manager);
+                }
+            }
+            Err(_) => {
+                info!(
+                    "No manager found for owner '{}'",
+                    owner.displayName.as_deref().unwrap_or("No Name")
+                );
+                resolved.push(owner);
+            }
+        }
+    }
+
+    Ok(resolved)
+}
+
+// The email address of a user's manager, looked up by the user's id or userPrincipalName.
+// Returns None if the user has no manager or it has no address.
+pub async fn get_manager_email(client: &GraphClient, user: &str) -> anyhow::Result<Option<String>> {
+    let response = send_with_retry(|| {
+        client
+            .user(user)
+            .get_manager()
+            .select(&["id", "displayName", "mail", "userPrincipalName"])
+            .send()
+    })
+    .await?;
+    if !response.status().is_success() {
+        return Ok(None);
+    }
+
+    let manager: Owner = response.json().await?;
+    Ok(manager.mail.or(manager.userPrincipalName))
+}
+
+// Turn the raw owners list into notifiable owners: groups expanded to members,
+// then the optional manager fallback applied.
+pub async fn resolve_owners(
+    client: &GraphClient,
+    owners: Vec<Owner>,
+) -> anyhow::Result<Vec<Owner>> {
+    let owners = expand_group_owners(client, owners).await?;
+    add_manager_fallback(client, owners).await
+}
+
+// Look up the service principal for an appId and return its owners.
+// Used when an app registration has no owners of its own.
+pub async fn get_service_principal_owners_by_app_id(
+    client: &GraphClient,
+    app_id: &str,
+) -> anyhow::Result<Vec<Owner>> {
+    let service_principal_response = send_with_retry(|| {
+        client
+            .service_principals()
+            .list_service_principal()
+            .filter(&[&format!("appId eq '{}'", app_id)])
+            .select(&["id"])
+            .send()
+    })
+    .await?
+    .json::<serde_json::Value>()
+    .await?;
+
+    let Some(sp_id) = service_principal_response["value"]
+        .as_array()
+        .and_then(|v| v.first())
+        .and_then(|sp| sp["id"].as_str())
+    else {
+        return Ok(Vec::new());
+    };
+
+    list_service_principal_owners(client, sp_id).await
+}
+
+// Fetch and resolve the owners of an application registration, falling back to the owners
+// of its service principal when the registration has none.
+// Returns None if the owners response could not be parsed.
+pub async fn get_application_owners(
+    client: &GraphClient,
+    app: &App,
+) -> anyhow::Result<Option<Vec<Owner>>> {
+    let owners = match list_application_owners(client, &app.id).await {
+        Ok(o) => o,
+        Err(e) => {
+            info!(
+                "Failed to read owners for application '{:?}': {}. Skipping.",
+                app.displayName, e
+            );
+            return Ok(None);
+        }
+    };
+
+    Ok(Some(
+        complete_application_owners(client, app, owners).await?,
+    ))
+}
+
+// Resolve the owners of an application registration, falling back to the owners of its
+// service principal when the registration has none.
+pub async fn complete_application_owners(
+    client: &GraphClient,
+    app: &App,
+    mut owners: Vec<Owner>,
+) -> anyhow::Result<Vec<Owner>> {
+    // Many app registrations have no owners while their service principal does.
+    if owners.is_empty()
+        && let Some(app_id) = &app.appId
+    {
+        owners = get_service_principal_owners_by_app_id(client, app_id).await?;
+        if !owners.is_empty() {
+            info!(
+                "Using service principal owners for application '{:?}'",
+                app.displayName
+            );
+        }
+    }
+
+    resolve_owners(client, owners).await
+}
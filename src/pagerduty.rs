@@ -0,0 +1,106 @@
+use chrono::Utc;
+use log::error;
+
+use crate::alerts::Alert;
+use crate::audit::record_finding;
+use crate::config::{dry_run, setting};
+use crate::notifiers::post_json;
+use crate::report::{FindingRecord, finding_records};
+use crate::templates::portal_url;
+
+const EVENTS_URL: &str = "https://events.pagerduty.com/v2/enqueue";
+
+// Credentials expiring within this many days (or already expired) page on-call.
+const DEFAULT_PAGE_DAYS: i64 = 7;
+
+// PagerDuty paging is enabled by PAGERDUTY_ROUTING_KEY, the integration key of an
+// Events API v2 integration. PAGERDUTY_DAYS changes the 7 day paging window.
+pub fn enabled() -> bool {
+    setting("PAGERDUTY_ROUTING_KEY").is_some()
+}
+
+fn page_days() -> i64 {
+    setting("PAGERDUTY_DAYS")
+        .and_then(|d| d.trim().parse().ok())
+        .unwrap_or(DEFAULT_PAGE_DAYS)
+}
+
+// One incident per credential: PagerDuty folds repeated triggers with the same key into
+// the open incident, so daily runs don't page again for the same secret.
+fn dedup_key(record: &FindingRecord) -> String {
+    format!(
+        "secret-manager:{}:{}:{}",
+        record.tenant,
+        record.appId.as_deref().unwrap_or(&record.application),
+        record.keyId.as_deref().unwrap_or(&record.credential)
+    )
+}
+
+fn event(routing_key: &str, record: &FindingRecord) -> anyhow::Result<serde_json::Value> {
+    let summary = if record.category == "expired" {
+        format!(
+            "{} of {} has expired ({})",
+            record.credential, record.application, record.tenant
+        )
+    } else {
+        format!(
+            "{} of {} expires in {} days ({})",
+            record.credential, record.application, record.daysRemaining, record.tenant
+        )
+    };
+
+    let mut event = serde_json::json!({
+        "routing_key": routing_key,
+        "event_action": "trigger",
+        "dedup_key": dedup_key(record),
+        "payload": {
+            "summary": summary,
+            "source": "secret-manager",
+            "severity": if record.category == "expired" { "critical" } else { "error" },
+            "component": record.application,
+            "group": record.tenant,
+            "class": "credential-expiry",
+            "custom_details": serde_json::to_value(record)?
+        }
+    });
+    if let Some(app_id) = &record.appId {
+        event["links"] = serde_json::json!([{
+            "href": portal_url(app_id),
+            "text": "Application credentials"
+        }]);
+    }
+    Ok(event)
+}
+
+// Trigger a PagerDuty event for every credential expired or expiring within the paging
+// window.
+pub async fn trigger_events(alerts: &[Alert]) -> anyhow::Result<()> {
+    let routing_key = setting("PAGERDUTY_ROUTING_KEY")
+        .ok_or_else(|| anyhow::anyhow!("PAGERDUTY_ROUTING_KEY is not set"))?;
+    let days = page_days();
+
+    let records: Vec<FindingRecord> = finding_records(alerts, Utc::now())
+        .into_iter()
+        .filter(|r| r.daysRemaining < days)
+        .collect();
+
+    let mut failures = 0;
+    for record in &records {
+        // Keep the routing key out of dry-run output.
+        let key = if dry_run() {
+            "<redacted>"
+        } else {
+            &routing_key
+        };
+        let findings = [record_finding(record)];
+        if let Err(e) = post_json("PagerDuty", EVENTS_URL, &event(key, record)?, &findings).await {
+            error!("{}", e);
+            failures += 1;
+        }
+    }
+
+    if failures > 0 {
+        anyhow::bail!("{} of {} PagerDuty events failed", failures, records.len());
+    }
+    Ok(())
+}
@@ -0,0 +1,199 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use log::{info, warn};
+use postgres_native_tls::MakeTlsConnector;
+use tokio::sync::Mutex;
+use tokio_postgres::Client;
+
+use crate::state::{LOCKS, Lock, StateStore};
+
+// Schema changes, applied in order like the SQLite backend's. The version is kept in
+// state_schema; an advisory lock keeps instances starting together from migrating at once.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS state (
+         namespace TEXT NOT NULL,
+         key TEXT NOT NULL,
+         value JSONB NOT NULL,
+         updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+         PRIMARY KEY (namespace, key)
+     );",
+    "CREATE INDEX IF NOT EXISTS state_updated_at ON state (namespace, updated_at);",
+];
+// Any fixed number, shared by every instance.
+const MIGRATION_LOCK: i64 = 0x5ec7e7;
+
+// STATE_BACKEND=postgres: a `state` table in the PostgreSQL database at the postgres:// URL
+// in STATE_PATH (sslmode=require etc. in the URL as usual). Documents are JSONB, so the
+// history and audit log can be queried and reported on directly, and any number of
+// instances can write concurrently.
+pub struct PostgresStore {
+    url: String,
+    tls: MakeTlsConnector,
+    client: Mutex<Arc<Client>>,
+}
+
+async fn connect(url: &str, tls: &MakeTlsConnector) -> anyhow::Result<Client> {
+    let (client, connection) = tokio_postgres::connect(url, tls.clone())
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to connect to PostgreSQL: {}", e))?;
+    // The connection does the actual I/O and runs until the client is dropped or the
+    // server goes away.
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            warn!("PostgreSQL connection closed: {}", e);
+        }
+    });
+    Ok(client)
+}
+
+async fn migrate(client: &mut Client) -> anyhow::Result<()> {
+    let transaction = client.transaction().await?;
+    transaction
+        .execute("SELECT pg_advisory_xact_lock($1)", &[&MIGRATION_LOCK])
+        .await?;
+    transaction
+        .batch_execute("CREATE TABLE IF NOT EXISTS state_schema (version INTEGER NOT NULL)")
+        .await?;
+    let version: i32 = transaction
+        .query_opt("SELECT version FROM state_schema", &[])
+        .await?
+        .map(|row| row.get(0))
+        .unwrap_or(0);
+    if version as usize > MIGRATIONS.len() {
+        anyhow::bail!(
+            "State database is at schema version {}, newer than this release supports ({}); \
+             upgrade secret-manager",
+            version,
+            MIGRATIONS.len()
+        );
+    }
+    for migration in &MIGRATIONS[version as usize..] {
+        transaction.batch_execute(migration).await?;
+    }
+    if version as usize != MIGRATIONS.len() {
+        let latest = MIGRATIONS.len() as i32;
+        transaction.execute("DELETE FROM state_schema", &[]).await?;
+        transaction
+            .execute("INSERT INTO state_schema (version) VALUES ($1)", &[&latest])
+            .await?;
+        info!("Upgraded the state database to schema version {}", latest);
+    }
+    transaction.commit().await?;
+    Ok(())
+}
+
+impl PostgresStore {
+    pub async fn open(url: &str) -> anyhow::Result<PostgresStore> {
+        let tls = MakeTlsConnector::new(native_tls::TlsConnector::new()?);
+        let mut client = connect(url, &tls).await?;
+        migrate(&mut client).await?;
+        Ok(PostgresStore {
+            url: url.to_string(),
+            tls,
+            client: Mutex::new(Arc::new(client)),
+        })
+    }
+
+    // The client, reconnecting first if the server closed the connection, e.g. after a
+    // restart or failover while the daemon was idle.
+    async fn client(&self) -> anyhow::Result<Arc<Client>> {
+        let mut client = self.client.lock().await;
+        if client.is_closed() {
+            info!("Reconnecting to PostgreSQL");
+            *client = Arc::new(connect(&self.url, &self.tls).await?);
+        }
+        Ok(client.clone())
+    }
+}
+
+#[async_trait]
+impl StateStore for PostgresStore {
+    async fn get(&self, namespace: &str, key: &str) -> anyhow::Result<Option<serde_json::Value>> {
+        Ok(self
+            .client()
+            .await?
+            .query_opt(
+                "SELECT value FROM state WHERE namespace = $1 AND key = $2",
+                &[&namespace, &key],
+            )
+            .await?
+            .map(|row| row.get(0)))
+    }
+
+    async fn put(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        self.client()
+            .await?
+            .execute(
+                "INSERT INTO state (namespace, key, value, updated_at)
+                 VALUES ($1, $2, $3, now())
+                 ON CONFLICT (namespace, key)
+                 DO UPDATE SET value = excluded.value, updated_at = excluded.updated_at",
+                &[&namespace, &key, value],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn delete(&self, namespace: &str, key: &str) -> anyhow::Result<()> {
+        self.client()
+            .await?
+            .execute(
+                "DELETE FROM state WHERE namespace = $1 AND key = $2",
+                &[&namespace, &key],
+            )
+            .await?;
+        Ok(())
+    }
+
+    // Inserted, or taken over once expired, in a single statement; no row is written when
+    // another holder still has it.
+    async fn try_lock(&self, name: &str, lock: &Lock) -> anyhow::Result<bool> {
+        let taken = self
+            .client()
+            .await?
+            .execute(
+                "INSERT INTO state (namespace, key, value, updated_at)
+                 VALUES ($1, $2, $3, now())
+                 ON CONFLICT (namespace, key)
+                 DO UPDATE SET value = excluded.value, updated_at = excluded.updated_at
+                 WHERE state.value->>'holder' = $4
+                    OR (state.value->>'expires')::timestamptz <= now()",
+                &[&LOCKS, &name, &serde_json::to_value(lock)?, &lock.holder],
+            )
+            .await?;
+        Ok(taken == 1)
+    }
+
+    async fn unlock(&self, name: &str, holder: &str) -> anyhow::Result<()> {
+        self.client()
+            .await?
+            .execute(
+                "DELETE FROM state WHERE namespace = $1 AND key = $2 AND value->>'holder' = $3",
+                &[&LOCKS, &name, &holder],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn list(&self, namespace: &str) -> anyhow::Result<Vec<(String, serde_json::Value)>> {
+        // COLLATE "C" orders keys bytewise, like the other backends, whatever the database's
+        // collation.
+        Ok(self
+            .client()
+            .await?
+            .query(
+                "SELECT key, value FROM state WHERE namespace = $1 ORDER BY key COLLATE \"C\"",
+                &[&namespace],
+            )
+            .await?
+            .into_iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect())
+    }
+}
@@ -0,0 +1,70 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use log::info;
+
+use crate::config::setting;
+
+// Seconds between progress lines, from PROGRESS_INTERVAL_SECS (default 10, 0 disables them).
+fn progress_interval() -> Option<Duration> {
+    let secs = setting("PROGRESS_INTERVAL_SECS")
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(10);
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+// Periodic progress lines for a long running step over a known number of items, so a scan
+// of a large tenant doesn't go silent for minutes. Safe to share between concurrent tasks.
+pub struct Progress {
+    label: String,
+    total: usize,
+    done: AtomicUsize,
+    interval: Option<Duration>,
+    started: Instant,
+    last_report: Mutex<Instant>,
+}
+
+impl Progress {
+    pub fn new(label: &str, total: usize) -> Progress {
+        let now = Instant::now();
+        Progress {
+            label: label.to_string(),
+            total,
+            done: AtomicUsize::new(0),
+            interval: progress_interval(),
+            started: now,
+            last_report: Mutex::new(now),
+        }
+    }
+
+    // Count one finished item, logging progress if the interval has passed.
+    pub fn inc(&self) {
+        let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+        let Some(interval) = self.interval else {
+            return;
+        };
+
+        let mut last_report = self.last_report.lock().unwrap();
+        if last_report.elapsed() >= interval {
+            *last_report = Instant::now();
+            info!(
+                "{}: {}/{} ({}%)",
+                self.label,
+                done,
+                self.total,
+                done * 100 / self.total.max(1)
+            );
+        }
+    }
+
+    pub fn finish(&self) {
+        info!(
+            "{}: {}/{} done in {:.1?}",
+            self.label,
+            self.done.load(Ordering::Relaxed),
+            self.total,
+            self.started.elapsed()
+        );
+    }
+}
@@ -0,0 +1,117 @@
+use async_trait::async_trait;
+use redis::AsyncCommands;
+use redis::aio::MultiplexedConnection;
+
+use crate::config::setting;
+use crate::state::{Lock, StateStore};
+
+// STATE_BACKEND=redis: a Redis hash per namespace, named "{prefix}:{namespace}" with
+// STATE_REDIS_PREFIX (default secret-manager), at the redis:// or rediss:// URL in
+// STATE_PATH. Lets several scanner instances, e.g. one per region, share notification
+// state.
+pub struct RedisStore {
+    connection: MultiplexedConnection,
+    prefix: String,
+}
+
+impl RedisStore {
+    pub async fn open(url: &str) -> anyhow::Result<RedisStore> {
+        let client = redis::Client::open(url)
+            .map_err(|e| anyhow::anyhow!("Invalid Redis URL in STATE_PATH: {}", e))?;
+        let connection = client
+            .get_multiplexed_async_connection()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to connect to Redis: {}", e))?;
+        Ok(RedisStore {
+            connection,
+            prefix: setting("STATE_REDIS_PREFIX").unwrap_or_else(|| "secret-manager".to_string()),
+        })
+    }
+
+    fn hash(&self, namespace: &str) -> String {
+        format!("{}:{}", self.prefix, namespace)
+    }
+
+    fn lock_key(&self, name: &str) -> String {
+        format!("{}:lock:{}", self.prefix, name)
+    }
+
+    // The multiplexed connection is cheap to clone and every clone shares the same socket.
+    fn connection(&self) -> MultiplexedConnection {
+        self.connection.clone()
+    }
+}
+
+#[async_trait]
+impl StateStore for RedisStore {
+    async fn get(&self, namespace: &str, key: &str) -> anyhow::Result<Option<serde_json::Value>> {
+        let value: Option<String> = self.connection().hget(self.hash(namespace), key).await?;
+        Ok(match value {
+            Some(value) => Some(serde_json::from_str(&value)?),
+            None => None,
+        })
+    }
+
+    async fn put(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        let _: () = self
+            .connection()
+            .hset(self.hash(namespace), key, value.to_string())
+            .await?;
+        Ok(())
+    }
+
+    async fn delete(&self, namespace: &str, key: &str) -> anyhow::Result<()> {
+        let _: () = self.connection().hdel(self.hash(namespace), key).await?;
+        Ok(())
+    }
+
+    // Locks are plain keys, "{prefix}:lock:{name}", set only if absent and expiring on
+    // their own.
+    async fn try_lock(&self, name: &str, lock: &Lock) -> anyhow::Result<bool> {
+        let ttl = (lock.expires - chrono::Utc::now())
+            .num_milliseconds()
+            .max(1);
+        let taken: Option<String> = redis::cmd("SET")
+            .arg(self.lock_key(name))
+            .arg(&lock.holder)
+            .arg("NX")
+            .arg("PX")
+            .arg(ttl)
+            .query_async(&mut self.connection())
+            .await?;
+        Ok(taken.is_some())
+    }
+
+    async fn unlock(&self, name: &str, holder: &str) -> anyhow::Result<()> {
+        // Compare and delete in one step, so a lock that expired and was taken by another
+        // instance isn't released.
+        let _: i64 = redis::Script::new(
+            "if redis.call('GET', KEYS[1]) == ARGV[1] then \
+                 return redis.call('DEL', KEYS[1]) \
+             else \
+                 return 0 \
+             end",
+        )
+        .key(self.lock_key(name))
+        .arg(holder)
+        .invoke_async(&mut self.connection())
+        .await?;
+        Ok(())
+    }
+
+    async fn list(&self, namespace: &str) -> anyhow::Result<Vec<(String, serde_json::Value)>> {
+        let entries: Vec<(String, String)> =
+            self.connection().hgetall(self.hash(namespace)).await?;
+        let mut documents = entries
+            .into_iter()
+            .map(|(key, value)| Ok((key, serde_json::from_str(&value)?)))
+            .collect::<anyhow::Result<Vec<(String, serde_json::Value)>>>()?;
+        documents.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(documents)
+    }
+}
@@ -0,0 +1,490 @@
+use chrono::{DateTime, Utc};
+use log::info;
+use rust_xlsxwriter::{Format, Workbook};
+use serde::Serialize;
+use std::io::Write;
+
+use crate::alerts::{Alert, Category, Severity, Thresholds, format_timestamp};
+use crate::cli::{ReportArgs, ReportFormat};
+use crate::email::render_alerts;
+
+// One expiring or expired credential, flattened for machine-readable output. Fields are
+// named as in the JSON output, which follows Graph's camelCase.
+#[derive(Serialize, Debug)]
+#[allow(non_snake_case)]
+pub struct FindingRecord {
+    pub tenant: String,
+    pub application: String,
+    pub appId: Option<String>,
+    pub credential: String,
+    pub keyId: Option<String>,
+    pub hint: Option<String>,
+    pub category: &'static str,
+    pub severity: &'static str,
+    pub expiry: DateTime<Utc>,
+    pub daysRemaining: i64,
+    pub tier: Option<i64>,
+    pub owners: Vec<String>,
+    pub description: String,
+    pub acknowledgment: Option<String>,
+}
+
+// Flatten alerts into one record per finding, in alert order.
+pub fn finding_records<'a>(
+    alerts: impl IntoIterator<Item = &'a Alert>,
+    now: DateTime<Utc>,
+) -> Vec<FindingRecord> {
+    alerts
+        .into_iter()
+        .flat_map(|alert| {
+            alert.findings.iter().map(move |finding| FindingRecord {
+                tenant: alert.tenant.clone(),
+                application: alert.name.clone(),
+                appId: alert.app_id.clone(),
+                credential: finding.credential.clone(),
+                keyId: finding.key_id.clone(),
+                hint: finding.hint.clone(),
+                category: match finding.category {
+                    Category::Expired => "expired",
+                    Category::ExpiringSoon => "expiringSoon",
+                },
+                severity: finding.severity.label(),
+                expiry: finding.end_date_time,
+                daysRemaining: finding.days_remaining(now),
+                tier: finding.tier,
+                owners: alert.owners.clone(),
+                description: finding.description.clone(),
+                acknowledgment: finding.acknowledgment.clone(),
+            })
+        })
+        .collect()
+}
+
+// Print the findings as a JSON array on stdout. Logs go to stderr, so stdout stays parseable.
+pub fn print_json(alerts: &[Alert]) -> anyhow::Result<()> {
+    let records = finding_records(alerts, Utc::now());
+    println!("{}", serde_json::to_string_pretty(&records)?);
+    Ok(())
+}
+
+// One row per credential: tenant, application, appId, credential type, keyId, hint,
+// expiry, days remaining, severity, owner emails and acknowledgment.
+pub fn render_csv(alerts: &[Alert]) -> anyhow::Result<String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record([
+        "Tenant",
+        "Application",
+        "App ID",
+        "Credential",
+        "Key ID",
+        "Hint",
+        "Expiry",
+        "Days Remaining",
+        "Severity",
+        "Owners",
+        "Acknowledgment",
+    ])?;
+
+    for record in finding_records(alerts, Utc::now()) {
+        writer.write_record([
+            record.tenant,
+            record.application,
+            record.appId.unwrap_or_default(),
+            record.credential,
+            record.keyId.unwrap_or_default(),
+            record.hint.unwrap_or_default(),
+            record.expiry.to_rfc3339(),
+            record.daysRemaining.to_string(),
+            record.severity.to_string(),
+            record.owners.join("; "),
+            record.acknowledgment.unwrap_or_default(),
+        ])?;
+    }
+
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+// Escape text for inclusion in HTML (and XML).
+pub fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+// Row class by days remaining: expired, within a week, within a month, later.
+fn html_row_class(days_remaining: i64) -> &'static str {
+    match days_remaining {
+        ..0 => "expired",
+        0..=7 => "critical",
+        8..=30 => "warning",
+        _ => "notice",
+    }
+}
+
+const HTML_STYLE: &str = r#"
+body { font-family: Segoe UI, Helvetica, Arial, sans-serif; margin: 2em; color: #222; }
+table { border-collapse: collapse; width: 100%; }
+th, td { border: 1px solid #ccc; padding: 6px 8px; text-align: left; vertical-align: top; }
+th { background: #f0f0f0; cursor: pointer; user-select: none; }
+tr.expired td { background: #f8d0d0; }
+tr.critical td { background: #fde2c4; }
+tr.warning td { background: #fff4c2; }
+tr.notice td { background: #ffffff; }
+.summary span { margin-right: 1.5em; }
+"#;
+
+// Click a header to sort by that column; numeric columns sort numerically.
+const HTML_SCRIPT: &str = r#"
+document.querySelectorAll("th").forEach((th, column) => {
+  th.addEventListener("click", () => {
+    const body = th.closest("table").tBodies[0];
+    const ascending = th.dataset.order !== "asc";
+    th.dataset.order = ascending ? "asc" : "desc";
+    const value = (row) => row.cells[column].dataset.sort ?? row.cells[column].textContent;
+    const rows = Array.from(body.rows).sort((a, b) => {
+      const x = value(a), y = value(b);
+      const cmp = isNaN(x) || isNaN(y) ? x.localeCompare(y) : x - y;
+      return ascending ? cmp : -cmp;
+    });
+    rows.forEach((row) => body.appendChild(row));
+  });
+});
+"#;
+
+// A standalone HTML page with one table row per credential, soonest expiry first.
+pub fn render_html(alerts: &[Alert]) -> String {
+    let now = Utc::now();
+    let mut records = finding_records(alerts, now);
+    records.sort_by_key(|r| r.expiry);
+
+    let expired = records.iter().filter(|r| r.category == "expired").count();
+    let applications = alerts.len();
+
+    let rows = records
+        .iter()
+        .map(|r| {
+            format!(
+                "<tr class=\"{}\"><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td data-sort=\"{}\">{}</td><td data-sort=\"{}\">{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                html_row_class(r.daysRemaining),
+                html_escape(&r.tenant),
+                html_escape(&r.application),
+                html_escape(r.appId.as_deref().unwrap_or("")),
+                html_escape(&r.credential),
+                html_escape(r.keyId.as_deref().unwrap_or("")),
+                html_escape(r.hint.as_deref().unwrap_or("")),
+                r.expiry.timestamp(),
+                format_timestamp(r.expiry),
+                r.daysRemaining,
+                r.daysRemaining,
+                r.severity,
+                html_escape(&if r.owners.is_empty() {
+                    "None".to_string()
+                } else {
+                    r.owners.join(", ")
+                }),
+                html_escape(r.acknowledgment.as_deref().unwrap_or("")),
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>Expiring Credentials Report</title>
+<style>{style}</style>
+</head>
+<body>
+<h1>Expiring Credentials Report</h1>
+<p>Generated {generated}</p>
+<p class="summary"><span>Applications: {applications}</span><span>Credentials: {credentials}</span><span>Expired: {expired}</span></p>
+<table>
+<thead>
+<tr><th>Tenant</th><th>Application</th><th>App ID</th><th>Credential</th><th>Key ID</th><th>Hint</th><th>Expiry</th><th>Days Remaining</th><th>Severity</th><th>Owners</th><th>Acknowledgment</th></tr>
+</thead>
+<tbody>
+{rows}
+</tbody>
+</table>
+<script>{script}</script>
+</body>
+</html>
+"#,
+        style = HTML_STYLE,
+        generated = format_timestamp(now),
+        applications = applications,
+        credentials = records.len(),
+        expired = expired,
+        rows = rows,
+        script = HTML_SCRIPT,
+    )
+}
+
+// Escape characters that would break a Markdown table cell.
+pub fn markdown_cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+// A Markdown summary with a section and table per application, expired credentials first.
+pub fn render_markdown(alerts: &[Alert]) -> String {
+    let now = Utc::now();
+    let mut content = String::from("# Expiring Credentials\n\n");
+
+    let expired = alerts
+        .iter()
+        .filter(|a| a.has_category(Category::Expired))
+        .count();
+    content.push_str(&format!(
+        "{} applications need attention, {} with expired credentials. Generated {}.\n",
+        alerts.len(),
+        expired,
+        format_timestamp(now)
+    ));
+
+    let mut sorted: Vec<&Alert> = alerts.iter().collect();
+    sorted.sort_by_key(|a| {
+        (
+            !a.has_category(Category::Expired),
+            a.findings.iter().map(|f| f.end_date_time).min(),
+        )
+    });
+
+    for alert in sorted {
+        content.push_str(&format!("\n## {}\n\n", markdown_cell(&alert.name)));
+        if !alert.tenant.is_empty() {
+            content.push_str(&format!("- Tenant: {}\n", alert.tenant));
+        }
+        if let Some(app_id) = &alert.app_id {
+            content.push_str(&format!("- App ID: `{}`\n", app_id));
+        }
+        content.push_str(&format!(
+            "- Owners: {}\n\n",
+            if alert.owners.is_empty() {
+                "None".to_string()
+            } else {
+                alert.owners.join(", ")
+            }
+        ));
+
+        content.push_str(
+            "| Credential | Key ID | Hint | Expiry | Days Remaining | Severity | Acknowledgment |\n",
+        );
+        content.push_str("|---|---|---|---|---:|---|---|\n");
+        for finding in &alert.findings {
+            content.push_str(&format!(
+                "| {} | {} | {} | {} | {} | {} | {} |\n",
+                markdown_cell(&finding.credential),
+                finding
+                    .key_id
+                    .as_deref()
+                    .map(markdown_cell)
+                    .unwrap_or_default(),
+                finding
+                    .hint
+                    .as_deref()
+                    .map(markdown_cell)
+                    .unwrap_or_default(),
+                format_timestamp(finding.end_date_time),
+                finding.days_remaining(now),
+                finding.severity.label(),
+                finding
+                    .acknowledgment
+                    .as_deref()
+                    .map(markdown_cell)
+                    .unwrap_or_default()
+            ));
+        }
+    }
+
+    content
+}
+
+// Excel limits sheet names to 31 characters and forbids a few punctuation characters.
+fn sheet_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            '[' | ']' | ':' | '*' | '?' | '/' | '\\' => '_',
+            c => c,
+        })
+        .take(31)
+        .collect();
+    if name.is_empty() {
+        "Tenant".to_string()
+    } else {
+        name
+    }
+}
+
+// An Excel workbook with a summary sheet of counts by severity per tenant, followed by one
+// sheet per tenant listing its credentials.
+pub fn render_xlsx(alerts: &[Alert]) -> anyhow::Result<Vec<u8>> {
+    let now = Utc::now();
+    let records = finding_records(alerts, now);
+    let bold = Format::new().set_bold();
+    let date = Format::new().set_num_format("yyyy-mm-dd hh:mm");
+
+    let mut tenants: Vec<&str> = records.iter().map(|r| r.tenant.as_str()).collect();
+    tenants.sort_unstable();
+    tenants.dedup();
+
+    let mut workbook = Workbook::new();
+
+    let summary = workbook.add_worksheet();
+    summary.set_name("Summary")?;
+    summary.write_string_with_format(0, 0, "Tenant", &bold)?;
+    for (col, severity) in Severity::ALL.iter().enumerate() {
+        summary.write_string_with_format(0, col as u16 + 1, severity.label(), &bold)?;
+    }
+    let total_col = Severity::ALL.len() as u16 + 1;
+    summary.write_string_with_format(0, total_col, "Total", &bold)?;
+
+    for (row, tenant) in tenants.iter().enumerate() {
+        let row = row as u32 + 1;
+        let in_tenant = || records.iter().filter(|r| r.tenant == *tenant);
+        summary.write_string(row, 0, *tenant)?;
+        for (col, severity) in Severity::ALL.iter().enumerate() {
+            let count = in_tenant()
+                .filter(|r| r.severity == severity.label())
+                .count();
+            summary.write_number(row, col as u16 + 1, count as f64)?;
+        }
+        summary.write_number(row, total_col, in_tenant().count() as f64)?;
+    }
+    summary.autofit();
+
+    let headings = [
+        "Application",
+        "App ID",
+        "Credential",
+        "Key ID",
+        "Hint",
+        "Expiry",
+        "Days Remaining",
+        "Severity",
+        "Owners",
+        "Acknowledgment",
+    ];
+
+    for tenant in &tenants {
+        let sheet = workbook.add_worksheet();
+        sheet.set_name(sheet_name(tenant))?;
+        for (col, heading) in headings.iter().enumerate() {
+            sheet.write_string_with_format(0, col as u16, *heading, &bold)?;
+        }
+
+        for (row, record) in records.iter().filter(|r| r.tenant == *tenant).enumerate() {
+            let row = row as u32 + 1;
+            sheet.write_string(row, 0, &record.application)?;
+            sheet.write_string(row, 1, record.appId.as_deref().unwrap_or(""))?;
+            sheet.write_string(row, 2, &record.credential)?;
+            sheet.write_string(row, 3, record.keyId.as_deref().unwrap_or(""))?;
+            sheet.write_string(row, 4, record.hint.as_deref().unwrap_or(""))?;
+            sheet.write_datetime_with_format(row, 5, record.expiry.naive_utc(), &date)?;
+            sheet.write_number(row, 6, record.daysRemaining as f64)?;
+            sheet.write_string(row, 7, record.severity)?;
+            sheet.write_string(row, 8, record.owners.join("; "))?;
+            sheet.write_string(row, 9, record.acknowledgment.as_deref().unwrap_or(""))?;
+        }
+        sheet.autofit();
+    }
+
+    Ok(workbook.save_to_buffer()?)
+}
+
+// JUnit XML with one test suite per tenant and one failing test case per application with
+// expiring or expired credentials, so CI systems render the scan as a test report.
+// A clean scan produces a single passing test case.
+pub fn render_junit(alerts: &[Alert]) -> String {
+    let now = Utc::now();
+
+    let mut tenants: Vec<&str> = alerts.iter().map(|a| a.tenant.as_str()).collect();
+    tenants.sort_unstable();
+    tenants.dedup();
+
+    let mut suites: Vec<String> = Vec::new();
+    for tenant in &tenants {
+        let cases = alerts
+            .iter()
+            .filter(|a| a.tenant == *tenant)
+            .map(|alert| {
+                let category = if alert.has_category(Category::Expired) {
+                    Category::Expired
+                } else {
+                    Category::ExpiringSoon
+                };
+                let details = alert
+                    .findings
+                    .iter()
+                    .map(|f| format!("{} ({} days remaining)", f.summary(), f.days_remaining(now)))
+                    .collect::<Vec<String>>()
+                    .join("\n");
+                format!(
+                    "    <testcase classname=\"{}\" name=\"{}\">\n      <failure type=\"{}\" message=\"{} credential(s) need attention\">{}</failure>\n    </testcase>",
+                    html_escape(tenant),
+                    html_escape(&alert.name),
+                    category.heading(),
+                    alert.findings.len(),
+                    html_escape(&details)
+                )
+            })
+            .collect::<Vec<String>>();
+
+        suites.push(format!(
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\">\n{}\n  </testsuite>",
+            html_escape(tenant),
+            cases.len(),
+            cases.len(),
+            cases.join("\n")
+        ));
+    }
+
+    if suites.is_empty() {
+        suites.push(
+            "  <testsuite name=\"secret-manager\" tests=\"1\" failures=\"0\">\n    <testcase classname=\"secret-manager\" name=\"No expiring credentials\"/>\n  </testsuite>".to_string(),
+        );
+    }
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites name=\"Expiring Credentials\" tests=\"{}\" failures=\"{}\">\n{}\n</testsuites>\n",
+        alerts.len().max(1),
+        alerts.len(),
+        suites.join("\n")
+    )
+}
+
+// Render the report in the requested format and write it to --out, or stdout.
+pub fn write_report(
+    alerts: &[Alert],
+    thresholds: &Thresholds,
+    args: &ReportArgs,
+) -> anyhow::Result<()> {
+    let report = match args.format {
+        ReportFormat::Text => render_alerts(alerts, thresholds).into_bytes(),
+        ReportFormat::Csv => render_csv(alerts)?.into_bytes(),
+        ReportFormat::Html => render_html(alerts).into_bytes(),
+        ReportFormat::Markdown => render_markdown(alerts).into_bytes(),
+        ReportFormat::Junit => render_junit(alerts).into_bytes(),
+        ReportFormat::Xlsx => {
+            if args.out.is_none() {
+                anyhow::bail!("--format xlsx needs --out to write the workbook to");
+            }
+            render_xlsx(alerts)?
+        }
+    };
+
+    match &args.out {
+        Some(path) => {
+            std::fs::write(path, report)
+                .map_err(|e| anyhow::anyhow!("Failed to write report to '{}': {}", path, e))?;
+            info!("Wrote {} findings to {}", alerts.len(), path);
+        }
+        None => std::io::stdout().write_all(&report)?,
+    }
+
+    Ok(())
+}
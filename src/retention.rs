@@ -0,0 +1,92 @@
+use chrono::{DateTime, Duration, Utc};
+use log::{info, warn};
+
+use crate::cli::PruneArgs;
+use crate::config::{dry_run, setting};
+use crate::state::{self, ACTIONS, AUDIT, FINDINGS, OWNERS, SCANS};
+
+// The history that accumulates in the state store, and the field of each document that
+// says how old it is. Notification records and acknowledgments aren't history: they are
+// dropped once their credential is rotated or removed.
+const HISTORY: [(&str, &str); 5] = [
+    (SCANS, "at"),
+    (AUDIT, "at"),
+    (ACTIONS, "at"),
+    (FINDINGS, "at"),
+    (OWNERS, "fetched_at"),
+];
+
+// STATE_RETENTION_DAYS: how long history is kept. Unset keeps everything.
+pub fn retention_days() -> Option<u32> {
+    setting("STATE_RETENTION_DAYS").and_then(|v| v.trim().parse().ok())
+}
+
+fn timestamp(document: &serde_json::Value, field: &str) -> Option<DateTime<Utc>> {
+    document[field].as_str()?.parse().ok()
+}
+
+// Remove the history older than `days` from every namespace, returning how many documents
+// were (or with --dry-run, would be) removed from each.
+async fn prune(
+    store: &dyn state::StateStore,
+    days: u32,
+) -> anyhow::Result<Vec<(&'static str, usize)>> {
+    let cutoff = Utc::now() - Duration::days(days.into());
+    let mut removed = Vec::new();
+    for (namespace, field) in HISTORY {
+        let mut count = 0;
+        for (key, document) in store.list(namespace).await? {
+            if timestamp(&document, field).is_some_and(|at| at < cutoff) {
+                if !dry_run() {
+                    store.delete(namespace, &key).await?;
+                }
+                count += 1;
+            }
+        }
+        removed.push((namespace, count));
+    }
+    Ok(removed)
+}
+
+// `state prune`: remove history older than --older-than days, or STATE_RETENTION_DAYS.
+pub async fn prune_command(args: &PruneArgs) -> anyhow::Result<()> {
+    let Some(store) = state::store() else {
+        anyhow::bail!("There is no state store to prune; set STATE_BACKEND or STATE_PATH");
+    };
+    let Some(days) = args.older_than.or_else(retention_days) else {
+        anyhow::bail!("Pass --older-than or set STATE_RETENTION_DAYS");
+    };
+
+    let removed = prune(store, days).await?;
+    let verb = if dry_run() { "Would remove" } else { "Removed" };
+    for (namespace, count) in &removed {
+        println!("{} {} {} records", verb, count, namespace);
+    }
+    println!(
+        "{} {} records older than {} days",
+        verb,
+        removed.iter().map(|(_, count)| count).sum::<usize>(),
+        days
+    );
+    Ok(())
+}
+
+// Apply STATE_RETENTION_DAYS at the end of a run, so the store doesn't grow forever
+// without anyone running `state prune`.
+pub async fn apply_retention() {
+    let (Some(store), Some(days)) = (state::store(), retention_days()) else {
+        return;
+    };
+    if dry_run() {
+        return;
+    }
+    match prune(store, days).await {
+        Ok(removed) => {
+            let total: usize = removed.iter().map(|(_, count)| count).sum();
+            if total > 0 {
+                info!("Removed {} state records older than {} days", total, days);
+            }
+        }
+        Err(e) => warn!("Failed to prune the state store: {}", e),
+    }
+}
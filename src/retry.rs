@@ -0,0 +1,232 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use graph_rs_sdk::GraphClient;
+use reqwest::Method;
+use tracing::{debug, warn};
+
+use crate::models::Page;
+use crate::stats::{GRAPH_CALLS, count};
+
+// Give up after this many retries of a single request.
+pub const MAX_RETRIES: u32 = 6;
+// First backoff delay; doubled on every retry.
+const BASE_DELAY_MS: u64 = 1000;
+// Never wait longer than this between attempts.
+const MAX_DELAY_MS: u64 = 60_000;
+
+// Number of throttled/unavailable responses seen this run, reported in the final summary.
+static THROTTLED: AtomicUsize = AtomicUsize::new(0);
+
+pub fn throttled_count() -> usize {
+    THROTTLED.load(Ordering::Relaxed)
+}
+
+pub fn reset_throttled_count() {
+    THROTTLED.store(0, Ordering::Relaxed);
+}
+
+pub fn is_retryable(status: reqwest::StatusCode) -> bool {
+    status == reqwest::StatusCode::TOO_MANY_REQUESTS
+        || status == reqwest::StatusCode::SERVICE_UNAVAILABLE
+        || status == reqwest::StatusCode::GATEWAY_TIMEOUT
+}
+
+// Retry-After is given in seconds by Graph.
+fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    headers
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+// Honor Retry-After when present, otherwise back off exponentially with up to 50% jitter.
+fn backoff_delay(attempt: u32, retry_after: Option<Duration>) -> Duration {
+    if let Some(delay) = retry_after {
+        return delay;
+    }
+
+    let delay = BASE_DELAY_MS
+        .saturating_mul(1 << attempt.min(16))
+        .min(MAX_DELAY_MS);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos() as u64)
+        .unwrap_or(0);
+    let jitter = nanos % (delay / 2 + 1);
+
+    Duration::from_millis(delay + jitter)
+}
+
+// Graph's request-id header, which Microsoft support asks for when investigating a request.
+fn request_id(headers: &reqwest::header::HeaderMap) -> &str {
+    headers
+        .get("request-id")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+}
+
+async fn wait_before_retry(
+    attempt: u32,
+    status: reqwest::StatusCode,
+    headers: &reqwest::header::HeaderMap,
+) {
+    wait_to_retry(attempt, status, retry_after(headers), request_id(headers)).await;
+}
+
+// Wait before retrying a request Graph answered with `status`, for `retry_after` if it said
+// how long.
+pub async fn wait_to_retry(
+    attempt: u32,
+    status: reqwest::StatusCode,
+    retry_after: Option<Duration>,
+    request_id: &str,
+) {
+    THROTTLED.fetch_add(1, Ordering::Relaxed);
+    let delay = backoff_delay(attempt, retry_after);
+    warn!(
+        request_id,
+        "Graph responded with {}. Retrying in {:?} (attempt {} of {}).",
+        status,
+        delay,
+        attempt + 1,
+        MAX_RETRIES
+    );
+    tokio::time::sleep(delay).await;
+}
+
+// Send a Graph request, retrying on 429/503/504 responses.
+// `request` builds and sends the request and is called once per attempt.
+pub async fn send_with_retry<F, Fut, E>(mut request: F) -> anyhow::Result<reqwest::Response>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<reqwest::Response, E>>,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    let mut attempt = 0;
+    loop {
+        let response = request().await?;
+        count(&GRAPH_CALLS, 1);
+        debug!(
+            request_id = request_id(response.headers()),
+            "Graph responded with {}",
+            response.status()
+        );
+        if attempt < MAX_RETRIES && is_retryable(response.status()) {
+            wait_before_retry(attempt, response.status(), response.headers()).await;
+            attempt += 1;
+            continue;
+        }
+        return Ok(response);
+    }
+}
+
+// A page of a paged request that Graph answered with an error, with the error code from the
+// body. Callers can downcast to it, e.g. to tell a rejected delta token from other failures.
+#[derive(Debug)]
+pub struct PagingError {
+    pub status: reqwest::StatusCode,
+    pub code: Option<String>,
+}
+
+impl std::fmt::Display for PagingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Graph paging request failed with status {}", self.status)?;
+        if let Some(code) = &self.code {
+            write!(f, " ({})", code)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for PagingError {}
+
+// A page Graph answered with an error, as a PagingError.
+fn paging_error(status: reqwest::StatusCode, body: Option<&serde_json::Value>) -> anyhow::Error {
+    PagingError {
+        status,
+        code: body
+            .and_then(|body| body["error"]["code"].as_str())
+            .map(|code| code.to_string()),
+    }
+    .into()
+}
+
+// Fetch the page at `url`, a nextLink, retrying it on its own when throttled.
+async fn next_page(client: &GraphClient, url: &str) -> anyhow::Result<Page<serde_json::Value>> {
+    let url = url::Url::parse(url)?;
+    let response = send_with_retry(|| {
+        let mut request = client.custom(Method::GET, None);
+        *request.as_mut() = url.clone();
+        request.send()
+    })
+    .await?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(paging_error(status, response.json().await.ok().as_ref()));
+    }
+    Ok(response.json().await?)
+}
+
+// Run a paged Graph request and return every page. A throttled page is retried on its own,
+// following the nextLink of the page before it, so the pages already fetched aren't
+// requested again. Fails if any page still came back unsuccessful.
+pub async fn paging_with_retry<F, Fut, E, PE>(
+    client: &GraphClient,
+    mut request: F,
+) -> anyhow::Result<Vec<Page<serde_json::Value>>>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<VecDeque<http::Response<Result<serde_json::Value, PE>>>, E>>,
+    E: std::error::Error + Send + Sync + 'static,
+    PE: std::fmt::Debug,
+{
+    // Paging stops at the first page that fails, so only a throttled first page means
+    // starting over.
+    let mut attempt = 0;
+    let responses = loop {
+        let responses = request().await?;
+        count(&GRAPH_CALLS, responses.len());
+        if attempt < MAX_RETRIES && responses.len() == 1 && is_retryable(responses[0].status()) {
+            wait_before_retry(attempt, responses[0].status(), responses[0].headers()).await;
+            attempt += 1;
+            continue;
+        }
+        break responses;
+    };
+
+    let mut pages: Vec<Page<serde_json::Value>> = Vec::new();
+    for response in responses {
+        let next_link = pages.last().and_then(|page| page.nextLink.as_ref());
+        if is_retryable(response.status()) && next_link.is_some() {
+            // Fetched again below.
+            break;
+        }
+        if !response.status().is_success() {
+            return Err(paging_error(
+                response.status(),
+                response.body().as_ref().ok(),
+            ));
+        }
+        // A page that doesn't parse fails the listing rather than silently leaving out its
+        // items.
+        let body = response
+            .into_body()
+            .map_err(|e| anyhow::anyhow!("Failed to read a page of a Graph listing: {:?}", e))?;
+        pages
+            .push(serde_json::from_value(body).map_err(|e| {
+                anyhow::anyhow!("Failed to parse a page of a Graph listing: {}", e)
+            })?);
+    }
+
+    while let Some(url) = pages.last().and_then(|page| page.nextLink.clone()) {
+        pages.push(next_page(client, &url).await?);
+    }
+    Ok(pages)
+}
@@ -0,0 +1,255 @@
+use chrono::{Duration, Utc};
+use graph_rs_sdk::GraphClient;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::audit::{self, ActionEntry};
+use crate::batch::get_applications_by_app_id;
+use crate::cli::{OutputFormat, RotateArgs};
+use crate::config::{Tenant, dry_run, setting};
+use crate::models::App;
+use crate::retry::send_with_retry;
+
+// `rotate` adds a new client secret to each application with addPassword; the app
+// registration the tool runs as needs Application.ReadWrite.All (or to own the applications).
+// The old secrets are left in place so services can be moved over first, and cleaned up
+// later with `cleanup --expired`.
+//
+//     ROTATION_LIFETIME_DAYS  how long new secrets are valid (default 180)
+//     ROTATION_DISPLAY_NAME   their display name; {date}, {user} and {app} are replaced with
+//                             the date, the user running the tool and the application's
+//                             name (default "secret-manager {date}")
+//
+// The secret itself is only ever printed, never logged or recorded.
+const DEFAULT_LIFETIME_DAYS: u32 = 180;
+const DEFAULT_DISPLAY_NAME: &str = "secret-manager {date}";
+
+// Problems with the rotation settings, for config::validate.
+pub fn validate() -> Vec<String> {
+    let mut problems = Vec::new();
+    if let Some(days) = setting("ROTATION_LIFETIME_DAYS")
+        && !days.trim().parse::<u32>().is_ok_and(|d| d > 0)
+    {
+        problems.push(format!(
+            "ROTATION_LIFETIME_DAYS must be a positive number, got '{}'",
+            days
+        ));
+    }
+    if setting("ROTATION_DISPLAY_NAME").is_some_and(|name| name.trim().is_empty()) {
+        problems.push("ROTATION_DISPLAY_NAME is empty".to_string());
+    }
+    problems
+}
+
+fn lifetime_days(args: &RotateArgs) -> u32 {
+    args.lifetime_days
+        .or_else(|| setting("ROTATION_LIFETIME_DAYS").and_then(|v| v.trim().parse().ok()))
+        .unwrap_or(DEFAULT_LIFETIME_DAYS)
+}
+
+fn display_name(args: &RotateArgs, application: &str) -> String {
+    let template = args
+        .display_name
+        .clone()
+        .or_else(|| setting("ROTATION_DISPLAY_NAME"))
+        .unwrap_or_else(|| DEFAULT_DISPLAY_NAME.to_string());
+    template
+        .trim()
+        .replace("{date}", &Utc::now().format("%Y-%m-%d").to_string())
+        .replace(
+            "{user}",
+            audit::current_user().as_deref().unwrap_or("unknown"),
+        )
+        .replace("{app}", application)
+}
+
+// The passwordCredential addPassword returns; the only time secretText is readable.
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct NewSecret {
+    key_id: Option<String>,
+    display_name: Option<String>,
+    end_date_time: Option<String>,
+    secret_text: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Rotated {
+    tenant: String,
+    app_id: String,
+    application: String,
+    #[serde(flatten)]
+    secret: NewSecret,
+}
+
+// Add a secret to `app`, recording the attempt in the audit log either way. The second
+// result is the audit log write's, kept apart so failing to record a secret that was added
+// doesn't lose it.
+async fn add_password(
+    client: &GraphClient,
+    tenant: &Tenant,
+    app: &App,
+    display_name: &str,
+    lifetime_days: u32,
+) -> (anyhow::Result<NewSecret>, anyhow::Result<()>) {
+    let application = app.displayName.clone().unwrap_or_else(|| app.id.clone());
+    let end = Utc::now() + Duration::days(lifetime_days.into());
+    let body = serde_json::json!({
+        "passwordCredential": {
+            "displayName": display_name,
+            "endDateTime": end.to_rfc3339(),
+        }
+    });
+
+    let mut request_id = None;
+    let result = async {
+        let response =
+            send_with_retry(|| client.application(&app.id).add_password(&body).send()).await?;
+        request_id = audit::response_id(response.headers());
+        let status = response.status();
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            anyhow::bail!("addPassword failed with status {}: {}", status, text);
+        }
+        Ok::<_, anyhow::Error>(response.json::<NewSecret>().await?)
+    }
+    .await;
+
+    let audited = audit::record_action(ActionEntry {
+        at: Utc::now(),
+        action: "addPassword".to_string(),
+        by: audit::current_user(),
+        client_id: tenant.client_id.clone(),
+        tenant: tenant.name.clone(),
+        app_id: app.appId.clone().unwrap_or_else(|| app.id.clone()),
+        application,
+        key_id: result.as_ref().ok().and_then(|s| s.key_id.clone()),
+        details: format!(
+            "Added client secret '{}' expiring {}",
+            display_name,
+            end.format("%Y-%m-%d")
+        ),
+        request_id,
+        error: result.as_ref().err().map(|e| e.to_string()),
+    })
+    .await;
+
+    (result, audited)
+}
+
+fn print_rotated(r: &Rotated) {
+    println!("{} ({}) in tenant {}", r.application, r.app_id, r.tenant);
+    println!("  Key ID:  {}", r.secret.key_id.as_deref().unwrap_or("-"));
+    println!(
+        "  Name:    {}",
+        r.secret.display_name.as_deref().unwrap_or("-")
+    );
+    println!(
+        "  Expires: {}",
+        r.secret.end_date_time.as_deref().unwrap_or("-")
+    );
+    println!("  Secret:  {}", r.secret.secret_text);
+    println!();
+}
+
+// `rotate`: add a new client secret to each of the applications, in whichever tenants they
+// are found. A secret exists only in the addPassword response, so once one is added nothing
+// may stop it being shown: with text output each is printed as soon as it is added, and
+// failures to look up applications in a tenant or to write the audit log are warnings,
+// counted toward the command's failure once every secret has been printed.
+pub async fn rotate(
+    args: &RotateArgs,
+    tenants: &[Tenant],
+    clients: &[GraphClient],
+    output: OutputFormat,
+) -> anyhow::Result<()> {
+    let lifetime_days = lifetime_days(args);
+    let mut rotated = Vec::new();
+    let mut found = Vec::new();
+    let mut failed = 0;
+    let mut searched_every_tenant = true;
+
+    for (tenant, client) in tenants.iter().zip(clients) {
+        let apps = match get_applications_by_app_id(client, &args.apps).await {
+            Ok(apps) => apps,
+            Err(e) => {
+                warn!(
+                    "Failed to look up the applications in tenant {}: {}",
+                    tenant.name, e
+                );
+                failed += 1;
+                searched_every_tenant = false;
+                continue;
+            }
+        };
+        for app in &apps {
+            let app_id = app.appId.clone().unwrap_or_else(|| app.id.clone());
+            let application = app.displayName.clone().unwrap_or_else(|| app.id.clone());
+            found.push(app_id.clone());
+            found.push(app.id.clone());
+            let display_name = display_name(args, &application);
+
+            if dry_run() {
+                println!(
+                    "Would add client secret '{}' valid for {} days to {} ({}) in tenant {}",
+                    display_name, lifetime_days, application, app_id, tenant.name
+                );
+                continue;
+            }
+
+            let (secret, audited) =
+                add_password(client, tenant, app, &display_name, lifetime_days).await;
+            match secret {
+                Ok(secret) => {
+                    let r = Rotated {
+                        tenant: tenant.name.clone(),
+                        app_id,
+                        application: application.clone(),
+                        secret,
+                    };
+                    if output == OutputFormat::Text {
+                        print_rotated(&r);
+                    }
+                    rotated.push(r);
+                }
+                Err(e) => {
+                    warn!("Failed to add a client secret to {}: {}", application, e);
+                    failed += 1;
+                }
+            }
+            if let Err(e) = audited {
+                warn!(
+                    "Failed to record the new client secret of {} in the audit log: {}",
+                    application, e
+                );
+                failed += 1;
+            }
+        }
+    }
+
+    if output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&rotated)?);
+    } else if !rotated.is_empty() {
+        println!("Store these secrets now; they can't be shown again.");
+    }
+
+    let missing: Vec<&str> = args
+        .apps
+        .iter()
+        .filter(|id| !found.contains(id))
+        .map(|id| id.as_str())
+        .collect();
+    if searched_every_tenant && !missing.is_empty() {
+        anyhow::bail!("Applications not found: {}", missing.join(", "));
+    }
+    if failed > 0 {
+        anyhow::bail!(
+            "Added {} client secrets with {} failures; see the warnings above",
+            rotated.len(),
+            failed
+        );
+    }
+    info!("Added client secrets to {} applications", rotated.len());
+    Ok(())
+}
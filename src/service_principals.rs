@@ -0,0 +1,211 @@
+use base64::Engine;
+use futures::{StreamExt, TryStreamExt};
+use graph_rs_sdk::*;
+use log::info;
+
+use crate::alerts::{Alert, Finding, Thresholds, format_expiry};
+use crate::config::{fetch_concurrency, page_size, service_principal_select};
+use crate::filters::AppFilter;
+use crate::models::{CredentialHolder, ServicePrincipal};
+use crate::owners::{list_service_principal_owners, resolve_owners};
+use crate::progress::Progress;
+use crate::retry::paging_with_retry;
+
+// Return a list of service principals with their credentials and owners.
+// Gallery and legacy apps often carry secrets/certs on the service principal
+// rather than on the app registration, so these need to be scanned separately.
+pub async fn get_all_service_principals(
+    client: &GraphClient,
+) -> anyhow::Result<Vec<ServicePrincipal>> {
+    let select = service_principal_select();
+    let select: Vec<&str> = select.iter().map(String::as_str).collect();
+    let top = page_size();
+
+    let all_service_principals_response = paging_with_retry(client, || {
+        let mut request = client
+            .service_principals()
+            .list_service_principal()
+            .select(&select);
+        if let Some(top) = &top {
+            request = request.top(top);
+        }
+        request.paging().json::<serde_json::Value>()
+    })
+    .await?;
+
+    // Most service principals carry no credentials of their own;
+    // don't spend an owners request on them.
+    let filter = AppFilter::from_env()?;
+    let parsed: Vec<ServicePrincipal> = all_service_principals_response
+        .into_iter()
+        .flat_map(|page| page.parse_items::<ServicePrincipal>("service principal"))
+        .filter(|sp| !sp.passwordCredentials.is_empty() || !sp.keyCredentials.is_empty())
+        .filter(|sp| filter.allows(sp.displayName.as_deref(), sp.appId.as_deref()))
+        .collect();
+
+    // Fetch owners for up to FETCH_CONCURRENCY service principals at a time.
+    let progress = Progress::new("Resolving service principal owners", parsed.len());
+    let progress = &progress;
+    let resolved: Vec<Option<ServicePrincipal>> = futures::stream::iter(parsed)
+        .map(|mut sp| async move {
+            let owners = match list_service_principal_owners(client, &sp.id).await {
+                Ok(o) => o,
+                Err(e) => {
+                    info!(
+                        "Failed to read owners for service principal '{:?}': {}. Skipping.",
+                        sp.displayName, e
+                    );
+                    progress.inc();
+                    return anyhow::Ok(None);
+                }
+            };
+
+            sp.insert_owners(resolve_owners(client, owners).await?);
+            progress.inc();
+            anyhow::Ok(Some(sp))
+        })
+        .buffered(fetch_concurrency())
+        .try_collect()
+        .await?;
+    progress.finish();
+
+    let service_principals: Vec<ServicePrincipal> = resolved.into_iter().flatten().collect();
+
+    info!("Fetched service principals with credentials");
+
+    Ok(service_principals)
+}
+
+// The hex thumbprint of a certificate from its customKeyIdentifier, which Graph returns
+// base64-encoded while preferredTokenSigningKeyThumbprint is hex. Identifiers that aren't
+// base64 are taken as they are.
+fn thumbprint(custom_key_identifier: &str) -> String {
+    match base64::engine::general_purpose::STANDARD.decode(custom_key_identifier.trim()) {
+        Ok(bytes) => bytes.iter().map(|b| format!("{:02X}", b)).collect(),
+        Err(_) => custom_key_identifier.to_string(),
+    }
+}
+
+// Check SAML enterprise applications for signing certificates expired or expiring within the threshold tiers.
+// Each alert contains the application name, owner emails, and expiring signing certificate info,
+// noting whether the certificate is the one currently used to sign tokens.
+pub fn check_saml_signing_certificates(
+    service_principals: &[ServicePrincipal],
+    thresholds: &Thresholds,
+) -> Vec<Alert> {
+    let mut alerts: Vec<Alert> = Vec::new();
+
+    let now = chrono::Utc::now();
+    let threshold = thresholds.cutoff(now);
+
+    for sp in service_principals.iter().filter(|sp| sp.is_saml()) {
+        let active_thumbprint = sp.preferredTokenSigningKeyThumbprint.as_deref();
+        let mut findings: Vec<Finding> = Vec::new();
+
+        // SAML signing certificates are stored as keyCredentials with usage "Sign".
+        for credential in sp
+            .keyCredentials
+            .iter()
+            .filter(|c| c.usage.as_deref() == Some("Sign"))
+        {
+            if credential.endDateTime < threshold {
+                let thumbprint = credential.customKeyIdentifier.as_deref().map(thumbprint);
+                let active = thumbprint
+                    .as_deref()
+                    .zip(active_thumbprint)
+                    .is_some_and(|(thumbprint, active)| thumbprint.eq_ignore_ascii_case(active));
+
+                info!(
+                    "SAML application '{:?}' has a signing certificate expiring on {} (Thumbprint: {:?}, Active: {})",
+                    sp.displayName, credential.endDateTime, thumbprint, active
+                );
+                findings.push(
+                    Finding::new(
+                        credential.endDateTime,
+                        now,
+                        thresholds,
+                        "SAML signing certificate",
+                        format!(
+                            "SAML signing certificate, Thumbprint: {:?}, Active: {}, Expiry: {}, Active signing thumbprint (preferredTokenSigningKeyThumbprint): {}",
+                            thumbprint,
+                            active,
+                            format_expiry(credential.endDateTime, now),
+                            active_thumbprint.unwrap_or("not set")
+                        ),
+                    )
+                    .with_key(credential.keyId.clone(), None),
+                );
+            }
+        }
+
+        if findings.is_empty() {
+            continue;
+        }
+
+        let owner_emails: Vec<String> = sp
+            .owners()
+            .iter()
+            .filter_map(|owner| owner.mail.clone().or(owner.userPrincipalName.clone()))
+            .collect();
+
+        if owner_emails.is_empty() {
+            info!(
+                "No owners to notify for SAML application '{:?}' (App ID: {:?})",
+                sp.displayName, sp.appId
+            );
+        }
+
+        findings.sort_by_key(|f| f.end_date_time);
+        alerts.push(Alert {
+            tenant: String::new(),
+            name: format!(
+                "{} (SAML Enterprise Application)",
+                sp.displayName.as_deref().unwrap_or("No Name")
+            ),
+            app_id: sp.appId.clone(),
+            owners: owner_emails,
+            escalate_to: Vec::new(),
+            findings,
+        });
+    }
+
+    alerts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn custom_key_identifiers_compare_with_hex_thumbprints() {
+        // base64 of the bytes 0x3A 0xF1 0x0B.
+        assert_eq!(thumbprint("OvEL"), "3AF10B");
+        assert_eq!(thumbprint("not base64!"), "not base64!");
+    }
+
+    #[test]
+    fn the_signing_certificate_in_use_is_marked_active() {
+        let expiry = (chrono::Utc::now() + chrono::Duration::days(3)).to_rfc3339();
+        let sp: ServicePrincipal = serde_json::from_value(serde_json::json!({
+            "id": "sp-id",
+            "displayName": "Payroll",
+            "preferredSingleSignOnMode": "saml",
+            "preferredTokenSigningKeyThumbprint": "3af10b",
+            "keyCredentials": [
+                { "customKeyIdentifier": "OvEL", "endDateTime": expiry, "usage": "Sign" },
+                { "customKeyIdentifier": "AAAA", "endDateTime": expiry, "usage": "Sign" },
+            ],
+        }))
+        .unwrap();
+        let thresholds = Thresholds::new(Thresholds::DEFAULT.to_vec()).unwrap();
+
+        let alerts = check_saml_signing_certificates(&[sp], &thresholds);
+        let descriptions: Vec<&str> = alerts[0]
+            .findings
+            .iter()
+            .map(|f| f.description.as_str())
+            .collect();
+        assert!(descriptions[0].contains("Thumbprint: Some(\"3AF10B\"), Active: true"));
+        assert!(descriptions[1].contains("Thumbprint: Some(\"000000\"), Active: false"));
+    }
+}
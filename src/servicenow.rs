@@ -0,0 +1,184 @@
+use chrono::Utc;
+use log::{error, info};
+
+use crate::alerts::{Alert, Finding, Severity, format_expiry};
+use crate::config::{dry_run, http_client, setting};
+use crate::stats::{NOTIFICATIONS_FAILED, NOTIFICATIONS_SENT, count};
+use crate::templates::portal_url;
+
+// ServiceNow incidents are enabled by SERVICENOW_URL (e.g. https://example.service-now.com).
+// One incident is filed per finding through the Table API, with the finding's key as the
+// correlation_id so later runs don't file it again while it is still active.
+//
+//     SERVICENOW_USER / SERVICENOW_PASSWORD   basic auth for an account with itil rights
+//     SERVICENOW_ASSIGNMENT_GROUP            group name or sys_id incidents are assigned to
+//     SERVICENOW_CATEGORY                    optional incident category
+//     SERVICENOW_MIN_SEVERITY                leave out less urgent credentials
+pub fn enabled() -> bool {
+    setting("SERVICENOW_URL").is_some()
+}
+
+// ServiceNow urgency: 1 (high) for critical findings, 2 for high, 3 otherwise.
+fn urgency(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Critical => "1",
+        Severity::High => "2",
+        Severity::Medium | Severity::Low => "3",
+    }
+}
+
+fn correlation_id(alert: &Alert, finding: &Finding) -> String {
+    format!(
+        "secret-manager:{}:{}:{}",
+        alert.tenant,
+        alert.app_id.as_deref().unwrap_or(&alert.name),
+        finding.key_id.as_deref().unwrap_or(&finding.credential)
+    )
+}
+
+struct ServiceNow {
+    http: reqwest::Client,
+    table_url: String,
+}
+
+impl ServiceNow {
+    fn request(&self, method: reqwest::Method) -> reqwest::RequestBuilder {
+        self.http
+            .request(method, &self.table_url)
+            .basic_auth(
+                setting("SERVICENOW_USER").unwrap_or_default(),
+                setting("SERVICENOW_PASSWORD"),
+            )
+            .header(reqwest::header::ACCEPT, "application/json")
+    }
+
+    async fn has_active_incident(&self, correlation_id: &str) -> anyhow::Result<bool> {
+        let response: serde_json::Value = self
+            .request(reqwest::Method::GET)
+            .query(&[
+                (
+                    "sysparm_query",
+                    format!("correlation_id={}^active=true", correlation_id),
+                ),
+                ("sysparm_fields", "number".to_string()),
+                ("sysparm_limit", "1".to_string()),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(response["result"].as_array().is_some_and(|r| !r.is_empty()))
+    }
+
+    async fn file_incident(&self, alert: &Alert, finding: &Finding) -> anyhow::Result<String> {
+        let now = Utc::now();
+        let mut description = format!(
+            "Tenant: {}\nApplication: {}\nCredential: {}\nExpiry: {}\nSeverity: {}\nOwners: {}\n",
+            alert.tenant,
+            alert.name,
+            finding.credential,
+            format_expiry(finding.end_date_time, now),
+            finding.severity.label(),
+            if alert.owners.is_empty() {
+                "None".to_string()
+            } else {
+                alert.owners.join(", ")
+            }
+        );
+        if let Some(app_id) = &alert.app_id {
+            description.push_str(&format!(
+                "App ID: {}\nPortal: {}\n",
+                app_id,
+                portal_url(app_id)
+            ));
+        }
+
+        let mut incident = serde_json::json!({
+            "short_description": format!(
+                "{} of {} {}",
+                finding.credential,
+                alert.name,
+                format_expiry(finding.end_date_time, now)
+            ),
+            "description": description,
+            "urgency": urgency(finding.severity),
+            "impact": "2",
+            "correlation_id": correlation_id(alert, finding),
+            "correlation_display": "secret-manager"
+        });
+        if let Some(group) = setting("SERVICENOW_ASSIGNMENT_GROUP") {
+            incident["assignment_group"] = serde_json::Value::String(group);
+        }
+        if let Some(category) = setting("SERVICENOW_CATEGORY") {
+            incident["category"] = serde_json::Value::String(category);
+        }
+
+        let response: serde_json::Value = self
+            .request(reqwest::Method::POST)
+            .json(&incident)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(response["result"]["number"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string())
+    }
+
+    // File an incident for the finding unless an active one already exists.
+    async fn sync(&self, alert: &Alert, finding: &Finding) -> anyhow::Result<()> {
+        let id = correlation_id(alert, finding);
+        if self.has_active_incident(&id).await? {
+            info!("ServiceNow already has an active incident for {}", id);
+            return Ok(());
+        }
+        let number = self.file_incident(alert, finding).await?;
+        count(&NOTIFICATIONS_SENT, 1);
+        info!("Filed ServiceNow incident {} for {}", number, id);
+        Ok(())
+    }
+}
+
+// File a ServiceNow incident for every finding at or above SERVICENOW_MIN_SEVERITY.
+pub async fn file_incidents(alerts: &[Alert]) -> anyhow::Result<()> {
+    let url =
+        setting("SERVICENOW_URL").ok_or_else(|| anyhow::anyhow!("SERVICENOW_URL is not set"))?;
+    let servicenow = ServiceNow {
+        http: http_client(),
+        table_url: format!("{}/api/now/table/incident", url.trim_end_matches('/')),
+    };
+    let min = setting("SERVICENOW_MIN_SEVERITY")
+        .and_then(|v| Severity::parse(&v).ok())
+        .unwrap_or(Severity::Low);
+
+    let mut total = 0;
+    let mut failures = 0;
+    for alert in alerts {
+        for finding in alert.findings.iter().filter(|f| f.severity >= min) {
+            total += 1;
+            if dry_run() {
+                println!(
+                    "[dry-run] Would file a ServiceNow incident for {}",
+                    correlation_id(alert, finding)
+                );
+                continue;
+            }
+            if let Err(e) = servicenow.sync(alert, finding).await {
+                count(&NOTIFICATIONS_FAILED, 1);
+                error!(
+                    "Failed to file a ServiceNow incident for {}: {}",
+                    alert.name, e
+                );
+                failures += 1;
+            }
+        }
+    }
+
+    if failures > 0 {
+        anyhow::bail!("{} of {} ServiceNow incidents failed", failures, total);
+    }
+    Ok(())
+}
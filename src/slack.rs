@@ -0,0 +1,139 @@
+use std::collections::BTreeMap;
+
+use log::error;
+
+use crate::alerts::{Alert, Severity, format_expiry};
+use crate::audit::alert_findings;
+use crate::notifiers::{alerts_at_or_above, post_json, severity_setting};
+use crate::templates::portal_url;
+
+// Slack allows 50 blocks per message; two are used for the header and the overflow note.
+const MAX_APPLICATIONS: usize = 48;
+
+// Slack notifications are enabled by SLACK_WEBHOOK_URL or any of the per-severity
+// SLACK_WEBHOOK_URL_<SEVERITY> overrides, so e.g. critical findings can go to an on-call
+// channel and the rest to a team channel. SLACK_CHANNEL (or SLACK_CHANNEL_<SEVERITY>)
+// overrides the webhook's default channel where the webhook allows it, and
+// SLACK_MIN_SEVERITY leaves out less urgent applications.
+pub fn enabled() -> bool {
+    Severity::ALL
+        .iter()
+        .any(|s| severity_setting("SLACK_WEBHOOK_URL", *s).is_some())
+}
+
+// Slack mrkdwn needs &, < and > escaped.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn emoji(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Critical => ":red_circle:",
+        Severity::High => ":large_orange_circle:",
+        Severity::Medium => ":large_yellow_circle:",
+        Severity::Low => ":white_circle:",
+    }
+}
+
+// A Block Kit message with a section per application, most urgent first.
+fn render_message(alerts: &[&Alert], channel: Option<&str>) -> serde_json::Value {
+    let now = chrono::Utc::now();
+    let mut sorted = alerts.to_vec();
+    sorted.sort_by(|a, b| b.severity().cmp(&a.severity()).then(a.name.cmp(&b.name)));
+
+    let title = format!(
+        "{} applications have credentials that need attention",
+        alerts.len()
+    );
+    let mut blocks = vec![serde_json::json!({
+        "type": "header",
+        "text": { "type": "plain_text", "text": title }
+    })];
+
+    for alert in sorted.iter().take(MAX_APPLICATIONS) {
+        let name = match &alert.app_id {
+            Some(app_id) => format!("<{}|{}>", portal_url(app_id), escape(&alert.name)),
+            None => escape(&alert.name),
+        };
+        let findings = alert
+            .findings
+            .iter()
+            .map(|f| {
+                format!(
+                    "• {} {}",
+                    escape(&f.credential),
+                    format_expiry(f.end_date_time, now)
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        let owners = if alert.owners.is_empty() {
+            "none".to_string()
+        } else {
+            escape(&alert.owners.join(", "))
+        };
+
+        blocks.push(serde_json::json!({
+            "type": "section",
+            "text": {
+                "type": "mrkdwn",
+                "text": format!(
+                    "{} *{}* [{}] ({})\nOwners: {}\n{}",
+                    emoji(alert.severity()),
+                    name,
+                    alert.severity().label(),
+                    escape(&alert.tenant),
+                    owners,
+                    findings
+                )
+            }
+        }));
+    }
+
+    if sorted.len() > MAX_APPLICATIONS {
+        blocks.push(serde_json::json!({
+            "type": "context",
+            "elements": [{
+                "type": "mrkdwn",
+                "text": format!("... and {} more applications.", sorted.len() - MAX_APPLICATIONS)
+            }]
+        }));
+    }
+
+    let mut message = serde_json::json!({ "text": title, "blocks": blocks });
+    if let Some(channel) = channel {
+        message["channel"] = serde_json::Value::String(channel.to_string());
+    }
+    message
+}
+
+// Post the findings to Slack, one message per webhook/channel the applications' severities
+// route to.
+pub async fn post_messages(alerts: &[Alert]) -> anyhow::Result<()> {
+    let mut routes: BTreeMap<(String, Option<String>), Vec<&Alert>> = BTreeMap::new();
+    for alert in alerts_at_or_above(alerts, "SLACK_MIN_SEVERITY") {
+        let severity = alert.severity();
+        let Some(url) = severity_setting("SLACK_WEBHOOK_URL", severity) else {
+            continue;
+        };
+        let channel = severity_setting("SLACK_CHANNEL", severity);
+        routes.entry((url, channel)).or_default().push(alert);
+    }
+
+    let mut failures = 0;
+    for ((url, channel), routed) in &routes {
+        let message = render_message(routed, channel.as_deref());
+        let findings = alert_findings(routed.iter().copied());
+        if let Err(e) = post_json("Slack", url, &message, &findings).await {
+            error!("{}", e);
+            failures += 1;
+        }
+    }
+
+    if failures > 0 {
+        anyhow::bail!("{} of {} Slack messages failed", failures, routes.len());
+    }
+    Ok(())
+}
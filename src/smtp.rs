@@ -0,0 +1,194 @@
+use lettre::message::header::{ContentType, Header, HeaderName, HeaderValue};
+use lettre::message::{Attachment as MimeAttachment, Mailbox, MultiPart, SinglePart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Address, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+use crate::config::setting;
+use crate::email::Mail;
+
+// Sending over SMTP instead of Graph, for environments where the app isn't granted
+// Mail.Send. Selected with EMAIL_TRANSPORT=smtp and configured with:
+//
+//     SMTP_HOST       relay host name (required)
+//     SMTP_PORT       defaults to 587 for starttls, 465 for tls and 25 for none
+//     SMTP_TLS        starttls (default), tls for implicit TLS, or none
+//     SMTP_USERNAME   optional; SMTP_PASSWORD is sent with it
+//
+// Mail is sent from EMAIL_FROM (with EMAIL_FROM_NAME), falling back to ALERTING_EMAIL.
+// Like any other setting these can be set per profile in the config file.
+
+// The Importance header Outlook and most other clients read.
+#[derive(Clone)]
+struct Importance(String);
+
+impl Header for Importance {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("Importance")
+    }
+
+    fn parse(s: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Importance(s.to_string()))
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), self.0.clone())
+    }
+}
+
+// Whether mail goes out over SMTP rather than Graph, from EMAIL_TRANSPORT.
+pub fn enabled() -> bool {
+    setting("EMAIL_TRANSPORT").is_some_and(|t| t.trim().eq_ignore_ascii_case("smtp"))
+}
+
+// Check the SMTP settings, returning a description of each problem.
+pub fn validate() -> Vec<String> {
+    let mut problems = Vec::new();
+
+    if let Some(transport) = setting("EMAIL_TRANSPORT")
+        && !["graph", "smtp"].contains(&transport.trim().to_lowercase().as_str())
+    {
+        problems.push(format!(
+            "EMAIL_TRANSPORT must be 'graph' or 'smtp', got '{}'",
+            transport
+        ));
+    }
+
+    if !enabled() {
+        return problems;
+    }
+
+    if setting("SMTP_HOST").is_none_or(|v| v.trim().is_empty()) {
+        problems.push("SMTP_HOST is not set (required with EMAIL_TRANSPORT=smtp)".to_string());
+    }
+    if let Some(port) = setting("SMTP_PORT")
+        && port.trim().parse::<u16>().is_err()
+    {
+        problems.push(format!("SMTP_PORT must be a port number, got '{}'", port));
+    }
+    if let Some(tls) = setting("SMTP_TLS")
+        && !["starttls", "tls", "none"].contains(&tls.trim().to_lowercase().as_str())
+    {
+        problems.push(format!(
+            "SMTP_TLS must be 'starttls', 'tls' or 'none', got '{}'",
+            tls
+        ));
+    }
+
+    problems
+}
+
+// Where dry-run output says the mail would have gone.
+pub fn describe() -> String {
+    format!(
+        "SMTP ({})",
+        setting("SMTP_HOST").unwrap_or_else(|| "SMTP_HOST not set".to_string())
+    )
+}
+
+fn transport() -> anyhow::Result<AsyncSmtpTransport<Tokio1Executor>> {
+    let host = setting("SMTP_HOST").ok_or_else(|| anyhow::anyhow!("SMTP_HOST is not set"))?;
+    let tls = setting("SMTP_TLS")
+        .map(|t| t.trim().to_lowercase())
+        .unwrap_or_else(|| "starttls".to_string());
+
+    let (builder, default_port) = match tls.as_str() {
+        "starttls" => (
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&host)?,
+            587,
+        ),
+        "tls" => (AsyncSmtpTransport::<Tokio1Executor>::relay(&host)?, 465),
+        "none" => (
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&host),
+            25,
+        ),
+        other => anyhow::bail!("Unknown SMTP_TLS mode '{}'", other),
+    };
+
+    let port = match setting("SMTP_PORT") {
+        Some(port) => port
+            .trim()
+            .parse::<u16>()
+            .map_err(|_| anyhow::anyhow!("Invalid SMTP_PORT '{}'", port))?,
+        None => default_port,
+    };
+
+    let mut builder = builder.port(port);
+    if let Some(username) = setting("SMTP_USERNAME") {
+        builder = builder.credentials(Credentials::new(
+            username,
+            setting("SMTP_PASSWORD").unwrap_or_default(),
+        ));
+    }
+
+    Ok(builder.build())
+}
+
+fn mailbox(address: &str) -> anyhow::Result<Mailbox> {
+    address
+        .trim()
+        .parse::<Mailbox>()
+        .map_err(|e| anyhow::anyhow!("Invalid email address '{}': {}", address, e))
+}
+
+fn sender(alerting_email: &str) -> anyhow::Result<Mailbox> {
+    let address = setting("EMAIL_FROM").unwrap_or_else(|| alerting_email.to_string());
+    let address = address
+        .trim()
+        .parse::<Address>()
+        .map_err(|e| anyhow::anyhow!("Invalid sender address '{}': {}", address, e))?;
+    Ok(Mailbox::new(setting("EMAIL_FROM_NAME"), address))
+}
+
+// Send a mail through the configured SMTP relay, as multipart/alternative when it has an
+// HTML body, wrapped in multipart/mixed when it has attachments. Returns the relay's reply,
+// which usually carries the queue id of the message.
+pub async fn send(alerting_email: &str, mail: &Mail) -> anyhow::Result<Option<String>> {
+    let mut builder = Message::builder()
+        .from(sender(alerting_email)?)
+        .subject(&mail.subject)
+        .header(Importance(mail.importance.to_string()));
+
+    for address in &mail.to {
+        builder = builder.to(mailbox(address)?);
+    }
+    for address in &mail.cc {
+        builder = builder.cc(mailbox(address)?);
+    }
+    for address in &mail.bcc {
+        builder = builder.bcc(mailbox(address)?);
+    }
+    for address in &mail.reply_to {
+        builder = builder.reply_to(mailbox(address)?);
+    }
+
+    let message = if mail.attachments.is_empty() {
+        match &mail.html {
+            Some(html) if mail.use_html() => builder.multipart(
+                MultiPart::alternative_plain_html(mail.text.clone(), html.clone()),
+            )?,
+            _ => builder
+                .header(ContentType::TEXT_PLAIN)
+                .body(mail.text.clone())?,
+        }
+    } else {
+        let mut parts = match &mail.html {
+            Some(html) if mail.use_html() => MultiPart::mixed().multipart(
+                MultiPart::alternative_plain_html(mail.text.clone(), html.clone()),
+            ),
+            _ => MultiPart::mixed().singlepart(SinglePart::plain(mail.text.clone())),
+        };
+        for attachment in &mail.attachments {
+            parts = parts.singlepart(MimeAttachment::new(attachment.name.clone()).body(
+                attachment.content.clone(),
+                ContentType::parse(&attachment.content_type)?,
+            ));
+        }
+        builder.multipart(parts)?
+    };
+
+    let response = transport()?.send(message).await?;
+    log::info!("Email sent over SMTP with response: {:?}", response.code());
+
+    let reply = response.message().collect::<Vec<&str>>().join(" ");
+    Ok((!reply.is_empty()).then_some(reply))
+}
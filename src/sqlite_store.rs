@@ -0,0 +1,232 @@
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use log::info;
+use rusqlite::{Connection, OptionalExtension, TransactionBehavior, params};
+
+use crate::state::{LOCKS, Lock, StateStore};
+
+// The default state backend: a single SQLite file (STATE_PATH, default secret-manager.db)
+// with one table of JSON documents.
+// Queries are small and local, so they run directly on the async task.
+pub struct SqliteStore {
+    connection: Mutex<Connection>,
+}
+
+// Schema changes, applied in order to bring the database of an older release up to date when
+// it is opened. The database's user_version is how many have been applied. Released
+// migrations must never change; add a new one instead.
+const MIGRATIONS: &[&str] = &[
+    // 1: the documents. IF NOT EXISTS because databases from before migrations were added
+    // already have it, at user_version 0.
+    "CREATE TABLE IF NOT EXISTS state (
+         namespace TEXT NOT NULL,
+         key TEXT NOT NULL,
+         value TEXT NOT NULL,
+         updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+         PRIMARY KEY (namespace, key)
+     );",
+    // 2: for finding stale documents when pruning and querying the database directly.
+    "CREATE INDEX IF NOT EXISTS state_updated_at ON state (namespace, updated_at);",
+];
+
+fn migrate(connection: &mut Connection, path: &str) -> anyhow::Result<()> {
+    let version: u32 = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version as usize > MIGRATIONS.len() {
+        anyhow::bail!(
+            "State database '{}' is at schema version {}, newer than this release supports ({}); \
+             upgrade secret-manager",
+            path,
+            version,
+            MIGRATIONS.len()
+        );
+    }
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let version = index as u32 + 1;
+        // Each migration and its version bump commit together, so an interrupted upgrade
+        // resumes where it stopped.
+        let transaction = connection.transaction()?;
+        transaction.execute_batch(migration)?;
+        transaction.pragma_update(None, "user_version", version)?;
+        transaction.commit()?;
+        info!(
+            "Upgraded state database '{}' to schema version {}",
+            path, version
+        );
+    }
+    Ok(())
+}
+
+const UPSERT: &str = "INSERT INTO state (namespace, key, value, updated_at)
+     VALUES (?1, ?2, ?3, CURRENT_TIMESTAMP)
+     ON CONFLICT (namespace, key)
+     DO UPDATE SET value = excluded.value, updated_at = excluded.updated_at";
+
+impl SqliteStore {
+    pub fn open(path: &str) -> anyhow::Result<SqliteStore> {
+        let mut connection = Connection::open(path)
+            .map_err(|e| anyhow::anyhow!("Failed to open state database '{}': {}", path, e))?;
+        connection.execute_batch("PRAGMA journal_mode = WAL;")?;
+        migrate(&mut connection, path)?;
+        Ok(SqliteStore {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    fn connection(&self) -> std::sync::MutexGuard<'_, Connection> {
+        // A panic mid-query leaves nothing half-written that SQLite wouldn't roll back.
+        self.connection.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[async_trait]
+impl StateStore for SqliteStore {
+    async fn get(&self, namespace: &str, key: &str) -> anyhow::Result<Option<serde_json::Value>> {
+        let value: Option<String> = self
+            .connection()
+            .query_row(
+                "SELECT value FROM state WHERE namespace = ?1 AND key = ?2",
+                params![namespace, key],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(match value {
+            Some(value) => Some(serde_json::from_str(&value)?),
+            None => None,
+        })
+    }
+
+    async fn put(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        self.connection()
+            .execute(UPSERT, params![namespace, key, value.to_string()])?;
+        Ok(())
+    }
+
+    async fn delete(&self, namespace: &str, key: &str) -> anyhow::Result<()> {
+        self.connection().execute(
+            "DELETE FROM state WHERE namespace = ?1 AND key = ?2",
+            params![namespace, key],
+        )?;
+        Ok(())
+    }
+
+    // Checked and taken in one write transaction, which also excludes other processes
+    // using the same database file.
+    async fn try_lock(&self, name: &str, lock: &Lock) -> anyhow::Result<bool> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let current: Option<String> = transaction
+            .query_row(
+                "SELECT value FROM state WHERE namespace = ?1 AND key = ?2",
+                params![LOCKS, name],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if let Some(current) = current.and_then(|c| serde_json::from_str::<Lock>(&c).ok())
+            && !current.available_to(&lock.holder, Utc::now())
+        {
+            return Ok(false);
+        }
+        transaction.execute(UPSERT, params![LOCKS, name, serde_json::to_string(lock)?])?;
+        transaction.commit()?;
+        Ok(true)
+    }
+
+    async fn list(&self, namespace: &str) -> anyhow::Result<Vec<(String, serde_json::Value)>> {
+        let connection = self.connection();
+        let mut statement =
+            connection.prepare("SELECT key, value FROM state WHERE namespace = ?1 ORDER BY key")?;
+        let rows = statement.query_map(params![namespace], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+
+        let mut documents = Vec::new();
+        for row in rows {
+            let (key, value) = row?;
+            documents.push((key, serde_json::from_str(&value)?));
+        }
+        Ok(documents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn documents_are_stored_by_namespace_and_key() {
+        let store = SqliteStore::open(":memory:").unwrap();
+        store
+            .put("notifications", "b", &serde_json::json!({ "n": 1 }))
+            .await
+            .unwrap();
+        store
+            .put("notifications", "b", &serde_json::json!({ "n": 2 }))
+            .await
+            .unwrap();
+        store
+            .put("notifications", "a", &serde_json::json!(null))
+            .await
+            .unwrap();
+        store
+            .put("scans", "a", &serde_json::json!(true))
+            .await
+            .unwrap();
+        store.delete("scans", "a").await.unwrap();
+
+        let keys: Vec<String> = store
+            .list("notifications")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(keys, ["a", "b"]);
+        assert_eq!(
+            store.get("notifications", "b").await.unwrap(),
+            Some(serde_json::json!({ "n": 2 }))
+        );
+        assert_eq!(store.get("scans", "a").await.unwrap(), None);
+    }
+
+    #[test]
+    fn databases_are_migrated_to_the_latest_schema() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        migrate(&mut connection, ":memory:").unwrap();
+        let version: u32 = connection
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version as usize, MIGRATIONS.len());
+        // Migrating again changes nothing.
+        migrate(&mut connection, ":memory:").unwrap();
+    }
+
+    #[test]
+    fn databases_of_newer_releases_are_refused() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        connection
+            .pragma_update(None, "user_version", MIGRATIONS.len() as u32 + 1)
+            .unwrap();
+        assert!(migrate(&mut connection, ":memory:").is_err());
+    }
+
+    #[tokio::test]
+    async fn a_lock_is_only_taken_by_one_holder_until_it_expires() {
+        let store = SqliteStore::open(":memory:").unwrap();
+        let lock = |holder: &str, minutes: i64| Lock {
+            holder: holder.to_string(),
+            expires: Utc::now() + chrono::Duration::minutes(minutes),
+        };
+
+        assert!(store.try_lock("run", &lock("one", 5)).await.unwrap());
+        assert!(!store.try_lock("run", &lock("two", 5)).await.unwrap());
+        assert!(store.try_lock("run", &lock("one", -1)).await.unwrap());
+        assert!(store.try_lock("run", &lock("two", 5)).await.unwrap());
+    }
+}
@@ -0,0 +1,291 @@
+use std::sync::OnceLock;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::alerts::{Alert, Category, Severity};
+use crate::config::{Tenant, setting};
+use crate::retry::throttled_count;
+use crate::stats::{
+    APPLICATIONS_SCANNED, CERTIFICATES_EVALUATED, CREDENTIALS_EVALUATED, GRAPH_CALLS,
+    NOTIFICATIONS_FAILED, NOTIFICATIONS_SENT, get,
+};
+
+// Namespaces documents are kept under.
+pub const NOTIFICATIONS: &str = "notifications";
+pub const SCANS: &str = "scans";
+pub const ACKNOWLEDGMENTS: &str = "acknowledgments";
+pub const DELTA: &str = "delta";
+pub const AUDIT: &str = "audit";
+pub const ACTIONS: &str = "actions";
+pub const CHECKPOINTS: &str = "checkpoints";
+pub const OWNERS: &str = "owners";
+pub const FINDINGS: &str = "findings";
+pub const LOCKS: &str = "locks";
+pub const HELD: &str = "held";
+
+// Every namespace worth keeping when the state moves elsewhere; locks only matter to the
+// instances running now.
+pub const PORTABLE: [&str; 10] = [
+    NOTIFICATIONS,
+    SCANS,
+    ACKNOWLEDGMENTS,
+    DELTA,
+    AUDIT,
+    ACTIONS,
+    CHECKPOINTS,
+    OWNERS,
+    FINDINGS,
+    HELD,
+];
+
+// Where the tool keeps what it needs to remember between runs: notification records, scan
+// history, delta tokens and acknowledgments. Everything is stored as JSON documents
+// identified by a namespace and a key, so a backend only needs somewhere to keep those.
+#[async_trait]
+pub trait StateStore: Send + Sync {
+    async fn get(&self, namespace: &str, key: &str) -> anyhow::Result<Option<serde_json::Value>>;
+
+    async fn put(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: &serde_json::Value,
+    ) -> anyhow::Result<()>;
+
+    async fn delete(&self, namespace: &str, key: &str) -> anyhow::Result<()>;
+
+    // Every document in a namespace, ordered by key.
+    async fn list(&self, namespace: &str) -> anyhow::Result<Vec<(String, serde_json::Value)>>;
+
+    // Pick up what other instances wrote since the store was opened. Called at the start of
+    // each daemon run; only backends that keep a copy of the state in memory need it.
+    async fn reload(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    // Take the lock `name` as described by `lock`, unless another holder has it and it hasn't
+    // expired. Returns whether it was taken. Backends that can be shared between instances
+    // do this atomically; this default is only safe within one process.
+    async fn try_lock(&self, name: &str, lock: &Lock) -> anyhow::Result<bool> {
+        let current = self.get(LOCKS, name).await?;
+        if let Some(current) = current.and_then(|c| serde_json::from_value::<Lock>(c).ok())
+            && !current.available_to(&lock.holder, Utc::now())
+        {
+            return Ok(false);
+        }
+        self.put(LOCKS, name, &serde_json::to_value(lock)?).await?;
+        Ok(true)
+    }
+
+    // Release the lock `name` if `holder` still has it.
+    async fn unlock(&self, name: &str, holder: &str) -> anyhow::Result<()> {
+        let current = self.get(LOCKS, name).await?;
+        if current
+            .and_then(|c| serde_json::from_value::<Lock>(c).ok())
+            .is_some_and(|current| current.holder == holder)
+        {
+            self.delete(LOCKS, name).await?;
+        }
+        Ok(())
+    }
+}
+
+// A lock held in the state store (see locking.rs). It expires on its own, so one whose holder
+// crashed doesn't stay taken.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Lock {
+    pub holder: String,
+    pub expires: DateTime<Utc>,
+}
+
+impl Lock {
+    pub fn available_to(&self, holder: &str, now: DateTime<Utc>) -> bool {
+        self.holder == holder || self.expires <= now
+    }
+}
+
+static STORE: OnceLock<Box<dyn StateStore>> = OnceLock::new();
+
+// Open the state store configured with STATE_BACKEND and STATE_PATH. The store is only
+// used when one of them is set; otherwise the tool is stateless.
+//   sqlite      a SQLite database file (the default; STATE_PATH defaults to secret-manager.db)
+//   json        a JSON file (STATE_PATH defaults to secret-manager-state.json), for small
+//               tenants only; see json_store.rs
+//   azure-table an Azure Storage table, STATE_PATH being its URL
+//   azure-blob  an Azure Storage blob, STATE_PATH being its URL
+//   redis       Redis, STATE_PATH being its redis:// or rediss:// URL
+//   postgres    PostgreSQL, STATE_PATH being its postgres:// URL
+// The Azure backends authenticate as `tenant`'s app registration.
+pub async fn open(tenant: &Tenant) -> anyhow::Result<()> {
+    let backend = setting("STATE_BACKEND").map(|b| b.trim().to_lowercase());
+    let path = setting("STATE_PATH");
+    if backend.is_none() && path.is_none() {
+        return Ok(());
+    }
+
+    let backend = backend.as_deref().unwrap_or("sqlite");
+    let url = || {
+        path.as_deref()
+            .ok_or_else(|| anyhow::anyhow!("STATE_PATH must be set to the {} URL", backend))
+    };
+    let store: Box<dyn StateStore> = match backend {
+        "sqlite" => Box::new(crate::sqlite_store::SqliteStore::open(
+            path.as_deref().unwrap_or("secret-manager.db"),
+        )?),
+        "json" => Box::new(crate::json_store::JsonStore::open(
+            path.as_deref().unwrap_or("secret-manager-state.json"),
+        )?),
+        "azure-table" => Box::new(crate::azure_store::AzureTableStore::open(url()?, tenant)),
+        "azure-blob" => Box::new(crate::azure_store::AzureBlobStore::open(url()?, tenant).await?),
+        "redis" => Box::new(crate::redis_store::RedisStore::open(url()?).await?),
+        "postgres" => Box::new(crate::postgres_store::PostgresStore::open(url()?).await?),
+        other => anyhow::bail!(
+            "Unknown STATE_BACKEND '{}' (expected sqlite, json, azure-table, azure-blob, redis or postgres)",
+            other
+        ),
+    };
+
+    info!("Using the {} state store", backend);
+    let _ = STORE.set(store);
+    Ok(())
+}
+
+// The configured state store, if any.
+pub fn store() -> Option<&'static dyn StateStore> {
+    STORE.get().map(|s| s.as_ref())
+}
+
+// Read a document as `T`. A document that no longer parses is treated as missing.
+pub async fn load<T: DeserializeOwned>(
+    store: &dyn StateStore,
+    namespace: &str,
+    key: &str,
+) -> anyhow::Result<Option<T>> {
+    Ok(store
+        .get(namespace, key)
+        .await?
+        .and_then(|value| match serde_json::from_value(value) {
+            Ok(document) => Some(document),
+            Err(e) => {
+                warn!("Ignoring unreadable {}/{}: {}", namespace, key, e);
+                None
+            }
+        }))
+}
+
+pub async fn save<T: Serialize>(
+    store: &dyn StateStore,
+    namespace: &str,
+    key: &str,
+    document: &T,
+) -> anyhow::Result<()> {
+    store
+        .put(namespace, key, &serde_json::to_value(document)?)
+        .await
+}
+
+// Every readable document in a namespace, as `T`.
+pub async fn load_all<T: DeserializeOwned>(
+    store: &dyn StateStore,
+    namespace: &str,
+) -> anyhow::Result<Vec<(String, T)>> {
+    Ok(store
+        .list(namespace)
+        .await?
+        .into_iter()
+        .filter_map(|(key, value)| Some((key, serde_json::from_value(value).ok()?)))
+        .collect())
+}
+
+// What one run found, kept as scan history.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ScanRecord {
+    pub at: DateTime<Utc>,
+    pub tenants: Vec<String>,
+    pub applications_scanned: usize,
+    pub credentials_evaluated: usize,
+    // Of credentials_evaluated; the rest are client secrets.
+    #[serde(default)]
+    pub certificates_evaluated: usize,
+    pub applications_flagged: usize,
+    pub expired: usize,
+    pub expiring: usize,
+    pub critical: usize,
+    pub high: usize,
+    pub medium: usize,
+    pub low: usize,
+    // Findings for certificates (app, SAML and Key Vault) and for everything else.
+    #[serde(default)]
+    pub certificate_findings: usize,
+    #[serde(default)]
+    pub secret_findings: usize,
+    pub notifications_sent: usize,
+    // Run metrics, for spotting slow runs and Graph throttling over time. Runs recorded
+    // before they were added read as zero.
+    #[serde(default)]
+    pub notifications_failed: usize,
+    #[serde(default)]
+    pub duration_seconds: f64,
+    #[serde(default)]
+    pub graph_calls: usize,
+    // Graph responses retried after a 429, 503 or 504.
+    #[serde(default)]
+    pub throttled: usize,
+}
+
+impl ScanRecord {
+    pub fn from_run(alerts: &[Alert], tenants: &[String], at: DateTime<Utc>) -> ScanRecord {
+        let findings = || alerts.iter().flat_map(|a| &a.findings);
+        let severity = |s: Severity| findings().filter(|f| f.severity == s).count();
+        let certificates = findings()
+            .filter(|f| f.credential.to_lowercase().contains("certificate"))
+            .count();
+        ScanRecord {
+            at,
+            tenants: tenants.to_vec(),
+            applications_scanned: get(&APPLICATIONS_SCANNED),
+            credentials_evaluated: get(&CREDENTIALS_EVALUATED),
+            certificates_evaluated: get(&CERTIFICATES_EVALUATED),
+            applications_flagged: alerts.len(),
+            expired: findings()
+                .filter(|f| f.category == Category::Expired)
+                .count(),
+            expiring: findings()
+                .filter(|f| f.category == Category::ExpiringSoon)
+                .count(),
+            critical: severity(Severity::Critical),
+            high: severity(Severity::High),
+            medium: severity(Severity::Medium),
+            low: severity(Severity::Low),
+            certificate_findings: certificates,
+            secret_findings: findings().count() - certificates,
+            // Filled in by finish once the notifications have gone out.
+            notifications_sent: 0,
+            notifications_failed: 0,
+            duration_seconds: 0.0,
+            graph_calls: 0,
+            throttled: 0,
+        }
+    }
+
+    // Fill in the counters of the rest of the run, at its end.
+    pub fn finish(&mut self) {
+        self.notifications_sent = get(&NOTIFICATIONS_SENT);
+        self.notifications_failed = get(&NOTIFICATIONS_FAILED);
+        self.duration_seconds = (Utc::now() - self.at).num_milliseconds() as f64 / 1000.0;
+        self.graph_calls = get(&GRAPH_CALLS);
+        self.throttled = throttled_count();
+    }
+}
+
+// Add the run to the scan history, keyed by its start time so the history lists in order.
+pub async fn record_scan(record: &ScanRecord) -> anyhow::Result<()> {
+    let Some(store) = store() else {
+        return Ok(());
+    };
+    save(store, SCANS, &record.at.to_rfc3339(), record).await
+}
@@ -0,0 +1,97 @@
+use std::io::Write;
+
+use log::info;
+
+use crate::cli::{ExportArgs, ImportArgs};
+use crate::config::dry_run;
+use crate::dedup::NotificationState;
+use crate::json_store::StateFile;
+use crate::state::{self, NOTIFICATIONS, PORTABLE};
+
+// Moving between backends, e.g. from the json file to SQLite or PostgreSQL: export with the
+// old STATE_BACKEND/STATE_PATH, import with the new ones. The portable format is the json
+// backend's file, unencrypted, so a json state file can be imported as it is.
+
+fn store() -> anyhow::Result<&'static dyn state::StateStore> {
+    state::store().ok_or_else(|| {
+        anyhow::anyhow!("There is no state store configured; set STATE_BACKEND or STATE_PATH")
+    })
+}
+
+// `state export`
+pub async fn export(args: &ExportArgs) -> anyhow::Result<()> {
+    let store = store()?;
+    let mut file = StateFile::default();
+    let mut total = 0;
+    for namespace in PORTABLE {
+        for (key, value) in store.list(namespace).await? {
+            file.insert(namespace, &key, &value);
+            total += 1;
+        }
+    }
+
+    let json = file.to_plain_json()?;
+    match &args.out {
+        Some(path) => {
+            std::fs::write(path, json)
+                .map_err(|e| anyhow::anyhow!("Failed to write '{}': {}", path, e))?;
+            info!("Exported {} documents to {}", total, path);
+        }
+        None => {
+            std::io::stdout().write_all(json.as_bytes())?;
+            println!();
+        }
+    }
+    Ok(())
+}
+
+// Read a state file, or a --notify-state-file from before there was a state store.
+fn read(path: &str) -> anyhow::Result<StateFile> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read '{}': {}", path, e))?;
+    let state_file_error = match StateFile::parse(text.clone(), path) {
+        Ok(file) => return Ok(file),
+        Err(e) => e,
+    };
+    let Ok(notified) = serde_json::from_str::<NotificationState>(&text) else {
+        return Err(state_file_error);
+    };
+    let mut file = StateFile::default();
+    for (key, record) in &notified.notified {
+        file.insert(NOTIFICATIONS, key, &serde_json::to_value(record)?);
+    }
+    Ok(file)
+}
+
+// `state import`
+pub async fn import(args: &ImportArgs) -> anyhow::Result<()> {
+    let store = store()?;
+    let file = read(&args.file)?;
+    let verb = if dry_run() {
+        "Would import"
+    } else {
+        "Imported"
+    };
+
+    for namespace in file.namespaces() {
+        if !PORTABLE.contains(&namespace.as_str()) {
+            info!("Skipping unknown namespace '{}'", namespace);
+            continue;
+        }
+        let documents = file.list(&namespace);
+        if dry_run() {
+            println!("{} {} {} documents", verb, documents.len(), namespace);
+            continue;
+        }
+        if args.replace {
+            for (key, _) in store.list(&namespace).await? {
+                store.delete(&namespace, &key).await?;
+            }
+        }
+        for (key, value) in &documents {
+            store.put(&namespace, key, value).await?;
+        }
+        println!("{} {} {} documents", verb, documents.len(), namespace);
+    }
+    Ok(())
+}
@@ -0,0 +1,79 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use log::info;
+
+use crate::alerts::{Alert, Severity};
+use crate::retry::{reset_throttled_count, throttled_count};
+
+// Counters for the end-of-run summary, updated from wherever the work happens.
+pub static APPLICATIONS_SCANNED: AtomicUsize = AtomicUsize::new(0);
+pub static CREDENTIALS_EVALUATED: AtomicUsize = AtomicUsize::new(0);
+// The certificates among CREDENTIALS_EVALUATED; the rest are client secrets.
+pub static CERTIFICATES_EVALUATED: AtomicUsize = AtomicUsize::new(0);
+pub static NOTIFICATIONS_SENT: AtomicUsize = AtomicUsize::new(0);
+pub static NOTIFICATIONS_FAILED: AtomicUsize = AtomicUsize::new(0);
+pub static GRAPH_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+// Zero the counters at the start of a run, so each run of the daemon reports its own numbers.
+pub fn reset() {
+    for counter in [
+        &APPLICATIONS_SCANNED,
+        &CREDENTIALS_EVALUATED,
+        &CERTIFICATES_EVALUATED,
+        &NOTIFICATIONS_SENT,
+        &NOTIFICATIONS_FAILED,
+        &GRAPH_CALLS,
+    ] {
+        counter.store(0, Ordering::Relaxed);
+    }
+    reset_throttled_count();
+}
+
+pub fn count(counter: &AtomicUsize, n: usize) {
+    counter.fetch_add(n, Ordering::Relaxed);
+}
+
+pub fn get(counter: &AtomicUsize) -> usize {
+    counter.load(Ordering::Relaxed)
+}
+
+// Findings per severity, most urgent first.
+pub fn severity_counts(alerts: &[Alert]) -> Vec<(Severity, usize)> {
+    Severity::ALL
+        .iter()
+        .map(|severity| {
+            let n = alerts
+                .iter()
+                .flat_map(|a| &a.findings)
+                .filter(|f| f.severity == *severity)
+                .count();
+            (*severity, n)
+        })
+        .collect()
+}
+
+// Log a summary block at the end of the run so scheduled-run logs are easy to skim.
+pub fn log_summary(severities: &[(Severity, usize)]) {
+    info!("==== Run summary ====");
+    info!("Objects scanned:       {}", get(&APPLICATIONS_SCANNED));
+    info!("Credentials evaluated: {}", get(&CREDENTIALS_EVALUATED));
+    info!(
+        "Findings:              {} ({})",
+        severities.iter().map(|(_, n)| n).sum::<usize>(),
+        severities
+            .iter()
+            .map(|(s, n)| format!("{} {}", n, s.label().to_lowercase()))
+            .collect::<Vec<String>>()
+            .join(", ")
+    );
+    info!(
+        "Notifications:         {} sent, {} failed",
+        get(&NOTIFICATIONS_SENT),
+        get(&NOTIFICATIONS_FAILED)
+    );
+    info!(
+        "Graph calls:           {} ({} throttled)",
+        get(&GRAPH_CALLS),
+        throttled_count()
+    );
+}
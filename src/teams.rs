@@ -0,0 +1,291 @@
+use graph_rs_sdk::*;
+use log::info;
+
+use crate::alerts::{Alert, Category, Severity, Thresholds, format_expiry};
+use crate::audit::{alert_findings, body_id, record_notification};
+use crate::config::{dry_run, setting};
+use crate::notifiers::post_json;
+use crate::report::html_escape;
+use crate::retry::send_with_retry;
+use crate::stats::{NOTIFICATIONS_FAILED, NOTIFICATIONS_SENT, count};
+use crate::templates::portal_url;
+
+// Most applications listed in one channel message; Teams rejects messages over ~28 KB.
+const MAX_APPLICATIONS: usize = 50;
+// Adaptive Cards are much more verbose per application, so they list fewer.
+const MAX_CARD_APPLICATIONS: usize = 20;
+
+// Posting to a channel is enabled by TEAMS_TEAM_ID and TEAMS_CHANNEL_ID.
+// App-only posting needs the ChannelMessage.Send.Group resource-specific permission,
+// consented for the team, rather than a tenant-wide Graph permission.
+pub fn channel_enabled() -> bool {
+    setting("TEAMS_TEAM_ID").is_some() && setting("TEAMS_CHANNEL_ID").is_some()
+}
+
+// Posting to an incoming webhook (or a Workflows "post to a channel when a webhook request
+// is received" flow) is enabled by TEAMS_WEBHOOK_URL. Unlike the Graph channel message this
+// needs no extra permissions.
+pub fn webhook_enabled() -> bool {
+    setting("TEAMS_WEBHOOK_URL").is_some()
+}
+
+// Alerts sorted most urgent first, then by name.
+fn by_urgency(alerts: &[Alert]) -> Vec<&Alert> {
+    let mut sorted: Vec<&Alert> = alerts.iter().collect();
+    sorted.sort_by(|a, b| b.severity().cmp(&a.severity()).then(a.name.cmp(&b.name)));
+    sorted
+}
+
+// Render alerts as the HTML body of a channel message, most urgent applications first.
+fn render_channel_message(alerts: &[Alert], thresholds: &Thresholds) -> String {
+    let now = chrono::Utc::now();
+    let sorted = by_urgency(alerts);
+
+    let expired = alerts
+        .iter()
+        .filter(|a| a.has_category(Category::Expired))
+        .count();
+    let mut content = format!(
+        "<h3>{} applications have credentials that need attention</h3><p>{} with expired credentials, {} expiring within {} days.</p><ul>",
+        alerts.len(),
+        expired,
+        alerts.len() - expired,
+        thresholds.max_days()
+    );
+
+    for alert in sorted.iter().take(MAX_APPLICATIONS) {
+        let name = match &alert.app_id {
+            Some(app_id) => format!(
+                "<a href=\"{}\">{}</a>",
+                html_escape(&portal_url(app_id)),
+                html_escape(&alert.name)
+            ),
+            None => html_escape(&alert.name),
+        };
+        let findings = alert
+            .findings
+            .iter()
+            .map(|f| {
+                format!(
+                    "{} {}",
+                    html_escape(&f.credential),
+                    html_escape(&format_expiry(f.end_date_time, now))
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("; ");
+        content.push_str(&format!(
+            "<li><b>[{}] {}</b> ({}), owners: {}<br>{}</li>",
+            alert.severity().label(),
+            name,
+            html_escape(&alert.tenant),
+            if alert.owners.is_empty() {
+                "none".to_string()
+            } else {
+                html_escape(&alert.owners.join(", "))
+            },
+            findings
+        ));
+    }
+    content.push_str("</ul>");
+
+    if sorted.len() > MAX_APPLICATIONS {
+        content.push_str(&format!(
+            "<p>... and {} more applications.</p>",
+            sorted.len() - MAX_APPLICATIONS
+        ));
+    }
+
+    content
+}
+
+// Post the findings to the Teams channel in TEAMS_TEAM_ID / TEAMS_CHANNEL_ID as a
+// chatMessage. With --dry-run the message is printed instead.
+pub async fn post_channel_message(
+    client: &GraphClient,
+    alerts: &[Alert],
+    thresholds: &Thresholds,
+) -> anyhow::Result<()> {
+    let team_id =
+        setting("TEAMS_TEAM_ID").ok_or_else(|| anyhow::anyhow!("TEAMS_TEAM_ID is not set"))?;
+    let channel_id = setting("TEAMS_CHANNEL_ID")
+        .ok_or_else(|| anyhow::anyhow!("TEAMS_CHANNEL_ID is not set"))?;
+
+    let content = render_channel_message(alerts, thresholds);
+
+    if dry_run() {
+        println!("[dry-run] Would post to Teams channel {}", channel_id);
+        println!("{}", content);
+        println!();
+        return Ok(());
+    }
+
+    let body = serde_json::json!({
+        "importance": if alerts.iter().any(|a| a.has_category(Category::Expired)) {
+            "high"
+        } else {
+            "normal"
+        },
+        "body": {
+            "contentType": "html",
+            "content": content
+        }
+    });
+
+    let response = match send_with_retry(|| {
+        client
+            .team(&team_id)
+            .channel(&channel_id)
+            .messages()
+            .create_messages(&body)
+            .send()
+    })
+    .await
+    {
+        Ok(response) if response.status().is_success() => response,
+        Ok(response) => {
+            count(&NOTIFICATIONS_FAILED, 1);
+            anyhow::bail!(
+                "Posting to Teams channel {} failed with status {}",
+                channel_id,
+                response.status()
+            );
+        }
+        Err(e) => {
+            count(&NOTIFICATIONS_FAILED, 1);
+            return Err(e);
+        }
+    };
+
+    count(&NOTIFICATIONS_SENT, 1);
+    info!(
+        "Teams message posted with response: {:?}",
+        response.status()
+    );
+    let message_id = response.text().await.ok().and_then(|body| body_id(&body));
+    record_notification(
+        "Teams channel",
+        &[format!("{}/{}", team_id, channel_id)],
+        &alert_findings(alerts),
+        message_id,
+    )
+    .await;
+
+    Ok(())
+}
+
+// Adaptive Card colour for a severity.
+fn card_color(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Critical => "attention",
+        Severity::High => "warning",
+        Severity::Medium => "accent",
+        Severity::Low => "default",
+    }
+}
+
+// Render alerts as an Adaptive Card: one container per application with its severity,
+// expiry countdown per credential, owners and a button opening it in the portal.
+fn render_adaptive_card(alerts: &[Alert], thresholds: &Thresholds) -> serde_json::Value {
+    let now = chrono::Utc::now();
+    let sorted = by_urgency(alerts);
+
+    let mut body = vec![serde_json::json!({
+        "type": "TextBlock",
+        "size": "Large",
+        "weight": "Bolder",
+        "wrap": true,
+        "text": format!(
+            "{} applications have credentials expired or expiring within {} days",
+            alerts.len(),
+            thresholds.max_days()
+        )
+    })];
+
+    for alert in sorted.iter().take(MAX_CARD_APPLICATIONS) {
+        let mut items = vec![
+            serde_json::json!({
+                "type": "TextBlock",
+                "weight": "Bolder",
+                "wrap": true,
+                "color": card_color(alert.severity()),
+                "text": format!("[{}] {}", alert.severity().label(), alert.name)
+            }),
+            serde_json::json!({
+                "type": "FactSet",
+                "facts": alert
+                    .findings
+                    .iter()
+                    .map(|f| serde_json::json!({
+                        "title": f.credential,
+                        "value": format_expiry(f.end_date_time, now)
+                    }))
+                    .chain([
+                        serde_json::json!({ "title": "Tenant", "value": alert.tenant }),
+                        serde_json::json!({
+                            "title": "Owners",
+                            "value": if alert.owners.is_empty() {
+                                "None".to_string()
+                            } else {
+                                alert.owners.join(", ")
+                            }
+                        }),
+                    ])
+                    .collect::<Vec<serde_json::Value>>()
+            }),
+        ];
+        if let Some(app_id) = &alert.app_id {
+            items.push(serde_json::json!({
+                "type": "ActionSet",
+                "actions": [{
+                    "type": "Action.OpenUrl",
+                    "title": "Open in portal",
+                    "url": portal_url(app_id)
+                }]
+            }));
+        }
+
+        body.push(serde_json::json!({
+            "type": "Container",
+            "separator": true,
+            "items": items
+        }));
+    }
+
+    if sorted.len() > MAX_CARD_APPLICATIONS {
+        body.push(serde_json::json!({
+            "type": "TextBlock",
+            "isSubtle": true,
+            "wrap": true,
+            "text": format!("... and {} more applications.", sorted.len() - MAX_CARD_APPLICATIONS)
+        }));
+    }
+
+    serde_json::json!({
+        "type": "message",
+        "attachments": [{
+            "contentType": "application/vnd.microsoft.card.adaptive",
+            "contentUrl": null,
+            "content": {
+                "$schema": "http://adaptivecards.io/schemas/adaptive-card.json",
+                "type": "AdaptiveCard",
+                "version": "1.4",
+                "msteams": { "width": "Full" },
+                "body": body
+            }
+        }]
+    })
+}
+
+// Post the findings as an Adaptive Card to TEAMS_WEBHOOK_URL.
+pub async fn post_webhook_card(alerts: &[Alert], thresholds: &Thresholds) -> anyhow::Result<()> {
+    let url = setting("TEAMS_WEBHOOK_URL")
+        .ok_or_else(|| anyhow::anyhow!("TEAMS_WEBHOOK_URL is not set"))?;
+    post_json(
+        "Teams webhook",
+        &url,
+        &render_adaptive_card(alerts, thresholds),
+        &alert_findings(alerts),
+    )
+    .await
+}
@@ -0,0 +1,309 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::alerts::{Alert, Category, Finding, Thresholds, format_timestamp};
+use crate::callback;
+use crate::config::setting;
+
+// Built-in HTML body for alert emails.
+const DEFAULT_ALERT_TEMPLATE: &str = include_str!("../templates/alert.html");
+
+#[derive(Serialize)]
+struct FindingView {
+    credential: String,
+    key_id: Option<String>,
+    hint: Option<String>,
+    expiry: String,
+    days_remaining: i64,
+    severity: &'static str,
+    summary: String,
+    description: String,
+    // Acknowledge and snooze links, when CALLBACK_URL is set.
+    ack_url: Option<String>,
+    snooze_url: Option<String>,
+}
+
+// One finding with its application's details, for templates that want a flat list.
+#[derive(Serialize)]
+struct FlatFindingView {
+    application: String,
+    app_id: Option<String>,
+    tenant: String,
+    owners: Vec<String>,
+    #[serde(flatten)]
+    finding: FindingView,
+}
+
+#[derive(Serialize)]
+struct AlertView {
+    tenant: String,
+    name: String,
+    app_id: Option<String>,
+    portal_url: Option<String>,
+    owners: Vec<String>,
+    severity: &'static str,
+    findings: Vec<FindingView>,
+}
+
+#[derive(Serialize)]
+struct SectionView {
+    heading: &'static str,
+    intro: String,
+    alerts: Vec<AlertView>,
+}
+
+// Link to the application's credentials blade in the Entra admin center.
+// PORTAL_URL overrides the portal host, e.g. for national clouds.
+pub fn portal_url(app_id: &str) -> String {
+    format!(
+        "{}/#view/Microsoft_AAD_RegisteredApps/ApplicationMenuBlade/~/Credentials/appId/{}",
+        setting("PORTAL_URL")
+            .unwrap_or_else(|| "https://entra.microsoft.com".to_string())
+            .trim_end_matches('/'),
+        app_id
+    )
+}
+
+fn finding_view(alert: &Alert, f: &Finding, now: DateTime<Utc>) -> FindingView {
+    let (ack_url, snooze_url) = callback::links(alert, f).unzip();
+    FindingView {
+        credential: f.credential.clone(),
+        key_id: f.key_id.clone(),
+        hint: f.hint.clone(),
+        expiry: format_timestamp(f.end_date_time),
+        days_remaining: f.days_remaining(now),
+        severity: f.severity.label(),
+        summary: f.summary(),
+        description: f.description.clone(),
+        ack_url,
+        snooze_url,
+    }
+}
+
+fn alert_view(alert: &Alert, category: Category) -> AlertView {
+    let now = Utc::now();
+    AlertView {
+        tenant: alert.tenant.clone(),
+        name: alert.name.clone(),
+        app_id: alert.app_id.clone(),
+        portal_url: alert.app_id.as_deref().map(portal_url),
+        owners: alert.owners.clone(),
+        severity: alert.severity().label(),
+        findings: alert
+            .findings_in(category)
+            .map(|f| finding_view(alert, f, now))
+            .collect(),
+    }
+}
+
+// Template context for a set of alerts:
+// - `intro`: the lead paragraph for this kind of mail
+// - `sections`: one entry per category with findings, expired first, each with `heading`,
+//   `intro` and `alerts` (name, app_id, portal_url, tenant, owners, severity, findings)
+// - `findings`: every finding flattened with its application, app_id, tenant and owners,
+//   plus credential, key_id, hint, expiry, days_remaining, severity, description and, with
+//   CALLBACK_URL set, ack_url and snooze_url
+// - `links`: whether findings have acknowledge and snooze links
+// - `recipients`: who the mail is addressed to
+// - `max_days`: the outermost threshold tier
+pub fn alerts_context(
+    intro: &str,
+    recipients: &[String],
+    alerts: &[&Alert],
+    thresholds: &Thresholds,
+) -> tera::Context {
+    let sections: Vec<SectionView> = [
+        (
+            Category::Expired,
+            "These credentials have already expired and should be removed or replaced.".to_string(),
+        ),
+        (
+            Category::ExpiringSoon,
+            format!(
+                "These credentials expire within the next {} days.",
+                thresholds.max_days()
+            ),
+        ),
+    ]
+    .into_iter()
+    .map(|(category, section_intro)| SectionView {
+        heading: category.heading(),
+        intro: section_intro,
+        alerts: alerts
+            .iter()
+            .filter(|a| a.has_category(category))
+            .map(|a| alert_view(a, category))
+            .collect(),
+    })
+    .filter(|s| !s.alerts.is_empty())
+    .collect();
+
+    let now = Utc::now();
+    let findings: Vec<FlatFindingView> = alerts
+        .iter()
+        .flat_map(|alert| {
+            alert.findings.iter().map(move |f| FlatFindingView {
+                application: alert.name.clone(),
+                app_id: alert.app_id.clone(),
+                tenant: alert.tenant.clone(),
+                owners: alert.owners.clone(),
+                finding: finding_view(alert, f, now),
+            })
+        })
+        .collect();
+
+    let mut context = tera::Context::new();
+    context.insert("intro", intro);
+    context.insert("sections", &sections);
+    context.insert("findings", &findings);
+    context.insert("recipients", recipients);
+    context.insert("max_days", &thresholds.max_days());
+    context.insert("links", &callback::enabled());
+    context
+}
+
+// A user supplied template file named by `var`, if set.
+fn custom_template(var: &str) -> anyhow::Result<Option<String>> {
+    let Some(path) = setting(var) else {
+        return Ok(None);
+    };
+    std::fs::read_to_string(&path)
+        .map(Some)
+        .map_err(|e| anyhow::anyhow!("Failed to read {} '{}': {}", var, path, e))
+}
+
+// Render the user supplied template named by `var`. Tera keeps the cause (an unknown
+// variable, a syntax error) in the error's sources, so those are included.
+fn render_custom(
+    var: &str,
+    template: &str,
+    context: &tera::Context,
+    autoescape: bool,
+) -> anyhow::Result<String> {
+    tera::Tera::one_off(template, context, autoescape).map_err(|e| {
+        let mut message = e.to_string();
+        let mut source = std::error::Error::source(&e);
+        while let Some(cause) = source {
+            message.push_str(&format!(": {}", cause));
+            source = cause.source();
+        }
+        anyhow::anyhow!("Failed to render {}: {}", var, message)
+    })
+}
+
+// The subject from EMAIL_SUBJECT_TEMPLATE, or `default`. `default` is also available to
+// the template as `subject`, so it can be wrapped rather than replaced.
+pub fn render_subject(default: &str, context: &tera::Context) -> anyhow::Result<String> {
+    let Some(template) = custom_template("EMAIL_SUBJECT_TEMPLATE")? else {
+        return Ok(default.to_string());
+    };
+    let mut context = context.clone();
+    context.insert("subject", default);
+    let subject = render_custom("EMAIL_SUBJECT_TEMPLATE", &template, &context, false)?;
+    // Mail subjects are a single line.
+    Ok(subject
+        .lines()
+        .map(str::trim)
+        .collect::<Vec<&str>>()
+        .join(" "))
+}
+
+// The body from EMAIL_BODY_TEMPLATE, if set. HTML bodies are autoescaped.
+pub fn render_custom_body(context: &tera::Context, html: bool) -> anyhow::Result<Option<String>> {
+    let Some(template) = custom_template("EMAIL_BODY_TEMPLATE")? else {
+        return Ok(None);
+    };
+    render_custom("EMAIL_BODY_TEMPLATE", &template, context, html).map(Some)
+}
+
+// Render the built-in HTML alert email body.
+pub fn render_html_alerts(context: &tera::Context) -> anyhow::Result<String> {
+    tera::Tera::one_off(DEFAULT_ALERT_TEMPLATE, context, true)
+        .map_err(|e| anyhow::anyhow!("Failed to render email template: {}", e))
+}
+
+// Sample alerts with an expired and an expiring credential, to try templates against.
+fn sample_context() -> tera::Context {
+    let now = Utc::now();
+    let thresholds = Thresholds::new(Thresholds::DEFAULT.to_vec()).unwrap();
+    let finding = |days: i64, credential: &str| {
+        Finding::new(
+            now + chrono::Duration::days(days),
+            now,
+            &thresholds,
+            credential,
+            format!("{} of Sample Application", credential),
+        )
+        .with_key(
+            Some("00000000-0000-0000-0000-000000000000".to_string()),
+            Some("abc".to_string()),
+        )
+    };
+    let alert = Alert {
+        tenant: "default".to_string(),
+        name: "Sample Application".to_string(),
+        app_id: Some("00000000-0000-0000-0000-000000000001".to_string()),
+        owners: vec!["owner@example.com".to_string()],
+        escalate_to: Vec::new(),
+        findings: vec![finding(-3, "Client secret"), finding(10, "Certificate")],
+    };
+    let mut context = alerts_context(
+        "Sample introduction.",
+        &alert.owners,
+        &[&alert],
+        &thresholds,
+    );
+    context.insert("subject", "Alert: Expiring Credentials for Applications");
+    context
+}
+
+// Render `template` from `var` against sample alerts.
+fn check_template(var: &str, template: &str) -> anyhow::Result<()> {
+    render_custom(var, template, &sample_context(), true).map(|_| ())
+}
+
+// Problems with EMAIL_SUBJECT_TEMPLATE and EMAIL_BODY_TEMPLATE, for config::validate. Each
+// is rendered against sample alerts, so a broken template is reported at startup instead of
+// failing every alert mail.
+pub fn validate() -> Vec<String> {
+    ["EMAIL_SUBJECT_TEMPLATE", "EMAIL_BODY_TEMPLATE"]
+        .into_iter()
+        .filter_map(|var| {
+            custom_template(var)
+                .and_then(|template| match template {
+                    Some(template) => check_template(var, &template),
+                    None => Ok(()),
+                })
+                .err()
+                .map(|e| e.to_string())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates_using_the_documented_variables_render() {
+        let template = "{{ intro }} {{ subject }} {{ max_days }} {{ recipients | join(sep=\", \") }}\n\
+            {% for section in sections %}{{ section.heading }}{% for alert in section.alerts %}\
+            {{ alert.name }} {{ alert.portal_url }}{% endfor %}{% endfor %}\n\
+            {% for f in findings %}{{ f.application }} {{ f.expiry }} {{ f.days_remaining }}{% endfor %}";
+        assert!(check_template("EMAIL_BODY_TEMPLATE", template).is_ok());
+    }
+
+    #[test]
+    fn unknown_variables_are_reported() {
+        let error = check_template("EMAIL_BODY_TEMPLATE", "{{ application_name }}")
+            .unwrap_err()
+            .to_string();
+        assert!(error.starts_with("Failed to render EMAIL_BODY_TEMPLATE"));
+        assert!(error.contains("application_name"));
+    }
+
+    #[test]
+    fn syntax_errors_are_reported() {
+        assert!(check_template("EMAIL_SUBJECT_TEMPLATE", "{% for alert in %}").is_err());
+    }
+}
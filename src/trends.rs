@@ -0,0 +1,167 @@
+use crate::alerts::format_timestamp;
+use crate::cli::{OutputFormat, TrendsArgs};
+use crate::state::{self, SCANS, ScanRecord};
+
+// "+3", "-1" or "0": the change of a count since the earlier run.
+fn change(earlier: usize, later: usize) -> String {
+    let delta = later as i64 - earlier as i64;
+    if delta > 0 {
+        format!("+{}", delta)
+    } else {
+        delta.to_string()
+    }
+}
+
+// The last `last` runs of the scan history.
+async fn recent_runs(last: usize) -> anyhow::Result<Vec<ScanRecord>> {
+    let Some(store) = state::store() else {
+        anyhow::bail!("Run history needs a state store; set STATE_BACKEND or STATE_PATH");
+    };
+    let mut runs: Vec<ScanRecord> = state::load_all::<ScanRecord>(store, SCANS)
+        .await?
+        .into_iter()
+        .map(|(_, record)| record)
+        .collect();
+    Ok(runs.split_off(runs.len().saturating_sub(last)))
+}
+
+// Print the scan history kept in the state store: one row per run with the credentials
+// evaluated and found, split into certificates and client secrets, followed by how the
+// numbers moved between the first and the last run shown.
+// --output json prints the records instead.
+pub async fn print_trends(args: &TrendsArgs, output: OutputFormat) -> anyhow::Result<()> {
+    let runs = recent_runs(args.last).await?;
+    let runs = runs.as_slice();
+
+    if output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(runs)?);
+        return Ok(());
+    }
+
+    if runs.is_empty() {
+        println!("No runs recorded yet.");
+        return Ok(());
+    }
+
+    println!(
+        "{:<20} {:>11} {:>6} {:>7} {:>8} {:>7} {:>10} {:>9}",
+        "Run", "Credentials", "Certs", "Secrets", "Expiring", "Expired", "Cert finds", "Sec finds"
+    );
+    for run in runs {
+        println!(
+            "{:<20} {:>11} {:>6} {:>7} {:>8} {:>7} {:>10} {:>9}",
+            format_timestamp(run.at),
+            run.credentials_evaluated,
+            run.certificates_evaluated,
+            run.credentials_evaluated - run.certificates_evaluated,
+            run.expiring,
+            run.expired,
+            run.certificate_findings,
+            run.secret_findings
+        );
+    }
+
+    if let [first, .., last] = runs {
+        println!();
+        println!(
+            "Over {} runs since {}:",
+            runs.len(),
+            format_timestamp(first.at)
+        );
+        for (label, earlier, later) in [
+            (
+                "Credentials",
+                first.credentials_evaluated,
+                last.credentials_evaluated,
+            ),
+            ("Expiring", first.expiring, last.expiring),
+            ("Expired", first.expired, last.expired),
+            (
+                "Certificate findings",
+                first.certificate_findings,
+                last.certificate_findings,
+            ),
+            (
+                "Secret findings",
+                first.secret_findings,
+                last.secret_findings,
+            ),
+        ] {
+            println!(
+                "  {:<21} {} -> {} ({})",
+                label,
+                earlier,
+                later,
+                change(earlier, later)
+            );
+        }
+    }
+    Ok(())
+}
+
+// Print the metrics of each recorded run: duration, Graph calls and throttled responses,
+// notifications, and findings by severity, followed by the averages over the runs shown.
+// --output json prints the records instead.
+pub async fn print_metrics(args: &TrendsArgs, output: OutputFormat) -> anyhow::Result<()> {
+    let runs = recent_runs(args.last).await?;
+
+    if output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&runs)?);
+        return Ok(());
+    }
+
+    if runs.is_empty() {
+        println!("No runs recorded yet.");
+        return Ok(());
+    }
+
+    println!(
+        "{:<20} {:>9} {:>11} {:>9} {:>9} {:>8} {:>6} {:>6} {:>5}",
+        "Run",
+        "Duration",
+        "Graph calls",
+        "Throttled",
+        "Sent/Fail",
+        "Critical",
+        "High",
+        "Medium",
+        "Low"
+    );
+    for run in &runs {
+        println!(
+            "{:<20} {:>8.1}s {:>11} {:>9} {:>9} {:>8} {:>6} {:>6} {:>5}",
+            format_timestamp(run.at),
+            run.duration_seconds,
+            run.graph_calls,
+            run.throttled,
+            format!("{}/{}", run.notifications_sent, run.notifications_failed),
+            run.critical,
+            run.high,
+            run.medium,
+            run.low
+        );
+    }
+
+    let n = runs.len() as f64;
+    let throttled_runs = runs.iter().filter(|run| run.throttled > 0).count();
+    println!();
+    println!("Over {} runs:", runs.len());
+    println!(
+        "  Average duration     {:.1}s (longest {:.1}s)",
+        runs.iter().map(|run| run.duration_seconds).sum::<f64>() / n,
+        runs.iter()
+            .map(|run| run.duration_seconds)
+            .fold(0.0, f64::max)
+    );
+    println!(
+        "  Average Graph calls  {:.0}",
+        runs.iter().map(|run| run.graph_calls).sum::<usize>() as f64 / n
+    );
+    println!(
+        "  Throttled in         {} of {} runs ({} responses)",
+        throttled_runs,
+        runs.len(),
+        runs.iter().map(|run| run.throttled).sum::<usize>()
+    );
+    Ok(())
+}
@@ -0,0 +1,224 @@
+use chrono::Utc;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Cell, Paragraph, Row, Table, TableState, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::alerts::{Alert, format_timestamp};
+use crate::report::{FindingRecord, finding_records};
+
+// Interactive triage view over the findings of a scan.
+// Up/Down (or j/k) move the selection, `/` starts typing a filter on application, app ID,
+// credential and owners, Esc clears it, q quits.
+struct Dashboard {
+    records: Vec<FindingRecord>,
+    filter: String,
+    editing_filter: bool,
+    state: TableState,
+}
+
+impl Dashboard {
+    fn visible(&self) -> Vec<&FindingRecord> {
+        let filter = self.filter.to_lowercase();
+        self.records
+            .iter()
+            .filter(|r| {
+                filter.is_empty()
+                    || r.application.to_lowercase().contains(&filter)
+                    || r.appId
+                        .as_deref()
+                        .unwrap_or("")
+                        .to_lowercase()
+                        .contains(&filter)
+                    || r.credential.to_lowercase().contains(&filter)
+                    || r.owners.iter().any(|o| o.to_lowercase().contains(&filter))
+            })
+            .collect()
+    }
+
+    fn move_selection(&mut self, delta: i64) {
+        let len = self.visible().len() as i64;
+        if len == 0 {
+            self.state.select(None);
+            return;
+        }
+        let current = self.state.selected().unwrap_or(0) as i64;
+        self.state
+            .select(Some((current + delta).clamp(0, len - 1) as usize));
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [header, table_area, details_area] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(5),
+            Constraint::Length(8),
+        ])
+        .areas(frame.area());
+
+        let visible = self.visible();
+
+        let filter_line = if self.editing_filter {
+            format!("Filter: {}_", self.filter)
+        } else if self.filter.is_empty() {
+            "Press / to filter, j/k to move, q to quit".to_string()
+        } else {
+            format!("Filter: {} (Esc to clear)", self.filter)
+        };
+        frame.render_widget(
+            Paragraph::new(filter_line).block(Block::bordered().title(format!(
+                " Expiring credentials: {} of {} ",
+                visible.len(),
+                self.records.len()
+            ))),
+            header,
+        );
+
+        let rows = visible.iter().map(|r| {
+            let color = match r.daysRemaining {
+                ..0 => Color::Red,
+                0..=7 => Color::LightRed,
+                8..=30 => Color::Yellow,
+                _ => Color::Reset,
+            };
+            Row::new(vec![
+                Cell::from(r.tenant.clone()),
+                Cell::from(r.application.clone()),
+                Cell::from(r.credential.clone()),
+                Cell::from(format_timestamp(r.expiry)),
+                Cell::from(r.daysRemaining.to_string()),
+                Cell::from(if r.owners.is_empty() {
+                    "None".to_string()
+                } else {
+                    r.owners.join(", ")
+                }),
+            ])
+            .style(Style::new().fg(color))
+        });
+
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(12),
+                Constraint::Percentage(30),
+                Constraint::Length(26),
+                Constraint::Length(22),
+                Constraint::Length(6),
+                Constraint::Fill(1),
+            ],
+        )
+        .header(
+            Row::new(vec![
+                "Tenant",
+                "Application",
+                "Credential",
+                "Expiry",
+                "Days",
+                "Owners",
+            ])
+            .style(Style::new().add_modifier(Modifier::BOLD)),
+        )
+        .block(Block::bordered())
+        .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+
+        let details = match self.state.selected().and_then(|i| visible.get(i)) {
+            Some(r) => vec![
+                Line::from(format!(
+                    "{} — App ID: {}",
+                    r.application,
+                    r.appId.as_deref().unwrap_or("-")
+                )),
+                Line::from(format!(
+                    "{} expiring {} ({} days, severity {})",
+                    r.credential,
+                    format_timestamp(r.expiry),
+                    r.daysRemaining,
+                    r.severity
+                )),
+                Line::from(format!(
+                    "Owners: {}",
+                    if r.owners.is_empty() {
+                        "None".to_string()
+                    } else {
+                        r.owners.join(", ")
+                    }
+                )),
+                Line::from(r.description.clone()),
+            ],
+            None => vec![Line::from("No credential selected")],
+        };
+
+        frame.render_stateful_widget(table, table_area, &mut self.state);
+        frame.render_widget(
+            Paragraph::new(details)
+                .wrap(Wrap { trim: true })
+                .block(Block::bordered().title(" Details ")),
+            details_area,
+        );
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> anyhow::Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            if self.editing_filter {
+                match key.code {
+                    KeyCode::Enter => self.editing_filter = false,
+                    KeyCode::Esc => {
+                        self.editing_filter = false;
+                        self.filter.clear();
+                    }
+                    KeyCode::Backspace => {
+                        self.filter.pop();
+                    }
+                    KeyCode::Char(c) => self.filter.push(c),
+                    _ => {}
+                }
+                self.state.select(Some(0));
+                self.move_selection(0);
+                continue;
+            }
+
+            match key.code {
+                KeyCode::Char('q') => return Ok(()),
+                KeyCode::Char('/') => self.editing_filter = true,
+                KeyCode::Esc => {
+                    self.filter.clear();
+                    self.move_selection(0);
+                }
+                KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+                KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+                KeyCode::PageDown => self.move_selection(10),
+                KeyCode::PageUp => self.move_selection(-10),
+                KeyCode::Home => self.state.select(Some(0)),
+                _ => {}
+            }
+        }
+    }
+}
+
+// Show the findings in an interactive terminal dashboard, soonest expiry first.
+pub fn run_dashboard(alerts: &[Alert]) -> anyhow::Result<()> {
+    let mut records = finding_records(alerts, Utc::now());
+    records.sort_by_key(|r| r.expiry);
+
+    let mut dashboard = Dashboard {
+        records,
+        filter: String::new(),
+        editing_filter: false,
+        state: TableState::default().with_selected(Some(0)),
+    };
+
+    let mut terminal = ratatui::init();
+    let result = dashboard.run(&mut terminal);
+    ratatui::restore();
+    result
+}
@@ -0,0 +1,132 @@
+use chrono::Utc;
+use log::{error, info};
+
+use crate::alerts::{Alert, Finding, format_expiry};
+use crate::audit::{body_id, record_notification};
+use crate::config::{dry_run, http_client, list_setting, setting};
+use crate::dedup::finding_key;
+use crate::stats::{NOTIFICATIONS_FAILED, NOTIFICATIONS_SENT, count};
+
+// Credentials expiring within this many days (or already expired) are texted.
+const DEFAULT_SMS_DAYS: i64 = 3;
+// Keep texts to a few SMS segments; Twilio rejects bodies over 1600 characters.
+const MAX_SMS_CHARS: usize = 600;
+
+// SMS is only for imminent outages: expired credentials and those expiring within
+// TWILIO_DAYS (default 3). Enabled by TWILIO_ACCOUNT_SID with TWILIO_AUTH_TOKEN,
+// TWILIO_FROM (a Twilio number or messaging service SID) and TWILIO_TO, the on-call numbers
+// in E.164 format (comma separated).
+pub fn enabled() -> bool {
+    setting("TWILIO_ACCOUNT_SID").is_some()
+}
+
+fn sms_days() -> i64 {
+    setting("TWILIO_DAYS")
+        .and_then(|d| d.trim().parse().ok())
+        .unwrap_or(DEFAULT_SMS_DAYS)
+}
+
+// A short text listing each urgent credential, most urgent first.
+fn render_sms(alerts: &[Alert], days: i64) -> Option<String> {
+    let now = Utc::now();
+    let mut urgent: Vec<(&Alert, &Finding)> = alerts
+        .iter()
+        .flat_map(|a| a.findings.iter().map(move |f| (a, f)))
+        .filter(|(_, f)| f.days_remaining(now) < days)
+        .collect();
+    if urgent.is_empty() {
+        return None;
+    }
+    urgent.sort_by_key(|(_, f)| f.end_date_time);
+
+    let mut text = format!(
+        "secret-manager: {} credentials expired or expiring within {} days:",
+        urgent.len(),
+        days
+    );
+    for (i, (alert, finding)) in urgent.iter().enumerate() {
+        let line = format!(
+            "\n{} {} ({})",
+            alert.name,
+            format_expiry(finding.end_date_time, now),
+            alert.tenant
+        );
+        if text.len() + line.len() > MAX_SMS_CHARS {
+            text.push_str(&format!("\n+{} more", urgent.len() - i));
+            break;
+        }
+        text.push_str(&line);
+    }
+    Some(text)
+}
+
+// Text the on-call numbers in TWILIO_TO about expired and imminently expiring credentials.
+pub async fn send_sms(alerts: &[Alert]) -> anyhow::Result<()> {
+    let account_sid = setting("TWILIO_ACCOUNT_SID")
+        .ok_or_else(|| anyhow::anyhow!("TWILIO_ACCOUNT_SID is not set"))?;
+    let from = setting("TWILIO_FROM").ok_or_else(|| anyhow::anyhow!("TWILIO_FROM is not set"))?;
+    let recipients = list_setting("TWILIO_TO").unwrap_or_default();
+
+    let days = sms_days();
+    let Some(text) = render_sms(alerts, days) else {
+        return Ok(());
+    };
+    let now = Utc::now();
+    let findings: Vec<String> = alerts
+        .iter()
+        .flat_map(|a| {
+            a.findings
+                .iter()
+                .filter(|f| f.days_remaining(now) < days)
+                .map(move |f| finding_key(a, f))
+        })
+        .collect();
+
+    if dry_run() {
+        println!("[dry-run] Would text {}", recipients.join(", "));
+        println!("{}", text);
+        println!();
+        return Ok(());
+    }
+
+    let http = http_client();
+    let url = format!(
+        "https://api.twilio.com/2010-04-01/Accounts/{}/Messages.json",
+        account_sid
+    );
+    // A messaging service SID starts with MG; anything else is a phone number.
+    let from_field = if from.starts_with("MG") {
+        "MessagingServiceSid"
+    } else {
+        "From"
+    };
+
+    let mut failures = 0;
+    for to in &recipients {
+        let result = http
+            .post(&url)
+            .basic_auth(&account_sid, setting("TWILIO_AUTH_TOKEN"))
+            .form(&[("To", to.as_str()), (from_field, &from), ("Body", &text)])
+            .send()
+            .await
+            .and_then(|r| r.error_for_status());
+        match result {
+            Ok(response) => {
+                count(&NOTIFICATIONS_SENT, 1);
+                info!("Texted {}", to);
+                let message_id = response.text().await.ok().and_then(|body| body_id(&body));
+                record_notification("SMS", std::slice::from_ref(to), &findings, message_id).await;
+            }
+            Err(e) => {
+                count(&NOTIFICATIONS_FAILED, 1);
+                error!("Failed to text {}: {}", to, e.without_url());
+                failures += 1;
+            }
+        }
+    }
+
+    if failures > 0 {
+        anyhow::bail!("{} of {} texts failed", failures, recipients.len());
+    }
+    Ok(())
+}
@@ -0,0 +1,95 @@
+use chrono::Utc;
+use log::error;
+
+use crate::alerts::Alert;
+use crate::audit::record_finding;
+use crate::config::{list_setting, setting};
+use crate::notifiers::{alerts_at_or_above, post_json_with_headers};
+use crate::report::finding_records;
+
+// The generic webhook POSTs findings as JSON to WEBHOOK_URL, for systems this tool has no
+// dedicated notifier for. Findings use the same records as --output json.
+//
+//     WEBHOOK_URL           endpoint to POST to (enables the webhook)
+//     WEBHOOK_MODE          "batch" (default) sends one request with every finding,
+//                           "finding" one request per finding
+//     WEBHOOK_HEADERS       extra headers as "Name: value" entries, e.g.
+//                           webhook_headers = ["Authorization: Bearer ...", "X-Team: iam"]
+//     WEBHOOK_MIN_SEVERITY  leave out less urgent applications
+pub fn enabled() -> bool {
+    setting("WEBHOOK_URL").is_some()
+}
+
+// Check the webhook settings, returning a description of each problem.
+pub fn validate() -> Vec<String> {
+    [headers().err(), per_finding().err()]
+        .into_iter()
+        .flatten()
+        .map(|e| e.to_string())
+        .collect()
+}
+
+// Parse WEBHOOK_HEADERS into name/value pairs.
+fn headers() -> anyhow::Result<Vec<(String, String)>> {
+    list_setting("WEBHOOK_HEADERS")
+        .unwrap_or_default()
+        .iter()
+        .map(|entry| {
+            let (name, value) = entry.split_once(':').ok_or_else(|| {
+                anyhow::anyhow!("WEBHOOK_HEADERS entry '{}' is not 'Name: value'", entry)
+            })?;
+            Ok((name.trim().to_string(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+// Whether each finding is posted on its own, from WEBHOOK_MODE.
+fn per_finding() -> anyhow::Result<bool> {
+    match setting("WEBHOOK_MODE")
+        .map(|m| m.trim().to_lowercase())
+        .as_deref()
+    {
+        None | Some("batch") => Ok(false),
+        Some("finding") => Ok(true),
+        Some(other) => anyhow::bail!("WEBHOOK_MODE must be 'batch' or 'finding', got '{}'", other),
+    }
+}
+
+// POST the findings to WEBHOOK_URL, as one batch or one request per finding.
+pub async fn post_findings(alerts: &[Alert]) -> anyhow::Result<()> {
+    let url = setting("WEBHOOK_URL").ok_or_else(|| anyhow::anyhow!("WEBHOOK_URL is not set"))?;
+    let headers = headers()?;
+
+    let alerts = alerts_at_or_above(alerts, "WEBHOOK_MIN_SEVERITY");
+    let now = Utc::now();
+    let records = finding_records(alerts.iter().copied(), now);
+    if records.is_empty() {
+        return Ok(());
+    }
+
+    if !per_finding()? {
+        let payload = serde_json::json!({
+            "generatedAt": now,
+            "applications": alerts.len(),
+            "findings": records
+        });
+        let findings: Vec<String> = records.iter().map(record_finding).collect();
+        return post_json_with_headers("webhook", &url, &headers, &payload, &findings).await;
+    }
+
+    let mut failures = 0;
+    for record in &records {
+        let payload = serde_json::to_value(record)?;
+        let findings = [record_finding(record)];
+        if let Err(e) = post_json_with_headers("webhook", &url, &headers, &payload, &findings).await
+        {
+            error!("{}", e);
+            failures += 1;
+        }
+    }
+
+    if failures > 0 {
+        anyhow::bail!("{} of {} webhook requests failed", failures, records.len());
+    }
+    Ok(())
+}